@@ -0,0 +1,177 @@
+#![allow(clippy::needless_return)]
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use regex::Regex;
+use platypus_lang::module::Module;
+use platypus_lang::value::{BoundMethod, Closure, EnumDefinition, EnumValue, Function, NativeFunction};
+use platypus_lang::{Backend, Engine, Value};
+
+// Arithmetic-heavy programs, timed on both backends. There's no loop statement, so the loops
+// are recursion. Run with `cargo bench`.
+const PROGRAMS: [(&str, &str); 3] = [
+    ("fib", "
+        fn fib(n) {
+            return match n { 0 | 1 => n, _ => fib(n - 1) + fib(n - 2) };
+        }
+        fib(22);
+    "),
+    ("sum of squares", "
+        fn squares(n) {
+            return match n { 0 => 0, _ => squares(n - 1) + n * n / 2 - n };
+        }
+        fn repeat(k) {
+            return match k { 0 => 0, _ => repeat(k - 1) + squares(300) };
+        }
+        repeat(300);
+    "),
+    ("float mix", "
+        fn step(x, n) {
+            return match n { 0 => x, _ => step((x * 1.0001 + 0.5) / 1.00005 - 0.25, n - 1) };
+        }
+        fn repeat(k) {
+            return match k { 0 => 0, _ => repeat(k - 1) + step(1.5, 400) };
+        }
+        repeat(200);
+    "),
+];
+
+const RUNS: u32 = 10;
+
+// The best of several runs, so noise from the rest of the machine mostly drops out.
+fn best_of(mut run: impl FnMut()) -> Duration {
+    let mut best = Duration::MAX;
+
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        run();
+        best = best.min(start.elapsed());
+    }
+
+    return best;
+}
+
+fn time(source: &str, backend: Backend) -> Duration {
+    return best_of(|| {
+        let mut engine = Engine::new();
+        engine.set_backend(backend);
+        engine.eval(source).unwrap();
+    });
+}
+
+// The 32-byte layout values had before they were shrunk to 16: the same variants, except that
+// strings, and the variant name of an enum constructor, are held inline and copied along with
+// the value.
+#[allow(dead_code)]
+#[derive(Clone)]
+enum WideValue {
+    Number(f64),
+    StringValue(String),
+    Boolean(bool),
+    Null,
+    List(Rc<RefCell<Vec<WideValue>>>),
+    Map(Rc<RefCell<BTreeMap<String, WideValue>>>),
+    Enum(Rc<EnumDefinition>),
+    EnumConstructor(Rc<EnumDefinition>, String),
+    EnumValue(Rc<EnumValue>),
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    NativeFunction(Rc<NativeFunction>),
+    BoundMethod(Rc<BoundMethod>),
+    Module(Rc<Module>),
+    Regex(Rc<Regex>),
+}
+
+trait Layout: Clone {
+    fn number(n: f64) -> Self;
+    fn string(s: &str) -> Self;
+    fn list() -> Self;
+    fn add(&self, other: &Self) -> Self;
+}
+
+impl Layout for Value {
+    fn number(n: f64) -> Self {
+        return Value::Number(n);
+    }
+
+    fn string(s: &str) -> Self {
+        return Value::from(s);
+    }
+
+    fn list() -> Self {
+        return Value::List(Rc::new(RefCell::new(vec![])));
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        return match (self, other) {
+            (Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+            _ => self.clone(),
+        };
+    }
+}
+
+impl Layout for WideValue {
+    fn number(n: f64) -> Self {
+        return WideValue::Number(n);
+    }
+
+    fn string(s: &str) -> Self {
+        return WideValue::StringValue(s.to_string());
+    }
+
+    fn list() -> Self {
+        return WideValue::List(Rc::new(RefCell::new(vec![])));
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        return match (self, other) {
+            (WideValue::Number(a), WideValue::Number(b)) => WideValue::Number(a + b),
+            _ => self.clone(),
+        };
+    }
+}
+
+const LAYOUT_STEPS: usize = 2_000_000;
+
+// What the VM does with values on every instruction: copy locals onto the stack, pop operands
+// and push results. Half the locals are numbers, the rest a string and a list.
+fn layout<V: Layout>(strings: bool) -> Duration {
+    let locals = [V::number(1.5), V::number(2.0), if strings { V::string("a short string") } else { V::number(3.0) }, V::list()];
+
+    return best_of(|| {
+        let mut stack: Vec<V> = Vec::with_capacity(256);
+
+        for i in 0..LAYOUT_STEPS {
+            stack.push(black_box(&locals)[i % 4].clone());
+            stack.push(locals[(i + 1) % 4].clone());
+            let b = stack.pop().unwrap();
+            let a = stack.pop().unwrap();
+            stack.push(a.add(&b));
+            black_box(stack.pop());
+        }
+    });
+}
+
+fn main() {
+    println!("{:<16} {:>10} {:>10}", "benchmark", "tree", "vm");
+
+    for (name, source) in PROGRAMS {
+        let tree = time(source, Backend::Tree);
+        let vm = time(source, Backend::Vm);
+
+        println!("{:<16} {:>8.2}ms {:>8.2}ms", name, tree.as_secs_f64() * 1000.0, vm.as_secs_f64() * 1000.0);
+    }
+
+    println!();
+    println!("{:<16} {:>10} {:>10}", "layout", format!("{} bytes", size_of::<Value>()), format!("{} bytes", size_of::<WideValue>()));
+
+    for (name, strings) in [("numbers", false), ("with strings", true)] {
+        let compact = layout::<Value>(strings);
+        let wide = layout::<WideValue>(strings);
+
+        println!("{:<16} {:>8.2}ms {:>8.2}ms", name, compact.as_secs_f64() * 1000.0, wide.as_secs_f64() * 1000.0);
+    }
+}
@@ -0,0 +1,582 @@
+use std::rc::Rc;
+use crate::chunk::{Chunk, Constant, FunctionProto, OpCode, UpvalueSource, NO_TARGET};
+use crate::symbol::Symbol;
+
+// Compiled scripts are stored as the magic number and format version, then the script's
+// function. A function is its name, file, arity, upvalues, code, line table and constants,
+// where a function constant nests another function. Integers are big-endian, like chunk
+// operands.
+pub const MAGIC: &[u8; 4] = b"PLTC";
+pub const VERSION: u16 = 3;
+
+const NUMBER: u8 = 0;
+const STRING: u8 = 1;
+const FUNCTION: u8 = 2;
+const ENUM: u8 = 3;
+
+// How deeply function constants may nest. Each level is read by a nested call, so a crafted
+// file can't nest them deep enough to overflow the native stack.
+const MAX_NESTING: usize = 256;
+
+pub fn serialize(function: &FunctionProto) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    bytes.extend(VERSION.to_be_bytes());
+    write_function(&mut bytes, function);
+
+    return bytes;
+}
+
+// Reads a compiled script back, checking it is well formed before the VM ever runs it.
+pub fn deserialize(bytes: &[u8]) -> Result<Rc<FunctionProto>, String> {
+    let mut reader = Reader { bytes, offset: 0, depth: 0 };
+
+    if reader.take(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+        return Err("Not a platypus bytecode file".to_string());
+    }
+
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(format!("Unsupported bytecode version {} (expected {})", version, VERSION));
+    }
+
+    let function = reader.function()?;
+
+    // `vm::run` starts the script without arguments or captured variables, so nothing would
+    // fill its parameter slots or upvalues.
+    if function.arity != 0 {
+        return Err(format!("The script can't take parameters, but the file gives it {}", function.arity));
+    }
+    if !function.upvalues.is_empty() {
+        return Err(format!("The script can't capture variables, but the file gives it {}", function.upvalues.len()));
+    }
+
+    if reader.offset != bytes.len() {
+        return Err(format!("Unexpected data after the script at byte {}", reader.offset));
+    }
+
+    return Ok(function);
+}
+
+fn write_function(bytes: &mut Vec<u8>, function: &FunctionProto) {
+    let chunk = &function.chunk;
+
+    write_string(bytes, &function.name);
+    write_string(bytes, &function.file);
+    write_u32(bytes, function.arity);
+    write_u32(bytes, function.upvalues.len());
+    for upvalue in &function.upvalues {
+        bytes.push(upvalue.local as u8);
+        bytes.extend(upvalue.index.to_be_bytes());
+    }
+
+    write_u32(bytes, chunk.code.len());
+    bytes.extend(&chunk.code);
+
+    // Lines change rarely from one byte to the next, so the table is run-length encoded.
+    let mut runs: Vec<(usize, usize)> = vec![];
+    for line in &chunk.lines {
+        match runs.last_mut() {
+            Some((last, count)) if last == line => *count += 1,
+            _ => runs.push((*line, 1)),
+        }
+    }
+
+    write_u32(bytes, runs.len());
+    for (line, count) in runs {
+        write_u32(bytes, line);
+        write_u32(bytes, count);
+    }
+
+    write_u32(bytes, chunk.constants.len());
+    for constant in &chunk.constants {
+        match constant {
+            Constant::Number(n) => {
+                bytes.push(NUMBER);
+                bytes.extend(n.to_be_bytes());
+            },
+            Constant::String(s) => {
+                bytes.push(STRING);
+                write_string(bytes, s.as_str());
+            },
+            Constant::Function(function) => {
+                bytes.push(FUNCTION);
+                write_function(bytes, function);
+            },
+            Constant::Enum { name, variants } => {
+                bytes.push(ENUM);
+                write_string(bytes, name);
+                write_u32(bytes, variants.len());
+
+                for (variant, arity) in variants {
+                    write_string(bytes, variant);
+                    write_u32(bytes, *arity);
+                }
+            },
+        }
+    }
+}
+
+fn write_u32(bytes: &mut Vec<u8>, value: usize) {
+    bytes.extend((value as u32).to_be_bytes());
+}
+
+fn write_string(bytes: &mut Vec<u8>, value: &str) {
+    write_u32(bytes, value.len());
+    bytes.extend(value.as_bytes());
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    // Function constants being read around the current one.
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.offset < count {
+            return Err("Unexpected end of bytecode file".to_string());
+        }
+
+        let bytes = &self.bytes[self.offset..self.offset + count];
+        self.offset += count;
+
+        return Ok(bytes);
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        return Ok(self.take(1)?[0]);
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        return Ok(u16::from_be_bytes([bytes[0], bytes[1]]));
+    }
+
+    fn u32(&mut self) -> Result<usize, String> {
+        let bytes = self.take(4)?;
+        return Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize);
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let length = self.u32()?;
+        let bytes = self.take(length)?;
+
+        return String::from_utf8(bytes.to_vec()).map_err(|_| format!("Invalid UTF-8 in string ending at byte {}", self.offset));
+    }
+
+    fn function(&mut self) -> Result<Rc<FunctionProto>, String> {
+        let name = self.string()?;
+        let file = self.string()?;
+        let arity = self.u32()?;
+
+        let mut upvalues = vec![];
+        for _ in 0..self.u32()? {
+            let local = self.u8()? != 0;
+            upvalues.push(UpvalueSource { local, index: self.u16()? });
+        }
+
+        let length = self.u32()?;
+        let code = self.take(length)?.to_vec();
+
+        let mut lines = vec![];
+        for _ in 0..self.u32()? {
+            let line = self.u32()?;
+            let count = self.u32()?;
+
+            if lines.len() + count > code.len() {
+                return Err(format!("Line table of {} covers more than its code", name));
+            }
+
+            lines.extend(std::iter::repeat_n(line, count));
+        }
+
+        if lines.len() != code.len() {
+            return Err(format!("Line table of {} doesn't cover its code", name));
+        }
+
+        let mut constants = vec![];
+        for _ in 0..self.u32()? {
+            let constant = match self.u8()? {
+                NUMBER => {
+                    let bytes = self.take(8)?;
+                    Constant::Number(f64::from_be_bytes(bytes.try_into().unwrap()))
+                },
+                STRING => Constant::String(Symbol::intern(&self.string()?)),
+                FUNCTION => Constant::Function(self.nested_function()?),
+                ENUM => {
+                    let name = self.string()?;
+                    let mut variants = vec![];
+
+                    for _ in 0..self.u32()? {
+                        variants.push((self.string()?, self.u32()?));
+                    }
+
+                    Constant::Enum { name, variants }
+                },
+                tag => return Err(format!("Unknown constant type {} in {}", tag, name)),
+            };
+
+            constants.push(constant);
+        }
+
+        let function = FunctionProto { name, file, arity, upvalues, chunk: Chunk { code, constants, lines } };
+        validate(&function)?;
+
+        return Ok(Rc::new(function));
+    }
+
+    fn nested_function(&mut self) -> Result<Rc<FunctionProto>, String> {
+        if self.depth == MAX_NESTING {
+            return Err(format!("Functions nested deeper than {} levels at byte {}", MAX_NESTING, self.offset));
+        }
+
+        self.depth += 1;
+        let function = self.function();
+        self.depth -= 1;
+
+        return function;
+    }
+}
+
+// Checks every instruction decodes, that its operands name constants of the right kind and
+// jump to instructions inside the function, and that it only uses stack slots that exist.
+fn validate(function: &FunctionProto) -> Result<(), String> {
+    let chunk = &function.chunk;
+    let error = |offset: usize, message: &str| format!("Invalid bytecode in {} at {:04}: {}", function.name, offset, message);
+
+    let mut starts = vec![];
+    let mut offset = 0;
+
+    while offset < chunk.code.len() {
+        let op = OpCode::from_byte(chunk.code[offset]).ok_or_else(|| error(offset, &format!("unknown opcode {}", chunk.code[offset])))?;
+
+        starts.push(offset);
+        offset += 1 + op.operand_bytes();
+    }
+
+    if offset != chunk.code.len() {
+        return Err(error(*starts.last().unwrap(), "instruction runs past the end of the code"));
+    }
+
+    if starts.last().map(|start| chunk.code[*start]) != Some(OpCode::Return as u8) {
+        return Err(error(chunk.code.len(), "code doesn't end with a return"));
+    }
+
+    let constant = |offset: usize, operand: usize, valid: fn(&Constant) -> bool| -> Result<(), String> {
+        match chunk.constants.get(chunk.read_u16(operand) as usize) {
+            Some(constant) if valid(constant) => Ok(()),
+            Some(_) => Err(error(offset, "constant of the wrong type")),
+            None => Err(error(offset, "constant index out of range")),
+        }
+    };
+    let target = |offset: usize, target: usize| -> Result<(), String> {
+        match starts.binary_search(&target) {
+            Ok(_) => Ok(()),
+            Err(_) => Err(error(offset, &format!("jump to {:04}, which isn't an instruction", target))),
+        }
+    };
+
+    let name = |constant: &Constant| matches!(constant, Constant::String(_));
+
+    for start in &starts {
+        let start = *start;
+        let operand = start + 1;
+        let op = OpCode::from_byte(chunk.code[start]).unwrap();
+
+        match op {
+            OpCode::Constant => constant(start, operand, |c| matches!(c, Constant::Number(_) | Constant::String(_)))?,
+            OpCode::Closure => {
+                constant(start, operand, |c| matches!(c, Constant::Function(_)))?;
+
+                if let Constant::Function(inner) = &chunk.constants[chunk.read_u16(operand) as usize] {
+                    if inner.upvalues.iter().any(|upvalue| !upvalue.local && upvalue.index as usize >= function.upvalues.len()) {
+                        return Err(error(start, "closure captures an upvalue that doesn't exist"));
+                    }
+                }
+            },
+            OpCode::GetUpvalue if chunk.read_u16(operand) as usize >= function.upvalues.len() => {
+                return Err(error(start, "upvalue index out of range"));
+            },
+            OpCode::Enum => constant(start, operand, |c| matches!(c, Constant::Enum { .. }))?,
+            OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::GetProperty | OpCode::Import | OpCode::ImportName
+            | OpCode::MapHas | OpCode::MapGet | OpCode::MissingKey => constant(start, operand, name)?,
+            OpCode::MatchVariant => {
+                if chunk.read_u16(operand) != NO_TARGET {
+                    constant(start, operand, name)?;
+                }
+
+                constant(start, operand + 2, name)?;
+            },
+            OpCode::Jump | OpCode::JumpIfFalse => target(start, operand + 2 + chunk.read_u16(operand) as usize)?,
+            OpCode::SetupTry => {
+                for handler in [chunk.read_u16(operand), chunk.read_u16(operand + 2)] {
+                    if handler != NO_TARGET {
+                        target(start, handler as usize)?;
+                    }
+                }
+            },
+            _ => {},
+        }
+    }
+
+    return check_stack(function, &starts);
+}
+
+// Follows the height of the stack through the code. Jumps only go forward, so one pass in code
+// order reaches every way into an instruction before the instruction itself; where paths meet,
+// the lowest height they arrive with is the one the instruction can count on.
+fn check_stack(function: &FunctionProto, starts: &[usize]) -> Result<(), String> {
+    let chunk = &function.chunk;
+    let error = |offset: usize, message: &str| format!("Invalid bytecode in {} at {:04}: {}", function.name, offset, message);
+
+    // Slot zero holds the function, then come its arguments.
+    let mut heights: Vec<Option<usize>> = vec![None; starts.len()];
+    heights[0] = Some(1 + function.arity);
+
+    for (i, start) in starts.iter().enumerate() {
+        let start = *start;
+        let operand = start + 1;
+        let op = OpCode::from_byte(chunk.code[start]).unwrap();
+
+        // Nothing jumps here, so it never runs.
+        let height = match heights[i] {
+            Some(height) => height,
+            None => continue,
+        };
+
+        let (popped, pushed) = match op {
+            OpCode::Call | OpCode::TailCall => (chunk.code[operand] as usize + 1, 1),
+            OpCode::List => (chunk.read_u16(operand) as usize, 1),
+            OpCode::Map => (chunk.read_u16(operand) as usize * 2, 1),
+            op => (op.popped(), (op.popped() as isize + op.stack_effect()) as usize),
+        };
+
+        if popped > height {
+            return Err(error(start, "stack underflow"));
+        }
+
+        match op {
+            OpCode::GetLocal if chunk.read_u16(operand) as usize >= height => return Err(error(start, "local slot out of range")),
+            // The value is popped before it is stored.
+            OpCode::SetLocal if chunk.read_u16(operand) as usize >= height - 1 => return Err(error(start, "local slot out of range")),
+            OpCode::Closure => {
+                if let Constant::Function(inner) = &chunk.constants[chunk.read_u16(operand) as usize] {
+                    if inner.upvalues.iter().any(|upvalue| upvalue.local && upvalue.index as usize >= height) {
+                        return Err(error(start, "closure captures a local slot out of range"));
+                    }
+                }
+            },
+            _ => {},
+        }
+
+        let after = height - popped + pushed;
+        let jump = || operand + 2 + chunk.read_u16(operand) as usize;
+
+        match op {
+            OpCode::Jump => merge(&mut heights, starts, jump(), after),
+            OpCode::JumpIfFalse => {
+                merge(&mut heights, starts, jump(), after);
+                merge(&mut heights, starts, starts[i + 1], after);
+            },
+            // Handlers resume with the stack cut back to its height when the try block started.
+            OpCode::SetupTry => {
+                for handler in [chunk.read_u16(operand), chunk.read_u16(operand + 2)] {
+                    if handler == NO_TARGET {
+                        continue;
+                    }
+
+                    if handler as usize <= start {
+                        return Err(error(start, "handler comes before its try block"));
+                    }
+
+                    merge(&mut heights, starts, handler as usize, height);
+                }
+
+                merge(&mut heights, starts, starts[i + 1], after);
+            },
+            OpCode::Return | OpCode::Throw | OpCode::Rethrow | OpCode::NoMatch | OpCode::MissingKey => {},
+            _ => merge(&mut heights, starts, starts[i + 1], after),
+        }
+    }
+
+    return Ok(());
+}
+
+fn merge(heights: &mut [Option<usize>], starts: &[usize], target: usize, height: usize) {
+    let index = starts.binary_search(&target).unwrap();
+
+    heights[index] = Some(heights[index].map_or(height, |known| known.min(height)));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capabilities::Capabilities;
+    use crate::compiler::compile;
+    use crate::disassembler::disassemble;
+    use crate::interpreter::Interpreter;
+    use crate::limits::Limits;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use super::*;
+
+    fn compiled(source: &str) -> Rc<FunctionProto> {
+        let statements = Parser::new(Scanner::new(source).scan_tokens().unwrap()).parse_statements().unwrap();
+
+        return compile(&statements, "test.pltps").unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let function = compiled(r#"
+            enum Shape { Circle(r), Empty }
+            fn area(shape) {
+                try {
+                    return match shape { Circle(r) => 3.5 * r * r, _ => 0 };
+                } finally {
+                    print "done";
+                }
+            }
+            let {name, size = 2} = {name: "circle"};
+            print [name, area(Shape.Circle(size))];
+        "#);
+
+        let loaded = deserialize(&serialize(&function)).unwrap();
+
+        assert_eq!(disassemble(&loaded), disassemble(&function));
+        assert_eq!(loaded.chunk.lines, function.chunk.lines);
+        assert_eq!(loaded.file, "test.pltps");
+    }
+
+    #[test]
+    fn test_loader_rejects_malformed_files() {
+        let bytes = serialize(&compiled("print 1 + 2;"));
+
+        assert_eq!(deserialize(b"#!/usr/bin/env platypus").unwrap_err(), "Not a platypus bytecode file");
+
+        let mut future = bytes.clone();
+        future[5] = 9;
+        assert_eq!(deserialize(&future).unwrap_err(), "Unsupported bytecode version 9 (expected 3)");
+
+        assert_eq!(deserialize(&bytes[..bytes.len() - 3]).unwrap_err(), "Unexpected end of bytecode file");
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(deserialize(&trailing).unwrap_err().starts_with("Unexpected data after the script"));
+
+        // The code starts after the header, the name, the file, the arity, the (empty) upvalues
+        // and the code length.
+        // Its first instruction loads constant 0; point it past the end of the pool.
+        let code = 6 + (4 + "<script>".len()) + (4 + "test.pltps".len()) + 4 + 4 + 4;
+        let mut out_of_range = bytes.clone();
+        out_of_range[code + 2] = 200;
+        assert_eq!(deserialize(&out_of_range).unwrap_err(), "Invalid bytecode in <script> at 0000: constant index out of range");
+
+        let mut unknown = bytes.clone();
+        unknown[code] = 255;
+        assert_eq!(deserialize(&unknown).unwrap_err(), "Invalid bytecode in <script> at 0000: unknown opcode 255");
+
+        // The code is Constant, Constant, Add, Print, Null, Return. Adding where the null was
+        // pops more than the stack holds.
+        let mut underflow = bytes.clone();
+        underflow[code + 8] = OpCode::Add as u8;
+        assert_eq!(deserialize(&underflow).unwrap_err(), "Invalid bytecode in <script> at 0008: stack underflow");
+
+        let mut missing_slot = bytes.clone();
+        missing_slot[code] = OpCode::GetLocal as u8;
+        missing_slot[code + 2] = 5;
+        assert_eq!(deserialize(&missing_slot).unwrap_err(), "Invalid bytecode in <script> at 0000: local slot out of range");
+
+        // With an arity of 3 the loader would let the script read slot 2, which nothing fills.
+        let arity = code - 12;
+        let mut parameters = bytes.clone();
+        parameters[arity + 3] = 3;
+        parameters[code] = OpCode::GetLocal as u8;
+        parameters[code + 2] = 2;
+        assert_eq!(deserialize(&parameters).unwrap_err(), "The script can't take parameters, but the file gives it 3");
+
+        let mut capturing = Rc::try_unwrap(compiled("print 1 + 2;")).unwrap();
+        capturing.upvalues.push(UpvalueSource { local: true, index: 0 });
+        assert_eq!(deserialize(&serialize(&capturing)).unwrap_err(), "The script can't capture variables, but the file gives it 1");
+    }
+
+    #[test]
+    fn test_loader_limits_function_nesting() {
+        // A function returning null whose only constant is the next function in. Constants come
+        // last, so the file is these headers repeated, then a function without constants.
+        fn header(bytes: &mut Vec<u8>, constants: usize) {
+            write_string(bytes, "f");
+            write_string(bytes, "test.pltc");
+            write_u32(bytes, 0);
+            write_u32(bytes, 0);
+            write_u32(bytes, 2);
+            bytes.extend([OpCode::Null as u8, OpCode::Return as u8]);
+            write_u32(bytes, 1);
+            write_u32(bytes, 1);
+            write_u32(bytes, 2);
+            write_u32(bytes, constants);
+        }
+
+        let nested = |levels: usize| {
+            let mut bytes = MAGIC.to_vec();
+            bytes.extend(VERSION.to_be_bytes());
+            for _ in 0..levels {
+                header(&mut bytes, 1);
+                bytes.push(FUNCTION);
+            }
+            header(&mut bytes, 0);
+
+            return bytes;
+        };
+
+        assert!(deserialize(&nested(MAX_NESTING)).is_ok());
+        assert!(deserialize(&nested(MAX_NESTING + 1)).unwrap_err().starts_with("Functions nested deeper than 256 levels"));
+        assert!(deserialize(&nested(100_000)).unwrap_err().starts_with("Functions nested deeper than 256 levels"));
+    }
+
+    // Whatever the loader accepts has to run without panicking the VM, so corrupt every byte of
+    // a script using most opcodes in a few ways and run whatever still loads.
+    #[test]
+    fn test_corrupted_files_load_or_fail_cleanly() {
+        let bytes = serialize(&compiled(r#"
+            enum Shape { Circle(r), Empty }
+            fn area(shape) {
+                return match shape { Shape.Circle(r) => 3 * r * r, [a, ...rest] => a, {k: v} => v, _ => 0 };
+            }
+            fn counter() {
+                let n = [1, 2];
+                fn get() { return n; }
+                return get;
+            }
+            let f = counter();
+            let [x, y] = f();
+            try { area(Shape.Circle(x)); throw y; } catch (e) { area({k: e}); } finally { area([x]); }
+            -x + !y;
+        "#));
+
+        let mut corrupted = vec![];
+        for length in 0..bytes.len() {
+            corrupted.push(bytes[..length].to_vec());
+        }
+        for offset in 0..bytes.len() {
+            for byte in [0, 1, 2, 5, 31, 255, bytes[offset] ^ 1, bytes[offset].wrapping_add(1), bytes[offset].wrapping_sub(1)] {
+                let mut file = bytes.clone();
+                file[offset] = byte;
+                corrupted.push(file);
+            }
+        }
+
+        for file in corrupted {
+            if let Ok(function) = deserialize(&file) {
+                let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    // A corrupted tail call can loop forever.
+                    let mut interpreter = Interpreter::with_capabilities(Capabilities::none());
+                    interpreter.set_limits(Limits { steps: Some(10_000), ..Limits::default() });
+                    let _ = interpreter.run_compiled(function.clone());
+                }));
+
+                assert!(result.is_ok(), "the VM panicked on a file the loader accepted:\n{}", disassemble(&function));
+            }
+        }
+    }
+}
@@ -0,0 +1,43 @@
+// Which host resources scripts may touch. Each enabled capability makes its builtin
+// module available; using or importing a disabled one raises a `CapabilityDenied` error.
+// With none, only the pure modules are left: `math`, `json`, `regex`, `time` and `gc`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Capabilities {
+    // `input`, `eprint` and the `io` module.
+    pub io: bool,
+    // The `fs` module, and importing files from outside the search paths.
+    pub fs: bool,
+    // The `process` module: arguments, environment variables and exiting.
+    pub process: bool,
+    // `process.run`, for spawning subprocesses. Off unless asked for.
+    pub subprocess: bool,
+}
+
+impl Capabilities {
+    pub fn all() -> Self {
+        Self {
+            io: true,
+            fs: true,
+            process: true,
+            subprocess: true,
+        }
+    }
+
+    pub fn none() -> Self {
+        Self {
+            io: false,
+            fs: false,
+            process: false,
+            subprocess: false,
+        }
+    }
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        return Self {
+            subprocess: false,
+            ..Self::all()
+        };
+    }
+}
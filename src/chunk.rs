@@ -0,0 +1,222 @@
+use std::rc::Rc;
+use crate::symbol::Symbol;
+
+// Operands follow the opcode byte. Unless noted they are u16, big-endian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+    Constant,
+    Null,
+    True,
+    False,
+    Pop,
+    GetLocal,
+    // Pops the value into the slot.
+    SetLocal,
+    GetGlobal,
+    // Pops the value into the named global.
+    DefineGlobal,
+    GetProperty,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Negate,
+    Not,
+    Print,
+    // Jumps are forward, relative to the end of the instruction.
+    Jump,
+    // Pops the condition.
+    JumpIfFalse,
+    // One u8 operand: the argument count.
+    Call,
+    // A call whose value is returned straight away. Calling a compiled function reuses the
+    // caller's frame; anything else is called like `Call`, with the `Return` after it still to run.
+    TailCall,
+    // Creates a closure over the function constant, capturing the upvalues its prototype lists.
+    Closure,
+    GetUpvalue,
+    // Moves the local on top of the stack into the upvalues that captured it, then pops it.
+    CloseUpvalue,
+    Return,
+    List,
+    // Pops `count` key/value pairs, keys first.
+    Map,
+    Enum,
+    Import,
+    // Replaces the module on top of the stack with one of its exports.
+    ImportName,
+    Throw,
+    // Two absolute code offsets, catch then finally, with `NO_TARGET` for a missing one.
+    SetupTry,
+    PopTry,
+    // Pushes the value of the error a catch block is handling.
+    TakeError,
+    // Raises the error a finally block ran for, once the block is done.
+    Rethrow,
+    // The remaining opcodes take apart values for patterns. Tests pop the value and push a boolean.
+    // Operands: the element count, then a u8 flag for a `...rest` element.
+    MatchList,
+    IsMap,
+    MapHas,
+    MapGet,
+    // Operands: the enum name constant or `NO_TARGET`, the variant name constant, the field count.
+    MatchVariant,
+    Index,
+    SliceFrom,
+    VariantField,
+    // Like `MatchList` and `IsMap`, but raise a destructuring error instead of pushing false.
+    DestructureList,
+    DestructureMap,
+    MissingKey,
+    NoMatch,
+}
+
+pub const NO_TARGET: u16 = u16::MAX;
+
+const OPCODES: [OpCode; 53] = [
+    OpCode::Constant, OpCode::Null, OpCode::True, OpCode::False, OpCode::Pop,
+    OpCode::GetLocal, OpCode::SetLocal, OpCode::GetGlobal, OpCode::DefineGlobal, OpCode::GetProperty,
+    OpCode::Add, OpCode::Subtract, OpCode::Multiply, OpCode::Divide,
+    OpCode::Equal, OpCode::NotEqual, OpCode::Greater, OpCode::GreaterEqual, OpCode::Less, OpCode::LessEqual,
+    OpCode::Negate, OpCode::Not, OpCode::Print, OpCode::Jump, OpCode::JumpIfFalse,
+    OpCode::Call, OpCode::TailCall, OpCode::Closure, OpCode::GetUpvalue, OpCode::CloseUpvalue, OpCode::Return,
+    OpCode::List, OpCode::Map, OpCode::Enum,
+    OpCode::Import, OpCode::ImportName, OpCode::Throw,
+    OpCode::SetupTry, OpCode::PopTry, OpCode::TakeError, OpCode::Rethrow,
+    OpCode::MatchList, OpCode::IsMap, OpCode::MapHas, OpCode::MapGet, OpCode::MatchVariant,
+    OpCode::Index, OpCode::SliceFrom, OpCode::VariantField,
+    OpCode::DestructureList, OpCode::DestructureMap, OpCode::MissingKey, OpCode::NoMatch,
+];
+
+impl OpCode {
+    // The table is in declaration order, which the filter double-checks.
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        return OPCODES.get(byte as usize).copied().filter(|op| *op as u8 == byte);
+    }
+
+    pub fn operand_bytes(&self) -> usize {
+        return match self {
+            OpCode::Call | OpCode::TailCall => 1,
+            OpCode::Constant | OpCode::GetLocal | OpCode::SetLocal | OpCode::GetGlobal | OpCode::DefineGlobal
+            | OpCode::GetProperty | OpCode::Jump | OpCode::JumpIfFalse | OpCode::Closure | OpCode::GetUpvalue | OpCode::List
+            | OpCode::Map | OpCode::Enum | OpCode::Import | OpCode::ImportName | OpCode::MapHas | OpCode::MapGet
+            | OpCode::Index | OpCode::SliceFrom | OpCode::VariantField | OpCode::MissingKey => 2,
+            OpCode::MatchList | OpCode::DestructureList => 3,
+            OpCode::SetupTry => 4,
+            OpCode::MatchVariant => 6,
+            _ => 0,
+        }
+    }
+
+    // How many slots the stack grows by, for opcodes whose effect doesn't depend on an operand.
+    pub fn stack_effect(&self) -> isize {
+        return match self {
+            OpCode::Constant | OpCode::Null | OpCode::True | OpCode::False | OpCode::GetLocal | OpCode::GetGlobal
+            | OpCode::Closure | OpCode::GetUpvalue | OpCode::Enum | OpCode::Import | OpCode::TakeError => 1,
+            OpCode::Pop | OpCode::CloseUpvalue | OpCode::SetLocal | OpCode::DefineGlobal | OpCode::Print | OpCode::JumpIfFalse | OpCode::Throw
+            | OpCode::Return | OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide | OpCode::Equal
+            | OpCode::NotEqual | OpCode::Greater | OpCode::GreaterEqual | OpCode::Less | OpCode::LessEqual
+            | OpCode::DestructureList | OpCode::DestructureMap | OpCode::NoMatch => -1,
+            _ => 0,
+        }
+    }
+
+    // How many values the instruction takes off the stack, for the same opcodes.
+    pub fn popped(&self) -> usize {
+        return match self {
+            OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide | OpCode::Equal | OpCode::NotEqual
+            | OpCode::Greater | OpCode::GreaterEqual | OpCode::Less | OpCode::LessEqual => 2,
+            OpCode::Pop | OpCode::CloseUpvalue | OpCode::SetLocal | OpCode::DefineGlobal | OpCode::GetProperty
+            | OpCode::Negate | OpCode::Not | OpCode::Print | OpCode::JumpIfFalse | OpCode::Return | OpCode::ImportName
+            | OpCode::Throw | OpCode::MatchList | OpCode::IsMap | OpCode::MapHas | OpCode::MapGet | OpCode::MatchVariant
+            | OpCode::Index | OpCode::SliceFrom | OpCode::VariantField | OpCode::DestructureList | OpCode::DestructureMap
+            | OpCode::NoMatch => 1,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Constant {
+    Number(f64),
+    String(Symbol),
+    Function(Rc<FunctionProto>),
+    Enum {
+        name: String,
+        variants: Vec<(String, usize)>,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct Chunk {
+    pub code: Vec<u8>,
+    pub constants: Vec<Constant>,
+    // The source line of every byte in `code`.
+    pub lines: Vec<usize>,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        return Self::default();
+    }
+
+    pub fn write(&mut self, byte: u8, line: usize) {
+        self.code.push(byte);
+        self.lines.push(line);
+    }
+
+    pub fn write_u16(&mut self, value: u16, line: usize) {
+        self.write((value >> 8) as u8, line);
+        self.write(value as u8, line);
+    }
+
+    pub fn read_u16(&self, offset: usize) -> u16 {
+        return ((self.code[offset] as u16) << 8) | self.code[offset + 1] as u16;
+    }
+
+    pub fn patch_u16(&mut self, offset: usize, value: u16) {
+        self.code[offset] = (value >> 8) as u8;
+        self.code[offset + 1] = value as u8;
+    }
+
+    // Reuses an existing slot for equal numbers and strings, so names repeated across a
+    // chunk share one constant.
+    pub fn add_constant(&mut self, constant: Constant) -> usize {
+        let existing = self.constants.iter().position(|c| match (c, &constant) {
+            (Constant::Number(a), Constant::Number(b)) => a.to_bits() == b.to_bits(),
+            (Constant::String(a), Constant::String(b)) => a == b,
+            _ => false,
+        });
+
+        if let Some(index) = existing {
+            return index;
+        }
+
+        self.constants.push(constant);
+        return self.constants.len() - 1;
+    }
+}
+
+// Where a closure finds a variable it captures when it is created: a local slot of the
+// function creating it, or one of that function's own upvalues.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpvalueSource {
+    pub local: bool,
+    pub index: u16,
+}
+
+#[derive(Debug)]
+pub struct FunctionProto {
+    pub name: String,
+    pub file: String,
+    pub arity: usize,
+    pub upvalues: Vec<UpvalueSource>,
+    pub chunk: Chunk,
+}
@@ -0,0 +1,1023 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::chunk::{Chunk, Constant, FunctionProto, OpCode, UpvalueSource, NO_TARGET};
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::expr::{Expr, ExpressionLiteralValue, MatchArm};
+use crate::pattern::Pattern;
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::token_type::TokenType;
+
+struct Local {
+    // None for hidden slots: temporaries and bindings a pattern hasn't finished matching yet.
+    name: Option<Symbol>,
+    depth: usize,
+    slot: u16,
+    // Block declarations get their slot when the block starts, like the tree-walker's scopes,
+    // so functions declared earlier can capture them. Until the declaration runs, reads in the
+    // block itself still see the enclosing variable of the same name.
+    defined: bool,
+    // Captured by a closure, so the slot is moved into its upvalue when the scope ends.
+    captured: bool,
+}
+
+#[derive(Clone, Copy)]
+struct TryScope<'a> {
+    finally: Option<&'a [Stmt]>,
+}
+
+struct FunctionState<'a> {
+    name: String,
+    arity: usize,
+    chunk: Chunk,
+    locals: Vec<Local>,
+    upvalues: Vec<UpvalueSource>,
+    scope_depth: usize,
+    // Stack slots in use at this point of the code, locals and temporaries alike.
+    height: usize,
+    // Try blocks around the code being compiled, innermost last, so `return` can leave them.
+    tries: Vec<TryScope<'a>>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PatternMode {
+    // A failed test jumps to the next match arm.
+    Match,
+    // A failed test raises a destructuring error at the `let`.
+    Destructure,
+}
+
+struct PatternContext<'p> {
+    mode: PatternMode,
+    // Destructuring errors are reported at the `let`, however deep the pattern that failed.
+    line: usize,
+    bindings: &'p HashMap<Symbol, u16>,
+    temps: Vec<u16>,
+    failures: Vec<usize>,
+}
+
+pub struct Compiler<'a> {
+    file: String,
+    functions: Vec<FunctionState<'a>>,
+    line: usize,
+}
+
+// Compiles a script or module into the function the VM runs it as. Top-level declarations
+// become globals, everything nested lives in stack slots.
+pub fn compile(statements: &[Stmt], file: &str) -> Result<Rc<FunctionProto>, RuntimeError> {
+    let mut compiler = Compiler {
+        file: file.to_string(),
+        functions: vec![],
+        line: 0,
+    };
+
+    compiler.begin_function("<script>", &[]);
+
+    match statements.split_last() {
+        Some((Stmt::Expression { expression }, rest)) => {
+            for statement in rest {
+                compiler.statement(statement)?;
+            }
+
+            compiler.expression(expression)?;
+        },
+        _ => {
+            for statement in statements {
+                compiler.statement(statement)?;
+            }
+
+            compiler.emit(OpCode::Null);
+        },
+    }
+
+    compiler.emit(OpCode::Return);
+
+    return Ok(compiler.end_function());
+}
+
+impl<'a> Compiler<'a> {
+    fn begin_function(&mut self, name: &str, params: &[Token]) {
+        let mut state = FunctionState {
+            name: name.to_string(),
+            arity: params.len(),
+            chunk: Chunk::new(),
+            locals: vec![],
+            upvalues: vec![],
+            scope_depth: if self.functions.is_empty() { 0 } else { 1 },
+            height: 0,
+            tries: vec![],
+        };
+
+        // Slot zero holds the function being called.
+        state.locals.push(Local { name: None, depth: state.scope_depth, slot: 0, defined: true, captured: false });
+        state.height = 1;
+
+        for param in params {
+            state.locals.push(Local { name: Some(param.lexeme.clone()), depth: state.scope_depth, slot: state.height as u16, defined: true, captured: false });
+            state.height += 1;
+        }
+
+        self.functions.push(state);
+    }
+
+    fn end_function(&mut self) -> Rc<FunctionProto> {
+        let state = self.functions.pop().unwrap();
+
+        return Rc::new(FunctionProto {
+            name: state.name,
+            file: self.file.clone(),
+            arity: state.arity,
+            upvalues: state.upvalues,
+            chunk: state.chunk,
+        });
+    }
+
+    fn current(&mut self) -> &mut FunctionState<'a> {
+        return self.functions.last_mut().unwrap();
+    }
+
+    fn statement(&mut self, statement: &'a Stmt) -> Result<(), RuntimeError> {
+        match statement {
+            Stmt::Expression { expression } => {
+                self.expression(expression)?;
+                self.emit(OpCode::Pop);
+            },
+            Stmt::Print { expression } => {
+                self.expression(expression)?;
+                self.emit(OpCode::Print);
+            },
+            Stmt::Let { keyword, pattern, initializer } => {
+                self.line = keyword.line_number;
+
+                match initializer {
+                    Some(initializer) => self.expression(initializer)?,
+                    None => self.emit(OpCode::Null),
+                }
+
+                self.let_pattern(keyword, pattern)?;
+            },
+            Stmt::Enum { name, variants } => {
+                self.line = name.line_number;
+
+                let constant = self.make_constant(Constant::Enum {
+                    name: name.lexeme.to_string(),
+                    variants: variants.iter()
+                        .map(|variant| (variant.name.lexeme.to_string(), variant.fields.len()))
+                        .collect(),
+                })?;
+
+                self.emit_with_u16(OpCode::Enum, constant);
+                self.define_variable(name.lexeme.clone())?;
+            },
+            Stmt::Block { statements } => self.block(statements)?,
+            Stmt::Function { name, params, body } => {
+                self.line = name.line_number;
+                self.function(name, params, body)?;
+                self.define_variable(name.lexeme.clone())?;
+            },
+            Stmt::Return { keyword: _, value } => {
+                match value {
+                    // A call inside a try isn't the last thing the function does: the try still
+                    // has to handle its errors.
+                    Some(value) if self.current().tries.is_empty() => self.tail_expression(value)?,
+                    Some(value) => self.expression(value)?,
+                    None => self.emit(OpCode::Null),
+                }
+
+                self.return_from_tries()?;
+                self.emit(OpCode::Return);
+            },
+            Stmt::Import { keyword, path, alias, names } => {
+                self.line = keyword.line_number;
+
+                let path = self.make_constant(Constant::String(Symbol::intern(path)))?;
+                self.emit_with_u16(OpCode::Import, path);
+
+                let module = self.add_local(None)?;
+
+                if let Some(alias) = alias {
+                    self.emit_with_u16(OpCode::GetLocal, module);
+                    self.define_variable(alias.lexeme.clone())?;
+                }
+
+                for name in names {
+                    self.line = name.line_number;
+
+                    let constant = self.make_constant(Constant::String(name.lexeme.clone()))?;
+                    self.emit_with_u16(OpCode::GetLocal, module);
+                    self.emit_with_u16(OpCode::ImportName, constant);
+                    self.define_variable(name.lexeme.clone())?;
+                }
+
+                // At the top level the module slot is only scratch space; in a block it stays
+                // hidden until the block ends, since locals may have been declared above it.
+                if self.current().scope_depth == 0 {
+                    self.current().locals.pop();
+                    self.emit(OpCode::Pop);
+                }
+            },
+            Stmt::Export { declaration } => self.statement(declaration)?,
+            Stmt::Throw { keyword, value } => {
+                self.expression(value)?;
+                self.line = keyword.line_number;
+                self.emit(OpCode::Throw);
+            },
+            Stmt::Try { body, catch_name, catch_body, finally_body } => {
+                self.try_statement(body, catch_name, catch_body, finally_body)?;
+            },
+        }
+
+        return Ok(());
+    }
+
+    fn function(&mut self, name: &Token, params: &[Token], body: &'a [Stmt]) -> Result<(), RuntimeError> {
+        self.begin_function(name.lexeme.as_str(), params);
+        self.declare(body)?;
+
+        for statement in body {
+            self.statement(statement)?;
+        }
+
+        self.emit(OpCode::Null);
+        self.emit(OpCode::Return);
+
+        let function = self.end_function();
+        self.line = name.line_number;
+        let constant = self.make_constant(Constant::Function(function))?;
+        self.emit_with_u16(OpCode::Closure, constant);
+
+        return Ok(());
+    }
+
+    // The try body runs under a handler whose catch target binds the error and runs the catch
+    // body, and whose finally target runs the finally body and raises the error again. With both
+    // a catch and a finally, a second handler covers the catch body so its errors reach the finally.
+    fn try_statement(
+        &mut self,
+        body: &'a [Stmt],
+        catch_name: &Option<Token>,
+        catch_body: &'a Option<Vec<Stmt>>,
+        finally_body: &'a Option<Vec<Stmt>>,
+    ) -> Result<(), RuntimeError> {
+        let finally = finally_body.as_deref();
+        let height = self.current().height;
+
+        let setup = self.emit_setup_try();
+        self.current().tries.push(TryScope { finally });
+        self.block(body)?;
+        self.current().tries.pop();
+        self.emit(OpCode::PopTry);
+
+        let mut done = vec![self.emit_jump(OpCode::Jump)];
+        let mut rethrow_setups = vec![];
+
+        if let (Some(name), Some(catch_body)) = (catch_name, catch_body) {
+            let target = self.current_offset()?;
+            self.current().chunk.patch_u16(setup, target);
+            self.current().height = height;
+
+            if finally.is_some() {
+                rethrow_setups.push(self.emit_setup_try());
+                self.current().tries.push(TryScope { finally });
+            }
+
+            self.begin_scope();
+            self.emit(OpCode::TakeError);
+            self.add_local(Some(name.lexeme.clone()))?;
+            self.declare(catch_body)?;
+
+            for statement in catch_body {
+                self.statement(statement)?;
+            }
+
+            self.end_scope();
+
+            if finally.is_some() {
+                self.current().tries.pop();
+                self.emit(OpCode::PopTry);
+            }
+
+            done.push(self.emit_jump(OpCode::Jump));
+        }
+
+        if let Some(finally) = finally {
+            let target = self.current_offset()?;
+            self.current().chunk.patch_u16(setup + 2, target);
+
+            for rethrow_setup in rethrow_setups {
+                self.current().chunk.patch_u16(rethrow_setup + 2, target);
+            }
+
+            self.current().height = height;
+            self.block(finally)?;
+            self.emit(OpCode::Rethrow);
+        }
+
+        for jump in done {
+            self.patch_jump(jump)?;
+        }
+
+        self.current().height = height;
+
+        if let Some(finally) = finally {
+            self.block(finally)?;
+        }
+
+        return Ok(());
+    }
+
+    // Before returning from inside try blocks, their handlers come off and their finally
+    // bodies run, innermost first, with the return value kept in a hidden slot.
+    fn return_from_tries(&mut self) -> Result<(), RuntimeError> {
+        let tries = self.current().tries.clone();
+
+        if tries.is_empty() {
+            return Ok(());
+        }
+
+        let height = self.current().height;
+        let hidden = self.add_local(None)?;
+
+        for (i, scope) in tries.iter().enumerate().rev() {
+            self.emit(OpCode::PopTry);
+
+            if let Some(finally) = scope.finally {
+                // A `return` inside this finally body only has the outer try blocks left to leave.
+                self.current().tries.truncate(i);
+                self.block(finally)?;
+            }
+        }
+
+        self.current().tries = tries;
+        self.current().locals.retain(|local| local.slot != hidden);
+        self.current().height = height;
+
+        return Ok(());
+    }
+
+    fn block(&mut self, statements: &'a [Stmt]) -> Result<(), RuntimeError> {
+        self.begin_scope();
+        self.declare(statements)?;
+
+        for statement in statements {
+            self.statement(statement)?;
+        }
+
+        self.end_scope();
+
+        return Ok(());
+    }
+
+    fn let_pattern(&mut self, keyword: &Token, pattern: &'a Pattern) -> Result<(), RuntimeError> {
+        match pattern {
+            Pattern::Binding { name } => return self.define_variable(name.lexeme.clone()),
+            Pattern::Wildcard => {
+                self.emit(OpCode::Pop);
+                return Ok(());
+            },
+            _ => {},
+        }
+
+        let height = self.current().height;
+        let locals = self.current().locals.len();
+        let declared = self.current().scope_depth > 0;
+        let subject = self.add_local(None)?;
+        let (bindings, temps) = self.pattern_slots(pattern, declared)?;
+
+        self.line = keyword.line_number;
+        self.emit_with_u16(OpCode::GetLocal, subject);
+
+        let mut context = PatternContext { mode: PatternMode::Destructure, line: keyword.line_number, bindings: &bindings, temps, failures: vec![] };
+        self.pattern(pattern, &mut context)?;
+
+        if declared {
+            for name in bindings.keys() {
+                self.define_local(name);
+            }
+
+            return Ok(());
+        }
+
+        let mut names: Vec<(&Symbol, &u16)> = bindings.iter().collect();
+        names.sort_by_key(|(_, slot)| **slot);
+
+        for (name, slot) in names {
+            let constant = self.make_constant(Constant::String(name.clone()))?;
+            self.emit_with_u16(OpCode::GetLocal, *slot);
+            self.emit_with_u16(OpCode::DefineGlobal, constant);
+        }
+
+        while self.current().height > height {
+            self.emit(OpCode::Pop);
+        }
+
+        self.current().locals.truncate(locals);
+
+        return Ok(());
+    }
+
+    // Reserves hidden slots, initialized to null, for the intermediate values a pattern takes
+    // apart and for its bindings, unless they go straight into the block's declared slots.
+    fn pattern_slots(&mut self, pattern: &Pattern, declared: bool) -> Result<(HashMap<Symbol, u16>, Vec<u16>), RuntimeError> {
+        let mut bindings = HashMap::new();
+
+        for name in pattern.bound_names() {
+            if let Entry::Vacant(entry) = bindings.entry(name) {
+                let slot = match declared {
+                    true => self.declared_slot(entry.key()).unwrap(),
+                    false => {
+                        self.emit(OpCode::Null);
+                        self.add_local(None)?
+                    },
+                };
+
+                entry.insert(slot);
+            }
+        }
+
+        let mut temps = vec![];
+
+        for _ in 0..composite_patterns(pattern) {
+            self.emit(OpCode::Null);
+            temps.push(self.add_local(None)?);
+        }
+
+        temps.reverse();
+
+        return Ok((bindings, temps));
+    }
+
+    // Gives a pattern's binding slots their names once the pattern has matched.
+    fn reveal(&mut self, bindings: &HashMap<Symbol, u16>) {
+        for local in self.current().locals.iter_mut() {
+            if let Some((name, _)) = bindings.iter().find(|(_, slot)| **slot == local.slot) {
+                local.name = Some(name.clone());
+            }
+        }
+    }
+
+    // Takes apart the value on top of the stack, popping it.
+    fn pattern(&mut self, pattern: &'a Pattern, context: &mut PatternContext) -> Result<(), RuntimeError> {
+        match pattern {
+            Pattern::Wildcard => self.emit(OpCode::Pop),
+            Pattern::Binding { name } => self.emit_with_u16(OpCode::SetLocal, context.bindings[&name.lexeme]),
+            Pattern::Literal { value } => {
+                self.literal(value)?;
+                self.emit(OpCode::Equal);
+                context.failures.push(self.emit_jump(OpCode::JumpIfFalse));
+            },
+            Pattern::List { elements, rest } => {
+                let temp = context.temps.pop().unwrap();
+                self.emit_with_u16(OpCode::SetLocal, temp);
+                self.emit_with_u16(OpCode::GetLocal, temp);
+
+                let op = match context.mode {
+                    PatternMode::Match => OpCode::MatchList,
+                    PatternMode::Destructure => OpCode::DestructureList,
+                };
+
+                self.line = context.line;
+                self.emit(op);
+                self.emit_u16(self.count(elements.len())?);
+                self.emit_byte(rest.is_some() as u8);
+
+                if context.mode == PatternMode::Match {
+                    context.failures.push(self.emit_jump(OpCode::JumpIfFalse));
+                }
+
+                for (i, element) in elements.iter().enumerate() {
+                    self.emit_with_u16(OpCode::GetLocal, temp);
+                    self.emit_with_u16(OpCode::Index, i as u16);
+                    self.pattern(element, context)?;
+                }
+
+                if let Some(rest) = rest {
+                    self.emit_with_u16(OpCode::GetLocal, temp);
+                    self.emit_with_u16(OpCode::SliceFrom, elements.len() as u16);
+                    self.emit_with_u16(OpCode::SetLocal, context.bindings[&rest.lexeme]);
+                }
+            },
+            Pattern::Map { entries } => {
+                let temp = context.temps.pop().unwrap();
+                self.emit_with_u16(OpCode::SetLocal, temp);
+                self.emit_with_u16(OpCode::GetLocal, temp);
+
+                match context.mode {
+                    PatternMode::Match => {
+                        self.emit(OpCode::IsMap);
+                        context.failures.push(self.emit_jump(OpCode::JumpIfFalse));
+                    },
+                    PatternMode::Destructure => {
+                        self.line = context.line;
+                        self.emit(OpCode::DestructureMap);
+                    },
+                }
+
+                for entry in entries {
+                    let key = self.make_constant(Constant::String(entry.key.lexeme.clone()))?;
+
+                    self.emit_with_u16(OpCode::GetLocal, temp);
+                    self.emit_with_u16(OpCode::MapHas, key);
+                    let missing = self.emit_jump(OpCode::JumpIfFalse);
+
+                    self.emit_with_u16(OpCode::GetLocal, temp);
+                    self.emit_with_u16(OpCode::MapGet, key);
+                    let found = self.emit_jump(OpCode::Jump);
+
+                    self.patch_jump(missing)?;
+                    self.current().height -= 1;
+
+                    match (&entry.default, context.mode) {
+                        (Some(default), _) => self.expression(default)?,
+                        (None, PatternMode::Match) => {
+                            context.failures.push(self.emit_jump(OpCode::Jump));
+                            self.current().height += 1;
+                        },
+                        (None, PatternMode::Destructure) => {
+                            self.line = entry.key.line_number;
+                            self.emit_with_u16(OpCode::MissingKey, key);
+                            self.current().height += 1;
+                        },
+                    }
+
+                    self.patch_jump(found)?;
+                    self.pattern(&entry.pattern, context)?;
+                }
+            },
+            Pattern::Variant { enum_name, variant, fields } => {
+                let temp = context.temps.pop().unwrap();
+                self.emit_with_u16(OpCode::SetLocal, temp);
+                self.emit_with_u16(OpCode::GetLocal, temp);
+
+                let enum_name = match enum_name {
+                    Some(enum_name) => self.make_constant(Constant::String(enum_name.lexeme.clone()))?,
+                    None => NO_TARGET,
+                };
+                let variant = self.make_constant(Constant::String(variant.lexeme.clone()))?;
+
+                self.emit(OpCode::MatchVariant);
+                self.emit_u16(enum_name);
+                self.emit_u16(variant);
+                self.emit_u16(self.count(fields.len())?);
+                context.failures.push(self.emit_jump(OpCode::JumpIfFalse));
+
+                for (i, field) in fields.iter().enumerate() {
+                    self.emit_with_u16(OpCode::GetLocal, temp);
+                    self.emit_with_u16(OpCode::VariantField, i as u16);
+                    self.pattern(field, context)?;
+                }
+            },
+            Pattern::Alternative { alternatives } => {
+                let temp = context.temps.pop().unwrap();
+                self.emit_with_u16(OpCode::SetLocal, temp);
+
+                let mut matched = vec![];
+
+                for alternative in alternatives {
+                    let outer = std::mem::take(&mut context.failures);
+
+                    self.emit_with_u16(OpCode::GetLocal, temp);
+                    self.pattern(alternative, context)?;
+                    matched.push(self.emit_jump(OpCode::Jump));
+
+                    for failure in std::mem::replace(&mut context.failures, outer) {
+                        self.patch_jump(failure)?;
+                    }
+                }
+
+                context.failures.push(self.emit_jump(OpCode::Jump));
+
+                for jump in matched {
+                    self.patch_jump(jump)?;
+                }
+            },
+        }
+
+        return Ok(());
+    }
+
+    fn expression(&mut self, expr: &'a Expr) -> Result<(), RuntimeError> {
+        match expr {
+            Expr::Literal { value } => self.literal(value)?,
+            Expr::Grouping { expression } => self.expression(expression)?,
+            Expr::Unary { operator, right } => {
+                self.expression(right)?;
+                self.line = operator.line_number;
+
+                match operator.token_type {
+                    TokenType::Minus => self.emit(OpCode::Negate),
+                    _ => self.emit(OpCode::Not),
+                }
+            },
+            Expr::Binary { left, operator, right } => {
+                self.expression(left)?;
+                self.expression(right)?;
+                self.line = operator.line_number;
+
+                let op = match operator.token_type {
+                    TokenType::Plus => OpCode::Add,
+                    TokenType::Minus => OpCode::Subtract,
+                    TokenType::Star => OpCode::Multiply,
+                    TokenType::Slash => OpCode::Divide,
+                    TokenType::EqualEqual => OpCode::Equal,
+                    TokenType::BangEqual => OpCode::NotEqual,
+                    TokenType::Greater => OpCode::Greater,
+                    TokenType::GreaterEqual => OpCode::GreaterEqual,
+                    TokenType::Less => OpCode::Less,
+                    TokenType::LessEqual => OpCode::LessEqual,
+                    _ => return Err(self.error(&format!("Unsupported operator '{}'", operator.lexeme))),
+                };
+
+                self.emit(op);
+            },
+            Expr::Variable { name } => {
+                self.line = name.line_number;
+                self.variable(&name.lexeme)?;
+            },
+            Expr::Call { callee, paren, arguments } => self.call(callee, paren, arguments, OpCode::Call)?,
+            Expr::Get { object, name } => {
+                self.expression(object)?;
+                self.line = name.line_number;
+
+                let constant = self.make_constant(Constant::String(name.lexeme.clone()))?;
+                self.emit_with_u16(OpCode::GetProperty, constant);
+            },
+            Expr::List { elements } => {
+                for element in elements {
+                    self.expression(element)?;
+                }
+
+                let count = self.count(elements.len())?;
+                self.emit_with_u16(OpCode::List, count);
+                self.current().height = self.current().height + 1 - elements.len();
+            },
+            Expr::Map { entries } => {
+                for (key, value) in entries {
+                    let constant = self.make_constant(Constant::String(key.clone()))?;
+                    self.emit_with_u16(OpCode::Constant, constant);
+                    self.expression(value)?;
+                }
+
+                let count = self.count(entries.len())?;
+                self.emit_with_u16(OpCode::Map, count);
+                self.current().height = self.current().height + 1 - entries.len() * 2;
+            },
+            Expr::Match { keyword, subject, arms } => self.match_expression(keyword, subject, arms, false)?,
+        }
+
+        return Ok(());
+    }
+
+    // The value of a `return`: a call there, including one a match arm ends in, is a tail call.
+    fn tail_expression(&mut self, expr: &'a Expr) -> Result<(), RuntimeError> {
+        return match expr {
+            Expr::Grouping { expression } => self.tail_expression(expression),
+            Expr::Call { callee, paren, arguments } => self.call(callee, paren, arguments, OpCode::TailCall),
+            Expr::Match { keyword, subject, arms } => self.match_expression(keyword, subject, arms, true),
+            expr => self.expression(expr),
+        }
+    }
+
+    fn call(&mut self, callee: &'a Expr, paren: &Token, arguments: &'a [Expr], op: OpCode) -> Result<(), RuntimeError> {
+        self.expression(callee)?;
+
+        for argument in arguments {
+            self.expression(argument)?;
+        }
+
+        if arguments.len() > u8::MAX as usize {
+            return Err(self.error("Can't have more than 255 arguments"));
+        }
+
+        self.line = paren.line_number;
+        self.emit(op);
+        self.emit_byte(arguments.len() as u8);
+        self.current().height -= arguments.len();
+
+        return Ok(());
+    }
+
+    // Each arm reserves its slots, tests the subject and either falls through to its guard
+    // and body or jumps to the next arm. The result replaces the subject in its slot.
+    fn match_expression(&mut self, keyword: &Token, subject: &'a Expr, arms: &'a [MatchArm], tail: bool) -> Result<(), RuntimeError> {
+        self.expression(subject)?;
+        let subject_slot = self.add_local(None)?;
+        let mut done = vec![];
+
+        for arm in arms {
+            let height = self.current().height;
+            self.begin_scope();
+
+            let (bindings, temps) = self.pattern_slots(&arm.pattern, false)?;
+            let reserved = self.current().height - height;
+
+            self.emit_with_u16(OpCode::GetLocal, subject_slot);
+            let mut context = PatternContext { mode: PatternMode::Match, line: keyword.line_number, bindings: &bindings, temps, failures: vec![] };
+            self.pattern(&arm.pattern, &mut context)?;
+            let mut failures = context.failures;
+
+            self.reveal(&bindings);
+
+            if let Some(guard) = &arm.guard {
+                self.expression(guard)?;
+                failures.push(self.emit_jump(OpCode::JumpIfFalse));
+            }
+
+            match tail {
+                true => self.tail_expression(&arm.body)?,
+                false => self.expression(&arm.body)?,
+            }
+
+            self.emit_with_u16(OpCode::SetLocal, subject_slot);
+            self.end_scope();
+            done.push(self.emit_jump(OpCode::Jump));
+
+            for failure in failures {
+                self.patch_jump(failure)?;
+            }
+
+            self.current().height = height + reserved;
+            for _ in 0..reserved {
+                self.emit(OpCode::Pop);
+            }
+        }
+
+        self.line = keyword.line_number;
+        self.emit_with_u16(OpCode::GetLocal, subject_slot);
+        self.emit(OpCode::NoMatch);
+
+        for jump in done {
+            self.patch_jump(jump)?;
+        }
+
+        // The slot now holds the match's value, an ordinary temporary from here on.
+        self.current().locals.pop();
+
+        return Ok(());
+    }
+
+    fn literal(&mut self, value: &ExpressionLiteralValue) -> Result<(), RuntimeError> {
+        match value {
+            ExpressionLiteralValue::Number(n) => {
+                let constant = self.make_constant(Constant::Number(*n))?;
+                self.emit_with_u16(OpCode::Constant, constant);
+            },
+            ExpressionLiteralValue::StringValue(s) => {
+                let constant = self.make_constant(Constant::String(s.clone()))?;
+                self.emit_with_u16(OpCode::Constant, constant);
+            },
+            ExpressionLiteralValue::True => self.emit(OpCode::True),
+            ExpressionLiteralValue::False => self.emit(OpCode::False),
+            ExpressionLiteralValue::Null => self.emit(OpCode::Null),
+        }
+
+        return Ok(());
+    }
+
+    fn variable(&mut self, name: &Symbol) -> Result<(), RuntimeError> {
+        if let Some(slot) = self.resolve_local(name) {
+            self.emit_with_u16(OpCode::GetLocal, slot);
+            return Ok(());
+        }
+
+        if let Some(index) = self.resolve_upvalue(self.functions.len() - 1, name)? {
+            self.emit_with_u16(OpCode::GetUpvalue, index);
+            return Ok(());
+        }
+
+        let constant = self.make_constant(Constant::String(name.clone()))?;
+        self.emit_with_u16(OpCode::GetGlobal, constant);
+
+        return Ok(());
+    }
+
+    fn resolve_local(&mut self, name: &Symbol) -> Option<u16> {
+        return self.current().locals.iter().rev()
+            .find(|local| local.name.as_ref() == Some(name) && local.defined)
+            .map(|local| local.slot);
+    }
+
+    // Finds a variable of an enclosing function, threading it through the upvalues of every
+    // function in between. Closures run later, so variables declared but not yet defined count.
+    fn resolve_upvalue(&mut self, function: usize, name: &Symbol) -> Result<Option<u16>, RuntimeError> {
+        if function == 0 {
+            return Ok(None);
+        }
+
+        let enclosing = &mut self.functions[function - 1];
+        if let Some(local) = enclosing.locals.iter_mut().rev().find(|local| local.name.as_ref() == Some(name)) {
+            local.captured = true;
+            let source = UpvalueSource { local: true, index: local.slot };
+
+            return self.add_upvalue(function, source).map(Some);
+        }
+
+        return match self.resolve_upvalue(function - 1, name)? {
+            Some(index) => self.add_upvalue(function, UpvalueSource { local: false, index }).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn add_upvalue(&mut self, function: usize, source: UpvalueSource) -> Result<u16, RuntimeError> {
+        let upvalues = &mut self.functions[function].upvalues;
+
+        if let Some(index) = upvalues.iter().position(|upvalue| *upvalue == source) {
+            return Ok(index as u16);
+        }
+
+        if upvalues.len() >= u16::MAX as usize {
+            return Err(self.error("Too many captured variables in function"));
+        }
+
+        upvalues.push(source);
+
+        return Ok((upvalues.len() - 1) as u16);
+    }
+
+    // Gives every name a block declares its slot up front; see `Local::defined`.
+    fn declare(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        if self.current().scope_depth == 0 {
+            return Ok(());
+        }
+
+        for statement in statements {
+            let names = match statement {
+                Stmt::Import { keyword: _, path: _, alias, names } => alias.iter().chain(names).map(|name| name.lexeme.clone()).collect(),
+                statement => statement.declared_names(),
+            };
+
+            for name in names {
+                if self.declared_slot(&name).is_none() {
+                    self.emit(OpCode::Null);
+                    self.add_local(Some(name))?;
+                    self.current().locals.last_mut().unwrap().defined = false;
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    fn declared_slot(&mut self, name: &Symbol) -> Option<u16> {
+        let state = self.current();
+
+        return state.locals.iter().rev()
+            .find(|local| local.name.as_ref() == Some(name) && local.depth == state.scope_depth)
+            .map(|local| local.slot);
+    }
+
+    fn define_local(&mut self, name: &Symbol) {
+        let depth = self.current().scope_depth;
+
+        if let Some(local) = self.current().locals.iter_mut().rev().find(|local| local.name.as_ref() == Some(name) && local.depth == depth) {
+            local.defined = true;
+        }
+    }
+
+    // Binds the value on top of the stack: a global at the top level, a local elsewhere.
+    fn define_variable(&mut self, name: Symbol) -> Result<(), RuntimeError> {
+        if self.current().scope_depth > 0 {
+            match self.declared_slot(&name) {
+                Some(slot) => {
+                    self.emit_with_u16(OpCode::SetLocal, slot);
+                    self.define_local(&name);
+                },
+                None => {
+                    self.add_local(Some(name))?;
+                },
+            }
+
+            return Ok(());
+        }
+
+        let constant = self.make_constant(Constant::String(name))?;
+        self.emit_with_u16(OpCode::DefineGlobal, constant);
+
+        return Ok(());
+    }
+
+    // Names the value on top of the stack as a local.
+    fn add_local(&mut self, name: Option<Symbol>) -> Result<u16, RuntimeError> {
+        let state = self.current();
+
+        if state.height > u16::MAX as usize {
+            return Err(self.error("Too many local variables in function"));
+        }
+
+        let slot = (state.height - 1) as u16;
+        state.locals.push(Local { name, depth: state.scope_depth, slot, defined: true, captured: false });
+
+        return Ok(slot);
+    }
+
+    fn begin_scope(&mut self) {
+        self.current().scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.current().scope_depth -= 1;
+
+        let depth = self.current().scope_depth;
+        while self.current().locals.last().is_some_and(|local| local.depth > depth) {
+            let local = self.current().locals.pop().unwrap();
+
+            match local.captured {
+                true => self.emit(OpCode::CloseUpvalue),
+                false => self.emit(OpCode::Pop),
+            }
+        }
+    }
+
+    fn make_constant(&mut self, constant: Constant) -> Result<u16, RuntimeError> {
+        let index = self.current().chunk.add_constant(constant);
+
+        if index >= NO_TARGET as usize {
+            return Err(self.error("Too many constants in one chunk"));
+        }
+
+        return Ok(index as u16);
+    }
+
+    fn count(&self, count: usize) -> Result<u16, RuntimeError> {
+        if count >= u16::MAX as usize {
+            return Err(self.error("Too many elements"));
+        }
+
+        return Ok(count as u16);
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        let line = self.line;
+        let state = self.current();
+
+        state.chunk.write(op as u8, line);
+        state.height = (state.height as isize + op.stack_effect()) as usize;
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        let line = self.line;
+        self.current().chunk.write(byte, line);
+    }
+
+    fn emit_u16(&mut self, value: u16) {
+        let line = self.line;
+        self.current().chunk.write_u16(value, line);
+    }
+
+    fn emit_with_u16(&mut self, op: OpCode, operand: u16) {
+        self.emit(op);
+        self.emit_u16(operand);
+    }
+
+    fn emit_setup_try(&mut self) -> usize {
+        self.emit(OpCode::SetupTry);
+        let operands = self.current().chunk.code.len();
+        self.emit_u16(NO_TARGET);
+        self.emit_u16(NO_TARGET);
+
+        return operands;
+    }
+
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.emit_with_u16(op, NO_TARGET);
+        return self.current().chunk.code.len() - 2;
+    }
+
+    fn patch_jump(&mut self, operand: usize) -> Result<(), RuntimeError> {
+        let distance = self.current().chunk.code.len() - operand - 2;
+
+        if distance >= NO_TARGET as usize {
+            return Err(self.error("Too much code to jump over"));
+        }
+
+        self.current().chunk.patch_u16(operand, distance as u16);
+        return Ok(());
+    }
+
+    fn current_offset(&mut self) -> Result<u16, RuntimeError> {
+        let offset = self.current().chunk.code.len();
+
+        if offset >= NO_TARGET as usize {
+            return Err(self.error("Too much code in one function"));
+        }
+
+        return Ok(offset as u16);
+    }
+
+    fn error(&self, message: &str) -> RuntimeError {
+        return RuntimeError::new(ErrorKind::CompileError, message.to_string(), self.line);
+    }
+}
+
+// Patterns that need a slot to hold the value they take apart.
+fn composite_patterns(pattern: &Pattern) -> usize {
+    return match pattern {
+        Pattern::Wildcard | Pattern::Literal { .. } | Pattern::Binding { .. } => 0,
+        Pattern::Alternative { alternatives } => 1 + alternatives.iter().map(composite_patterns).sum::<usize>(),
+        Pattern::List { elements, rest: _ } => 1 + elements.iter().map(composite_patterns).sum::<usize>(),
+        Pattern::Map { entries } => 1 + entries.iter().map(|entry| composite_patterns(&entry.pattern)).sum::<usize>(),
+        Pattern::Variant { enum_name: _, variant: _, fields } => 1 + fields.iter().map(composite_patterns).sum::<usize>(),
+    }
+}
@@ -0,0 +1,102 @@
+use std::rc::Rc;
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::gc;
+use crate::value::Value;
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        return Value::Number(value);
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        return Value::Number(value as f64);
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        return Value::Boolean(value);
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        return Value::from(value.to_string());
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        gc::charge(value.len());
+        return Value::StringValue(value.into());
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Self {
+        return Value::list(values.into_iter().map(|v| v.into()).collect());
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        return match value {
+            Some(value) => value.into(),
+            None => Value::Null,
+        }
+    }
+}
+
+fn expected(expected: &str, value: &Value) -> RuntimeError {
+    return RuntimeError::new(
+        ErrorKind::TypeError,
+        format!("Expected {}, got {}", expected, value.type_name()),
+        0,
+    );
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        return match value {
+            Value::Number(n) => Ok(n),
+            value => Err(expected("number", &value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        return match value {
+            Value::Boolean(b) => Ok(b),
+            value => Err(expected("boolean", &value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        return match value {
+            Value::StringValue(s) => Ok(Rc::unwrap_or_clone(s)),
+            value => Err(expected("string", &value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for Vec<Value> {
+    type Error = RuntimeError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        return match value {
+            Value::List(elements) => Ok(elements.borrow().clone()),
+            value => Err(expected("list", &value)),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::chunk::{Chunk, Constant, FunctionProto, OpCode, NO_TARGET};
+
+// Lists every instruction of a function, then of the functions defined inside it.
+pub fn disassemble(function: &FunctionProto) -> String {
+    let chunk = &function.chunk;
+    let mut output = format!("== {} ==\n", function.name);
+    let mut offset = 0;
+
+    while offset < chunk.code.len() {
+        let (line, next) = disassemble_instruction(chunk, offset);
+        output.push_str(&line);
+        output.push('\n');
+        offset = next;
+    }
+
+    for constant in &chunk.constants {
+        if let Constant::Function(function) = constant {
+            output.push('\n');
+            output.push_str(&disassemble(function));
+        }
+    }
+
+    return output;
+}
+
+// Formats the instruction at `offset`, returning it with the offset of the next one.
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> (String, usize) {
+    let line = match offset > 0 && chunk.lines[offset] == chunk.lines[offset - 1] {
+        true => "   |".to_string(),
+        false => format!("{:4}", chunk.lines[offset]),
+    };
+
+    let op = match OpCode::from_byte(chunk.code[offset]) {
+        Some(op) => op,
+        None => return (format!("{:04} {} <unknown opcode {}>", offset, line, chunk.code[offset]), offset + 1),
+    };
+
+    let operand = offset + 1;
+    let next = operand + op.operand_bytes();
+
+    let operands = match op {
+        OpCode::Closure => {
+            let index = chunk.read_u16(operand);
+            let upvalues: Vec<String> = match &chunk.constants[index as usize] {
+                Constant::Function(function) => function.upvalues.iter()
+                    .map(|upvalue| format!("{} {}", if upvalue.local { "local" } else { "upvalue" }, upvalue.index))
+                    .collect(),
+                _ => vec![],
+            };
+
+            match upvalues.is_empty() {
+                true => constant(chunk, index),
+                false => format!("{} [{}]", constant(chunk, index), upvalues.join(", ")),
+            }
+        },
+        OpCode::Constant | OpCode::GetGlobal | OpCode::DefineGlobal | OpCode::GetProperty
+        | OpCode::Enum | OpCode::Import | OpCode::ImportName | OpCode::MapHas | OpCode::MapGet | OpCode::MissingKey => {
+            constant(chunk, chunk.read_u16(operand))
+        },
+        OpCode::GetLocal | OpCode::SetLocal | OpCode::GetUpvalue | OpCode::List | OpCode::Map | OpCode::Index | OpCode::SliceFrom
+        | OpCode::VariantField => format!("{:4}", chunk.read_u16(operand)),
+        OpCode::Jump | OpCode::JumpIfFalse => {
+            let distance = chunk.read_u16(operand) as usize;
+            format!("{:4} -> {:04}", distance, next + distance)
+        },
+        OpCode::Call | OpCode::TailCall => format!("{:4}", chunk.code[operand]),
+        OpCode::SetupTry => format!("catch {} finally {}", target(chunk.read_u16(operand)), target(chunk.read_u16(operand + 2))),
+        OpCode::MatchList | OpCode::DestructureList => match chunk.code[operand + 2] {
+            0 => format!("{:4}", chunk.read_u16(operand)),
+            _ => format!("{:4} ...rest", chunk.read_u16(operand)),
+        },
+        OpCode::MatchVariant => {
+            let variant = constant(chunk, chunk.read_u16(operand + 2));
+
+            match chunk.read_u16(operand) {
+                NO_TARGET => format!("{} {}", variant, chunk.read_u16(operand + 4)),
+                name => format!("{} {} {}", constant(chunk, name), variant, chunk.read_u16(operand + 4)),
+            }
+        },
+        _ => String::new(),
+    };
+
+    let text = format!("{:04} {} {:<16} {}", offset, line, format!("{:?}", op), operands);
+
+    return (text.trim_end().to_string(), next);
+}
+
+fn constant(chunk: &Chunk, index: u16) -> String {
+    let value = match &chunk.constants[index as usize] {
+        Constant::Number(n) => n.to_string(),
+        Constant::String(s) => format!("{:?}", s),
+        Constant::Function(function) => format!("<fn {}>", function.name),
+        Constant::Enum { name, variants: _ } => format!("<enum {}>", name),
+    };
+
+    return format!("{:4} {}", index, value);
+}
+
+fn target(offset: u16) -> String {
+    return match offset {
+        NO_TARGET => "-".to_string(),
+        offset => format!("{:04}", offset),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::compile;
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use super::*;
+
+    #[test]
+    fn test_disassemble_script_and_functions() {
+        let source = "fn add(a, b) {\n  return a + b;\n}\nprint add(1, 2);";
+        let statements = Parser::new(Scanner::new(source).scan_tokens().unwrap()).parse_statements().unwrap();
+        let function = compile(&statements, "test.pltps").unwrap();
+
+        assert_eq!(disassemble(&function), r#"== <script> ==
+0000    1 Closure             0 <fn add>
+0003    | DefineGlobal        1 "add"
+0006    4 GetGlobal           1 "add"
+0009    | Constant            2 1
+0012    | Constant            3 2
+0015    | Call                2
+0017    | Print
+0018    | Null
+0019    | Return
+
+== add ==
+0000    2 GetLocal            1
+0003    | GetLocal            2
+0006    | Add
+0007    | Return
+0008    | Null
+0009    | Return
+"#);
+    }
+}
@@ -0,0 +1,280 @@
+use std::fs;
+use std::path::PathBuf;
+use crate::bytecode;
+use crate::capabilities::Capabilities;
+use crate::disassembler::disassemble;
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::interpreter::{Backend, Interpreter, OptLevel};
+use crate::limits::{CancelHandle, Limits};
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::stmt::Stmt;
+use crate::value::{Arity, Value};
+
+#[derive(Debug)]
+pub enum EngineError {
+    Io(String),
+    Syntax(String),
+    // A compiled script that is malformed or from another version.
+    Bytecode(String),
+    Runtime(RuntimeError),
+    // The script called `process.exit` with this status.
+    Exit(i32),
+}
+
+impl EngineError {
+    pub fn to_string(&self) -> String {
+        return match self {
+            EngineError::Io(message) | EngineError::Syntax(message) | EngineError::Bytecode(message) => message.clone(),
+            EngineError::Runtime(error) => error.report(),
+            EngineError::Exit(code) => format!("Exited with status {}", code),
+        }
+    }
+}
+
+impl From<RuntimeError> for EngineError {
+    fn from(error: RuntimeError) -> Self {
+        if let Some(code) = error.exit_code {
+            return EngineError::Exit(code);
+        }
+
+        return EngineError::Runtime(error);
+    }
+}
+
+pub struct Engine {
+    interpreter: Interpreter,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self {
+            interpreter: Interpreter::new(),
+        }
+    }
+
+    pub fn with_capabilities(capabilities: Capabilities) -> Self {
+        Self {
+            interpreter: Interpreter::with_capabilities(capabilities),
+        }
+    }
+
+    pub fn eval(&mut self, source: &str) -> Result<Value, EngineError> {
+        let statements = self.parse(source)?;
+
+        return Ok(self.interpreter.interpret(&statements)?);
+    }
+
+    // Compiles the source for the VM and lists its bytecode, without running it.
+    pub fn disassemble(&mut self, source: &str) -> Result<String, EngineError> {
+        let statements = self.parse(source)?;
+        let function = self.interpreter.compile(&statements)?;
+
+        return Ok(disassemble(&function));
+    }
+
+    fn parse(&mut self, source: &str) -> Result<Vec<Stmt>, EngineError> {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().map_err(EngineError::Syntax)?;
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse_statements().map_err(EngineError::Syntax)?;
+
+        for warning in parser.warnings {
+            self.interpreter.add_warning(warning);
+        }
+
+        return Ok(self.interpreter.optimize(statements));
+    }
+
+    // Runs a script, or a compiled script when the file has the `.pltc` extension.
+    pub fn run_file(&mut self, path: &str) -> Result<Value, EngineError> {
+        if path.ends_with(".pltc") {
+            let bytes = fs::read(path).map_err(|e| EngineError::Io(format!("{}: {}", path, e)))?;
+            let function = bytecode::deserialize(&bytes).map_err(|message| EngineError::Bytecode(format!("{}: {}", path, message)))?;
+
+            self.interpreter.set_file(path);
+
+            return Ok(self.interpreter.run_compiled(function)?);
+        }
+
+        let source = fs::read_to_string(path).map_err(|e| EngineError::Io(format!("{}: {}", path, e)))?;
+
+        self.interpreter.set_file(path);
+
+        return self.eval(&source);
+    }
+
+    // Compiles a script into the contents of a `.pltc` file.
+    pub fn compile_file(&mut self, path: &str) -> Result<Vec<u8>, EngineError> {
+        let source = fs::read_to_string(path).map_err(|e| EngineError::Io(format!("{}: {}", path, e)))?;
+
+        self.interpreter.set_file(path);
+
+        let statements = self.parse(&source)?;
+        let function = self.interpreter.compile(&statements)?;
+
+        return Ok(bytecode::serialize(&function));
+    }
+
+    pub fn call(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, EngineError> {
+        let function = match self.interpreter.get_global(name) {
+            Some(function) => function,
+            None => return Err(EngineError::Runtime(RuntimeError::new(
+                ErrorKind::UndefinedVariable,
+                format!("Undefined function '{}'", name),
+                0,
+            ))),
+        };
+
+        return Ok(self.interpreter.call_value(function, arguments)?);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        return self.interpreter.get_global(name);
+    }
+
+    pub fn set_global<T: Into<Value>>(&mut self, name: &str, value: T) {
+        self.interpreter.set_global(name, value.into());
+    }
+
+    pub fn register_native<F>(&mut self, name: &str, arity: Arity, function: F)
+    where
+        F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    {
+        self.interpreter.register_native(name, arity, function);
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.interpreter.set_backend(backend);
+    }
+
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.interpreter.set_opt_level(opt_level);
+    }
+
+    // Prints the VM stack before every instruction; only the VM backend traces.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.interpreter.set_trace(trace);
+    }
+
+    // The number of heap objects that starts a garbage collection; zero collects only when
+    // the script calls `gc.collect()`.
+    pub fn set_gc_threshold(&mut self, threshold: usize) {
+        self.interpreter.set_gc_threshold(threshold);
+    }
+
+    // Calls nested deeper than this raise a catchable `StackOverflow` error. Tail calls don't
+    // nest, so tail recursion isn't limited.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.interpreter.set_max_depth(max_depth);
+    }
+
+    // Limits for each later run: `eval`, `run_file` or `call`.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.interpreter.set_limits(limits);
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        return self.interpreter.cancel_handle();
+    }
+
+    // Arguments the script sees as `process.args`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.interpreter.set_args(args);
+    }
+
+    pub fn add_search_path(&mut self, path: PathBuf) {
+        self.interpreter.add_search_path(path);
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        return self.interpreter.take_warnings();
+    }
+
+    // Hands warnings to `handler` as soon as parsing or optimizing finds them, before the code
+    // they're about runs, instead of keeping them for `take_warnings`.
+    pub fn set_warning_handler<F: FnMut(&str) + 'static>(&mut self, handler: F) {
+        self.interpreter.set_warning_handler(Box::new(handler));
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::test_dir::TestDir;
+    use super::*;
+
+    #[test]
+    fn test_eval_returns_final_expression() {
+        let mut engine = Engine::new();
+
+        assert_eq!(engine.eval("let a = 2; a * 21;").unwrap(), Value::Number(42.0));
+        assert_eq!(engine.eval("a;").unwrap(), Value::Number(2.0));
+        assert!(matches!(engine.eval("let = 1;"), Err(EngineError::Syntax(_))));
+        assert_eq!(engine.eval("a + 1; // no newline after this").unwrap(), Value::Number(3.0));
+        assert!(matches!(engine.eval("a; /* never closed"), Err(EngineError::Syntax(_))));
+    }
+
+    #[test]
+    fn test_call_script_function_with_host_globals() {
+        let mut engine = Engine::new();
+        engine.set_global("greeting", "hello");
+        engine.eval(r#"fn greet(name) { return greeting + ", " + name; }"#).unwrap();
+
+        let result: String = engine.call("greet", vec!["platypus".into()]).unwrap().try_into().unwrap();
+
+        assert_eq!(result, "hello, platypus");
+        assert!(engine.call("missing", vec![]).is_err());
+    }
+
+    #[test]
+    fn test_compiled_files_run_like_their_source() {
+        let directory = TestDir::new("pltc");
+
+        let source = directory.join("script.pltps");
+        let compiled = directory.join("script.pltc");
+        fs::write(&source, "fn twice(x) { return [x, x]; }\nlet result = twice(21);\nresult;").unwrap();
+
+        let bytes = Engine::new().compile_file(source.to_str().unwrap()).unwrap();
+        fs::write(&compiled, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(matches!(Engine::new().run_file(compiled.to_str().unwrap()), Err(EngineError::Bytecode(_))));
+
+        fs::write(&compiled, bytes).unwrap();
+        let mut engine = Engine::new();
+        assert_eq!(engine.run_file(compiled.to_str().unwrap()).unwrap().to_string(), "[21, 21]");
+        assert_eq!(engine.get_global("result").unwrap().to_string(), "[21, 21]");
+    }
+
+    #[test]
+    fn test_exit_is_reported_as_status() {
+        let mut engine = Engine::new();
+
+        assert!(matches!(engine.eval("process.exit(2);"), Err(EngineError::Exit(2))));
+    }
+
+    #[test]
+    fn test_warnings_reach_the_handler_before_the_script_runs() {
+        let events = Rc::new(RefCell::new(vec![]));
+        let mut engine = Engine::new();
+
+        let log = events.clone();
+        engine.set_warning_handler(move |warning| log.borrow_mut().push(warning.to_string()));
+        let log = events.clone();
+        engine.register_native("ran", Arity::Fixed(0), move |_, _| {
+            log.borrow_mut().push("ran".to_string());
+            Ok(Value::Null)
+        });
+
+        engine.eval("ran(); match true { true => 1 };").unwrap();
+
+        assert_eq!(*events.borrow(), vec!["Warning at line 1: non-exhaustive match, missing false", "ran"]);
+        assert!(engine.take_warnings().is_empty());
+    }
+}
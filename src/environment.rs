@@ -0,0 +1,68 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::value::Value;
+
+pub struct Environment {
+    values: HashMap<Symbol, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self {
+            values: HashMap::new(),
+            enclosing: None,
+        }
+    }
+
+    pub fn new_enclosed(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    pub fn define(&mut self, name: Symbol, value: Value) {
+        self.values.insert(name, value);
+    }
+
+    pub fn lookup(&self, name: &Symbol) -> Option<Value> {
+        if let Some(value) = self.values.get(name) {
+            return Some(value.clone());
+        }
+
+        return match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().lookup(name),
+            None => None,
+        }
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Value> {
+        return self.values.values();
+    }
+
+    pub(crate) fn enclosing(&self) -> Option<&Rc<RefCell<Environment>>> {
+        return self.enclosing.as_ref();
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Value, RuntimeError> {
+        return match self.lookup(&name.lexeme) {
+            Some(value) => Ok(value),
+            None => Err(RuntimeError::new(
+                ErrorKind::UndefinedVariable,
+                format!("Undefined variable '{}'", name.lexeme),
+                name.line_number,
+            )),
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
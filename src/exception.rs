@@ -1,3 +1,6 @@
+use std::collections::BTreeMap;
+use crate::value::Value;
+
 pub struct Exception {
 
 }
@@ -6,4 +9,157 @@ impl Exception {
     pub fn throw(message: String, line: usize) -> Result<(), String> {
         return Err(format!("Error at line {}: {}", line, message))
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    Error,
+    TypeError,
+    DivisionByZero,
+    UndefinedVariable,
+    UndefinedProperty,
+    MatchError,
+    DestructureError,
+    ImportError,
+    IndexError,
+    ValueError,
+    IoError,
+    // Calls nested deeper than the interpreter's maximum depth.
+    StackOverflow,
+    // A builtin the engine's capabilities leave out was used.
+    CapabilityDenied,
+    // The bytecode compiler couldn't translate the program.
+    CompileError,
+    // A loaded bytecode file did something the loader's checks can't rule out ahead of time,
+    // such as indexing past the end of a list no pattern checked the length of.
+    InvalidBytecode,
+    // Raised by `process.exit`. Passes through `catch` so the script really stops.
+    Exit,
+    // The embedder's limits were reached, or it cancelled the script. These end the script
+    // without running its catch or finally blocks.
+    StepLimitExceeded,
+    Timeout,
+    HeapLimitExceeded,
+    CallDepthExceeded,
+    Cancelled,
+}
+
+impl ErrorKind {
+    pub fn is_catchable(&self) -> bool {
+        return *self != ErrorKind::Exit && !self.is_termination();
+    }
+
+    pub fn is_termination(&self) -> bool {
+        return matches!(
+            self,
+            ErrorKind::StepLimitExceeded | ErrorKind::Timeout | ErrorKind::HeapLimitExceeded | ErrorKind::CallDepthExceeded | ErrorKind::Cancelled,
+        );
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// How many times in a row a traceback shows the same frame before summarizing the rest.
+const REPEATED_FRAMES: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct TraceFrame {
+    pub function: String,
+    pub file: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub line: usize,
+    // The value given to `throw`, handed back unchanged to the `catch` that handles it.
+    pub thrown: Option<Value>,
+    // Call frames active when the error was raised, outermost first.
+    pub trace: Vec<TraceFrame>,
+    // Set for `Exit` errors, the status the process should exit with.
+    pub exit_code: Option<i32>,
+}
+
+impl RuntimeError {
+    pub fn new(kind: ErrorKind, message: String, line: usize) -> Self {
+        Self {
+            kind,
+            message,
+            line,
+            thrown: None,
+            trace: vec![],
+            exit_code: None,
+        }
+    }
+
+    pub fn thrown(value: Value, line: usize) -> Self {
+        Self {
+            kind: ErrorKind::Error,
+            message: value.to_string(),
+            line,
+            thrown: Some(value),
+            trace: vec![],
+            exit_code: None,
+        }
+    }
+
+    pub fn exit(code: i32) -> Self {
+        Self {
+            exit_code: Some(code),
+            ..Self::new(ErrorKind::Exit, format!("Exited with status {}", code), 0)
+        }
+    }
+
+    // The value a script sees when it catches this error.
+    pub fn to_value(&self) -> Value {
+        if let Some(value) = &self.thrown {
+            return value.clone();
+        }
+
+        return Value::map(BTreeMap::from([
+            ("kind".to_string(), Value::from(self.kind.to_string())),
+            ("message".to_string(), Value::from(self.message.as_str())),
+            ("line".to_string(), Value::Number(self.line as f64)),
+        ]));
+    }
+
+    pub fn to_string(&self) -> String {
+        return format!("{} at line {}: {}", self.kind, self.line, self.message);
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = String::from("Traceback (most recent call last):\n");
+        let lines: Vec<String> = self.trace.iter()
+            .map(|frame| format!("  File \"{}\", line {}, in {}\n", frame.file, frame.line, frame.function))
+            .collect();
+
+        // Deep recursion repeats the same frame, so runs of it are shortened after a few.
+        let mut repeated = 0;
+
+        for (i, line) in lines.iter().enumerate() {
+            let last = i + 1 == lines.len() || lines[i + 1] != *line;
+            repeated = match i > 0 && lines[i - 1] == *line {
+                true => repeated + 1,
+                false => 0,
+            };
+
+            if repeated < REPEATED_FRAMES {
+                report.push_str(line);
+            }
+
+            if last && repeated >= REPEATED_FRAMES {
+                report.push_str(&format!("  [Previous line repeated {} more times]\n", repeated + 1 - REPEATED_FRAMES));
+            }
+        }
+
+        report.push_str(&format!("{}: {}", self.kind, self.message));
+
+        return report;
+    }
+}
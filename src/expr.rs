@@ -1,10 +1,12 @@
 use crate::literal_value::LiteralValue;
+use crate::pattern::Pattern;
+use crate::symbol::Symbol;
 use crate::token::Token;
 use crate::token_type::TokenType;
 
 pub enum ExpressionLiteralValue {
-    Number(f32),
-    StringValue(String),
+    Number(f64),
+    StringValue(Symbol),
     True,
     False,
     Null,
@@ -14,7 +16,7 @@ impl ExpressionLiteralValue {
     pub fn to_string(&self) -> String {
         return match self {
             ExpressionLiteralValue::Number(n) => n.to_string(),
-            ExpressionLiteralValue::StringValue(s) => s.clone(),
+            ExpressionLiteralValue::StringValue(s) => s.to_string(),
             ExpressionLiteralValue::True => String::from("true"),
             ExpressionLiteralValue::False => String::from("false"),
             ExpressionLiteralValue::Null => String::from("null"),
@@ -23,7 +25,7 @@ impl ExpressionLiteralValue {
 
     pub fn from_token(token: Token) -> ExpressionLiteralValue {
         return match token.token_type {
-            TokenType::Number => Self::Number(unwrap_as_f32(token.literal)),
+            TokenType::Number => Self::Number(unwrap_as_f64(token.literal)),
             TokenType::String => Self::StringValue(unwrap_as_string(token.literal)),
             TokenType::False => Self::False,
             TokenType::True => Self::True,
@@ -33,18 +35,33 @@ impl ExpressionLiteralValue {
     }
 }
 
-fn unwrap_as_string(literal: Option<LiteralValue>) -> String {
+fn unwrap_as_string(literal: Option<LiteralValue>) -> Symbol {
     match literal.unwrap() {
-        LiteralValue::StringValue(s) | LiteralValue::IdentifierValue(s) => s.clone(),
+        LiteralValue::StringValue(s) | LiteralValue::IdentifierValue(s) => s,
         _ => panic!("Could not unwrap as string"),
     }
 }
 
-fn unwrap_as_f32(literal: Option<LiteralValue>) -> f32 {
+fn unwrap_as_f64(literal: Option<LiteralValue>) -> f64 {
     match literal.unwrap() {
-        LiteralValue::FloatValue(x) => x as f32,
-        LiteralValue::IntegerValue(x) => x as f32,
-        _ => panic!("Could not unwrap as f32"),
+        LiteralValue::FloatValue(x) => x,
+        LiteralValue::IntegerValue(x) => x as f64,
+        _ => panic!("Could not unwrap as f64"),
+    }
+}
+
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+}
+
+impl MatchArm {
+    pub fn to_string(&self) -> String {
+        return match &self.guard {
+            Some(guard) => format!("({} if {} => {})", self.pattern.to_string(), guard.to_string(), self.body.to_string()),
+            None => format!("({} => {})", self.pattern.to_string(), self.body.to_string()),
+        }
     }
 }
 
@@ -67,6 +84,35 @@ pub enum Expr {
         operator: Token,
         right: Box<Expr>,
     },
+
+    Variable {
+        name: Token,
+    },
+
+    Call {
+        callee: Box<Expr>,
+        paren: Token,
+        arguments: Vec<Expr>,
+    },
+
+    Get {
+        object: Box<Expr>,
+        name: Token,
+    },
+
+    List {
+        elements: Vec<Expr>,
+    },
+
+    Map {
+        entries: Vec<(Symbol, Expr)>,
+    },
+
+    Match {
+        keyword: Token,
+        subject: Box<Expr>,
+        arms: Vec<MatchArm>,
+    },
 }
 
 impl Expr {
@@ -79,11 +125,33 @@ impl Expr {
                 format!("(group {})", expression.to_string())
             },
             Expr::Literal { value } => {
-                format!("{}", value.to_string())
+                value.to_string()
             },
             Expr::Unary { operator, right } => {
                 format!("({} {})", operator.lexeme, (*right).to_string())
-            }
+            },
+            Expr::Variable { name } => {
+                name.lexeme.to_string()
+            },
+            Expr::Call { callee, paren: _, arguments } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                format!("(call {} {})", callee.to_string(), arguments.join(" "))
+            },
+            Expr::Get { object, name } => {
+                format!("(. {} {})", object.to_string(), name.lexeme)
+            },
+            Expr::List { elements } => {
+                let elements: Vec<String> = elements.iter().map(|e| e.to_string()).collect();
+                format!("[{}]", elements.join(" "))
+            },
+            Expr::Map { entries } => {
+                let entries: Vec<String> = entries.iter().map(|(key, value)| format!("{}: {}", key, value.to_string())).collect();
+                format!("{{{}}}", entries.join(" "))
+            },
+            Expr::Match { keyword: _, subject, arms } => {
+                let arms: Vec<String> = arms.iter().map(|arm| arm.to_string()).collect();
+                format!("(match {} {})", subject.to_string(), arms.join(" "))
+            },
         }
     }
 
@@ -103,7 +171,7 @@ mod tests {
     fn pretty_print_ast() {
         let minus = Token {
             token_type: TokenType::Minus,
-            lexeme: "-".to_string(),
+            lexeme: Symbol::intern("-"),
             literal: None,
             line_number: 0
         };
@@ -112,7 +180,7 @@ mod tests {
         });
         let multiplication = Token {
             token_type: TokenType::Star,
-            lexeme: "*".to_string(),
+            lexeme: Symbol::intern("*"),
             literal: None,
             line_number: 0
         };
@@ -0,0 +1,446 @@
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::{Rc, Weak};
+use crate::environment::Environment;
+use crate::value::{Upvalue, Value};
+
+// Values are reference counted, which frees everything except cycles: a list that contains
+// itself, or a function stored in the scope it closes over. The collector finds those.
+//
+// Every object that can be part of a cycle (lists, maps, scopes and upvalues, the only
+// values that can be changed after they are created) is registered here when it's
+// allocated. A collection:
+//
+//  1. counts, for every object reachable from a registered one, the references to it that
+//     come from inside the heap;
+//  2. treats objects with more references than that as roots. Those references come from
+//     outside the heap: the interpreter's globals and module scopes, the VM stack, open
+//     upvalues and natives holding values while they run;
+//  3. marks everything reachable from the roots, and empties the registered objects it
+//     didn't reach, which breaks their cycles so reference counting frees them.
+//
+// The heap is per thread and shared by every interpreter running on it.
+
+pub const DEFAULT_THRESHOLD: usize = 10_000;
+
+enum Object {
+    List(Weak<RefCell<Vec<Value>>>),
+    Map(Weak<RefCell<BTreeMap<String, Value>>>),
+    Environment(Weak<RefCell<Environment>>),
+    Upvalue(Weak<RefCell<Upvalue>>),
+}
+
+impl Object {
+    fn upgrade(&self) -> Option<Node> {
+        return match self {
+            Object::List(list) => list.upgrade().map(Node::List),
+            Object::Map(map) => map.upgrade().map(Node::Map),
+            Object::Environment(environment) => environment.upgrade().map(Node::Environment),
+            Object::Upvalue(upvalue) => upvalue.upgrade().map(Node::Upvalue),
+        }
+    }
+
+    fn is_live(&self) -> bool {
+        return match self {
+            Object::List(list) => list.strong_count() > 0,
+            Object::Map(map) => map.strong_count() > 0,
+            Object::Environment(environment) => environment.strong_count() > 0,
+            Object::Upvalue(upvalue) => upvalue.strong_count() > 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub collections: usize,
+    // Objects emptied by collections so far.
+    pub freed: usize,
+    // Registered objects that are still alive.
+    pub live: usize,
+    pub threshold: usize,
+    // The heap size, counting objects not yet found dead, that starts the next collection.
+    pub next_collection: usize,
+}
+
+struct Heap {
+    objects: Vec<Object>,
+    threshold: usize,
+    next_collection: usize,
+    collections: usize,
+    freed: usize,
+    collecting: bool,
+}
+
+thread_local! {
+    static HEAP: RefCell<Heap> = const { RefCell::new(Heap {
+        objects: vec![],
+        threshold: DEFAULT_THRESHOLD,
+        next_collection: DEFAULT_THRESHOLD,
+        collections: 0,
+        freed: 0,
+        collecting: false,
+    }) };
+
+    static BYTES: Cell<usize> = const { Cell::new(0) };
+}
+
+pub fn list(elements: Vec<Value>) -> Rc<RefCell<Vec<Value>>> {
+    charge(elements.len() * size_of::<Value>());
+    let list = Rc::new(RefCell::new(elements));
+    register(Object::List(Rc::downgrade(&list)));
+
+    return list;
+}
+
+pub fn map(entries: BTreeMap<String, Value>) -> Rc<RefCell<BTreeMap<String, Value>>> {
+    charge(entries.keys().map(|key| key.len() + size_of::<Value>()).sum());
+    let map = Rc::new(RefCell::new(entries));
+    register(Object::Map(Rc::downgrade(&map)));
+
+    return map;
+}
+
+pub fn environment(environment: Environment) -> Rc<RefCell<Environment>> {
+    let environment = Rc::new(RefCell::new(environment));
+    register(Object::Environment(Rc::downgrade(&environment)));
+
+    return environment;
+}
+
+pub fn upvalue(upvalue: Upvalue) -> Rc<RefCell<Upvalue>> {
+    let upvalue = Rc::new(RefCell::new(upvalue));
+    register(Object::Upvalue(Rc::downgrade(&upvalue)));
+
+    return upvalue;
+}
+
+// Collects once the heap reaches the threshold. Every object the collection keeps counts
+// towards the next one, which starts when the heap has doubled, or at the threshold again.
+fn register(object: Object) {
+    let due = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.objects.push(object);
+
+        heap.objects.len() >= heap.next_collection && !heap.collecting
+    });
+
+    if due {
+        collect();
+    }
+}
+
+// Sets the heap size that starts a collection, as a number of objects. Zero turns automatic
+// collection off; `collect` still works.
+pub fn set_threshold(threshold: usize) {
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.threshold = threshold;
+        heap.next_collection = match threshold {
+            0 => usize::MAX,
+            threshold => threshold.max(heap.objects.len() + 1),
+        };
+    });
+}
+
+// Counts bytes allocated for strings and for the elements of lists and maps. Nothing is
+// taken off when they're freed, so two readings differ by what was allocated in between.
+pub fn charge(bytes: usize) {
+    BYTES.with(|total| total.set(total.get().wrapping_add(bytes)));
+}
+
+pub fn allocated_bytes() -> usize {
+    return BYTES.with(|total| total.get());
+}
+
+// Registered objects, including dead ones a collection hasn't cleared yet.
+pub fn allocated() -> usize {
+    return HEAP.with(|heap| heap.borrow().objects.len());
+}
+
+pub fn stats() -> Stats {
+    return HEAP.with(|heap| {
+        let heap = heap.borrow();
+
+        Stats {
+            collections: heap.collections,
+            freed: heap.freed,
+            live: heap.objects.iter().filter(|object| object.is_live()).count(),
+            threshold: heap.threshold,
+            next_collection: heap.next_collection,
+        }
+    });
+}
+
+// Runs a full collection and returns the number of objects it freed.
+pub fn collect() -> usize {
+    let objects = HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        heap.collecting = true;
+
+        std::mem::take(&mut heap.objects)
+    });
+
+    let mut graph = Graph::default();
+    let mut registered = vec![];
+
+    for object in objects {
+        if let Some(node) = object.upgrade() {
+            graph.add(node);
+            registered.push(object);
+        }
+    }
+
+    graph.count_references();
+
+    let reachable = graph.mark();
+    let freed = (0..graph.nodes.len())
+        .filter(|index| !reachable[*index] && graph.nodes[*index].empty())
+        .count();
+
+    // The garbage only has the graph's own references left.
+    drop(graph);
+    registered.retain(|object| object.is_live());
+
+    HEAP.with(|heap| {
+        let mut heap = heap.borrow_mut();
+        let live = registered.len();
+
+        // Objects allocated while collecting, by natives dropping values, go after the survivors.
+        registered.append(&mut heap.objects);
+        heap.objects = registered;
+        heap.collections += 1;
+        heap.freed += freed;
+        heap.collecting = false;
+        heap.next_collection = match heap.threshold {
+            0 => usize::MAX,
+            threshold => threshold.max(live * 2),
+        };
+    });
+
+    return freed;
+}
+
+// Anything in the heap that holds other values. Only registered objects are ever emptied;
+// the rest are followed so references made through them are counted.
+enum Node {
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<BTreeMap<String, Value>>>),
+    Environment(Rc<RefCell<Environment>>),
+    Upvalue(Rc<RefCell<Upvalue>>),
+    Other(Value),
+}
+
+impl Node {
+    fn from_value(value: &Value) -> Option<Node> {
+        return match value {
+            Value::List(list) => Some(Node::List(list.clone())),
+            Value::Map(map) => Some(Node::Map(map.clone())),
+            Value::EnumValue(_) | Value::Function(_) | Value::Closure(_) | Value::BoundMethod(_) | Value::Module(_) => {
+                Some(Node::Other(value.clone()))
+            },
+            _ => None,
+        }
+    }
+
+    fn address(&self) -> usize {
+        return match self {
+            Node::List(list) => Rc::as_ptr(list) as *const () as usize,
+            Node::Map(map) => Rc::as_ptr(map) as *const () as usize,
+            Node::Environment(environment) => Rc::as_ptr(environment) as *const () as usize,
+            Node::Upvalue(upvalue) => Rc::as_ptr(upvalue) as *const () as usize,
+            Node::Other(Value::EnumValue(value)) => Rc::as_ptr(value) as *const () as usize,
+            Node::Other(Value::Function(function)) => Rc::as_ptr(function) as *const () as usize,
+            Node::Other(Value::Closure(closure)) => Rc::as_ptr(closure) as *const () as usize,
+            Node::Other(Value::BoundMethod(method)) => Rc::as_ptr(method) as *const () as usize,
+            Node::Other(Value::Module(module)) => Rc::as_ptr(module) as *const () as usize,
+            Node::Other(_) => unreachable!(),
+        }
+    }
+
+    fn strong_count(&self) -> usize {
+        return match self {
+            Node::List(list) => Rc::strong_count(list),
+            Node::Map(map) => Rc::strong_count(map),
+            Node::Environment(environment) => Rc::strong_count(environment),
+            Node::Upvalue(upvalue) => Rc::strong_count(upvalue),
+            Node::Other(Value::EnumValue(value)) => Rc::strong_count(value),
+            Node::Other(Value::Function(function)) => Rc::strong_count(function),
+            Node::Other(Value::Closure(closure)) => Rc::strong_count(closure),
+            Node::Other(Value::BoundMethod(method)) => Rc::strong_count(method),
+            Node::Other(Value::Module(module)) => Rc::strong_count(module),
+            Node::Other(_) => unreachable!(),
+        }
+    }
+
+    // The objects this one refers to, or None when it's borrowed and can't be looked into.
+    // The collector then keeps it and everything it refers to.
+    fn children(&self) -> Option<Vec<Node>> {
+        let mut children = vec![];
+
+        match self {
+            Node::List(list) => {
+                children.extend(list.try_borrow().ok()?.iter().filter_map(Node::from_value));
+            },
+            Node::Map(map) => {
+                children.extend(map.try_borrow().ok()?.values().filter_map(Node::from_value));
+            },
+            Node::Environment(environment) => {
+                let environment = environment.try_borrow().ok()?;
+                children.extend(environment.values().filter_map(Node::from_value));
+                children.extend(environment.enclosing().map(|enclosing| Node::Environment(enclosing.clone())));
+            },
+            Node::Upvalue(upvalue) => {
+                if let Upvalue::Closed(value) = &*upvalue.try_borrow().ok()? {
+                    children.extend(Node::from_value(value));
+                }
+            },
+            Node::Other(Value::EnumValue(value)) => children.extend(value.fields.iter().filter_map(Node::from_value)),
+            Node::Other(Value::Function(function)) => children.push(Node::Environment(function.closure.clone())),
+            Node::Other(Value::Closure(closure)) => {
+                children.push(Node::Environment(closure.globals.clone()));
+                children.extend(closure.upvalues.iter().map(|upvalue| Node::Upvalue(upvalue.clone())));
+            },
+            Node::Other(Value::BoundMethod(method)) => children.extend(Node::from_value(&method.receiver)),
+            Node::Other(Value::Module(module)) => children.extend(module.exports.values().filter_map(Node::from_value)),
+            Node::Other(_) => {},
+        }
+
+        return Some(children);
+    }
+
+    // Drops what a registered object holds, returning whether it was one.
+    fn empty(&self) -> bool {
+        return match self {
+            Node::List(list) => list.try_borrow_mut().map(|mut list| list.clear()).is_ok(),
+            Node::Map(map) => map.try_borrow_mut().map(|mut map| map.clear()).is_ok(),
+            Node::Environment(environment) => {
+                environment.try_borrow_mut().map(|mut environment| *environment = Environment::new()).is_ok()
+            },
+            Node::Upvalue(upvalue) => upvalue.try_borrow_mut().map(|mut upvalue| *upvalue = Upvalue::Closed(Value::Null)).is_ok(),
+            Node::Other(_) => false,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Graph {
+    nodes: Vec<Node>,
+    indices: HashMap<usize, usize>,
+    // References to each node from other nodes.
+    internal: Vec<usize>,
+    // The children of each node as indices, or None when it couldn't be looked into.
+    edges: Vec<Option<Vec<usize>>>,
+}
+
+impl Graph {
+    fn add(&mut self, node: Node) -> usize {
+        let address = node.address();
+
+        if let Some(index) = self.indices.get(&address) {
+            return *index;
+        }
+
+        self.nodes.push(node);
+        self.internal.push(0);
+        self.indices.insert(address, self.nodes.len() - 1);
+
+        return self.nodes.len() - 1;
+    }
+
+    // Walks the heap from the registered objects, adding every object found on the way.
+    fn count_references(&mut self) {
+        let mut index = 0;
+
+        while index < self.nodes.len() {
+            let edges = self.nodes[index].children().map(|children| {
+                children.into_iter().map(|child| {
+                    let child = self.add(child);
+                    self.internal[child] += 1;
+                    child
+                }).collect()
+            });
+
+            self.edges.push(edges);
+            index += 1;
+        }
+    }
+
+    fn mark(&self) -> Vec<bool> {
+        // The graph holds one reference to each node itself.
+        let mut pending: Vec<usize> = (0..self.nodes.len())
+            .filter(|index| self.nodes[*index].strong_count() - 1 > self.internal[*index] || self.edges[*index].is_none())
+            .collect();
+        let mut reachable = vec![false; self.nodes.len()];
+
+        while let Some(index) = pending.pop() {
+            if reachable[index] {
+                continue;
+            }
+
+            reachable[index] = true;
+            pending.extend(self.edges[index].iter().flatten().filter(|child| !reachable[**child]));
+        }
+
+        return reachable;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycles_are_freed() {
+        let list = Value::List(list(vec![]));
+        let map = Value::Map(map(BTreeMap::new()));
+
+        if let (Value::List(elements), Value::Map(entries)) = (&list, &map) {
+            elements.borrow_mut().push(map.clone());
+            entries.borrow_mut().insert("list".to_string(), list.clone());
+        }
+
+        let weak = match &list {
+            Value::List(elements) => Rc::downgrade(elements),
+            _ => unreachable!(),
+        };
+
+        drop(list);
+        drop(map);
+
+        assert_eq!(weak.strong_count(), 1);
+        assert_eq!(collect(), 2);
+        assert_eq!(weak.strong_count(), 0);
+    }
+
+    #[test]
+    fn test_values_held_outside_the_heap_are_kept() {
+        let inner = Value::List(list(vec![Value::Number(1.0)]));
+        let outer = list(vec![inner.clone()]);
+        outer.borrow_mut().push(Value::List(outer.clone()));
+
+        // `outer` is only reachable through a Rust variable, like a value a native is using.
+        assert_eq!(collect(), 0);
+        assert_eq!(outer.borrow().len(), 2);
+
+        drop(inner);
+        assert_eq!(collect(), 0);
+        assert_eq!(outer.borrow()[0].to_string(), "[1]");
+    }
+
+    #[test]
+    fn test_threshold_starts_collections() {
+        collect();
+        set_threshold(100);
+        let before = stats().collections;
+
+        for _ in 0..250 {
+            let cycle = list(vec![]);
+            cycle.borrow_mut().push(Value::List(cycle.clone()));
+        }
+
+        let stats = stats();
+        assert_eq!(stats.collections - before, 2);
+        assert!(stats.live < 100);
+        assert_eq!(stats.threshold, 100);
+    }
+}
@@ -0,0 +1,1443 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+use crate::capabilities::Capabilities;
+use crate::chunk::FunctionProto;
+use crate::compiler;
+use crate::environment::Environment;
+use crate::exception::{ErrorKind, RuntimeError, TraceFrame};
+use crate::gc;
+use crate::limits::{CancelHandle, Limits, Meter};
+use crate::expr::{Expr, MatchArm};
+use crate::module::{directory_of, display_path, resolve, within_search_paths, Module};
+use crate::optimizer::Optimizer;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::pattern::Pattern;
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::token_type::TokenType;
+use crate::stdlib;
+use crate::value::{Arity, BoundMethod, EnumDefinition, EnumValue, Function, NativeFunction, Value};
+use crate::vm;
+
+// How scripts are executed: walking the syntax tree, or compiled to bytecode for the VM.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Backend {
+    #[default]
+    Tree,
+    Vm,
+}
+
+// `O1` runs the optimizer over every program and module before executing it.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OptLevel {
+    O0,
+    #[default]
+    O1,
+}
+
+// Calls deeper than this raise a `StackOverflow` error.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+// Each tree-walker call takes tens of kilobytes of native stack in debug builds. When less than
+// `RED_ZONE` is left, calls continue on a new `STACK_SEGMENT` from the heap, so the call depth
+// limit decides how deep scripts recurse whatever the size of the host's thread.
+const RED_ZONE: usize = 256 * 1024;
+const STACK_SEGMENT: usize = 4 * 1024 * 1024;
+
+struct CallFrame {
+    function: String,
+    file: String,
+    // The line currently executing in this frame, updated at every call it makes.
+    line: usize,
+}
+
+pub type WarningHandler = dyn FnMut(&str);
+
+struct TailCall {
+    line: usize,
+    function: Rc<Function>,
+    arguments: Vec<Value>,
+}
+
+pub struct Interpreter {
+    // Host-provided builtins, visible from the script and from every module.
+    globals: Rc<RefCell<Environment>>,
+    // Top-level scope of the main script, where the host reads and writes globals.
+    script: Rc<RefCell<Environment>>,
+    environment: Rc<RefCell<Environment>>,
+    file: String,
+    frames: Vec<CallFrame>,
+    returning: Option<Value>,
+    // A call a `return` left for the function it returns from to make, once its scope is gone.
+    tail_call: Option<TailCall>,
+    // Whether a `return` may leave its call behind: only directly in a function body, not in a
+    // `try` that still has to handle the call's errors.
+    tail_calls: bool,
+    max_depth: usize,
+    limits: Limits,
+    meter: Meter,
+    cancel: CancelHandle,
+    search_paths: Vec<PathBuf>,
+    // Whether `search_paths` starts with the main script's directory, which `set_file` replaces.
+    script_directory: bool,
+    modules: HashMap<PathBuf, Rc<Module>>,
+    // Modules currently being executed, outermost first, used to detect import cycles.
+    loading: Vec<PathBuf>,
+    warnings: Vec<String>,
+    // Receives warnings as they're found instead of `warnings`, when set.
+    warning_handler: Option<Box<WarningHandler>>,
+    // Native methods available on builtin values, keyed by type name and then method name.
+    methods: HashMap<String, HashMap<String, Rc<NativeFunction>>>,
+    capabilities: Capabilities,
+    backend: Backend,
+    opt_level: OptLevel,
+    // Print the VM stack and each instruction as it runs.
+    trace: bool,
+    // The VM's value stack, handed from one VM run to the runs nested inside it (through natives
+    // calling back into scripts) so slots captured by closures stay at the same index.
+    pub(crate) vm_stack: Vec<Value>,
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        return Self::with_capabilities(Capabilities::default());
+    }
+
+    pub fn with_capabilities(capabilities: Capabilities) -> Self {
+        let globals = gc::environment(Environment::new());
+        let script = gc::environment(Environment::new_enclosed(globals.clone()));
+
+        let mut interpreter = Self {
+            globals,
+            script: script.clone(),
+            environment: script,
+            file: "<script>".to_string(),
+            frames: vec![CallFrame {
+                function: "<script>".to_string(),
+                file: "<script>".to_string(),
+                line: 0,
+            }],
+            returning: None,
+            tail_call: None,
+            tail_calls: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+            limits: Limits::default(),
+            meter: Meter::default(),
+            cancel: CancelHandle::default(),
+            search_paths: vec![],
+            script_directory: false,
+            modules: HashMap::new(),
+            loading: vec![],
+            warnings: vec![],
+            warning_handler: None,
+            methods: HashMap::new(),
+            capabilities,
+            backend: Backend::default(),
+            opt_level: OptLevel::default(),
+            trace: false,
+            vm_stack: vec![],
+        };
+
+        stdlib::register_defaults(&mut interpreter);
+
+        return interpreter;
+    }
+
+    pub fn with_file(file: &str) -> Self {
+        let mut interpreter = Self::new();
+        interpreter.set_file(file);
+
+        return interpreter;
+    }
+
+    // Names the file the main script comes from, for tracebacks and resolving relative imports.
+    pub fn set_file(&mut self, file: &str) {
+        self.file = file.to_string();
+        self.frames[0].file = file.to_string();
+        self.loading = PathBuf::from(file).canonicalize().into_iter().collect();
+
+        if self.script_directory {
+            self.search_paths[0] = directory_of(file);
+        } else {
+            self.search_paths.insert(0, directory_of(file));
+            self.script_directory = true;
+        }
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        return Symbol::find(name).and_then(|name| self.script.borrow().lookup(&name));
+    }
+
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.script.borrow_mut().define(Symbol::intern(name), value);
+    }
+
+    pub fn set_args(&mut self, args: Vec<String>) {
+        stdlib::set_process_args(self, args);
+    }
+
+    pub fn capabilities(&self) -> &Capabilities {
+        return &self.capabilities;
+    }
+
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend;
+    }
+
+    pub fn backend(&self) -> Backend {
+        return self.backend;
+    }
+
+    pub fn set_opt_level(&mut self, opt_level: OptLevel) {
+        self.opt_level = opt_level;
+    }
+
+    pub fn opt_level(&self) -> OptLevel {
+        return self.opt_level;
+    }
+
+    // Runs the optimizer when it's enabled, keeping its warnings with the parser's.
+    pub fn optimize(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        if self.opt_level == OptLevel::O0 {
+            return statements;
+        }
+
+        let mut optimizer = Optimizer::new();
+        let statements = optimizer.optimize(statements);
+
+        for warning in optimizer.warnings {
+            self.add_warning(warning);
+        }
+
+        return statements;
+    }
+
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    pub fn trace(&self) -> bool {
+        return self.trace;
+    }
+
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn max_depth(&self) -> usize {
+        return self.max_depth;
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &Limits {
+        return &self.limits;
+    }
+
+    // A handle other threads can use to stop the script this interpreter is running.
+    pub fn cancel_handle(&self) -> CancelHandle {
+        return self.cancel.clone();
+    }
+
+    // The collector is shared by every interpreter on the thread, so this sets its threshold for all of them.
+    pub fn set_gc_threshold(&mut self, threshold: usize) {
+        gc::set_threshold(threshold);
+    }
+
+    // Compiles a program for the VM, naming it after the file it came from.
+    pub fn compile(&self, statements: &[Stmt]) -> Result<Rc<FunctionProto>, RuntimeError> {
+        return compiler::compile(statements, &self.file);
+    }
+
+    // Runs an already compiled program on the VM, whichever backend is selected.
+    pub fn run_compiled(&mut self, function: Rc<FunctionProto>) -> Result<Value, RuntimeError> {
+        self.start_run();
+
+        return vm::run(self, function, self.environment.clone()).map_err(|error| self.with_trace(error));
+    }
+
+    pub fn add_warning(&mut self, warning: String) {
+        match &mut self.warning_handler {
+            Some(handler) => handler(&warning),
+            None => self.warnings.push(warning),
+        }
+    }
+
+    pub fn set_warning_handler(&mut self, handler: Box<WarningHandler>) {
+        self.warning_handler = Some(handler);
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<String> {
+        return std::mem::take(&mut self.warnings);
+    }
+
+    pub fn register_native<F>(&mut self, name: &str, arity: Arity, function: F)
+    where
+        F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    {
+        let native = NativeFunction {
+            name: name.to_string(),
+            arity,
+            function: Box::new(function),
+        };
+
+        self.globals.borrow_mut().define(Symbol::intern(name), Value::NativeFunction(Rc::new(native)));
+    }
+
+    pub fn register_global(&mut self, name: &str, value: Value) {
+        self.globals.borrow_mut().define(Symbol::intern(name), value);
+    }
+
+    pub fn register_method<F>(&mut self, type_name: &str, name: &str, arity: Arity, function: F)
+    where
+        F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    {
+        let native = NativeFunction {
+            name: format!("{}.{}", type_name, name),
+            arity,
+            function: Box::new(function),
+        };
+
+        self.methods.entry(type_name.to_string())
+            .or_default()
+            .insert(name.to_string(), Rc::new(native));
+    }
+
+    pub fn add_search_path(&mut self, path: PathBuf) {
+        self.search_paths.push(path);
+    }
+
+    // Runs a program, returning the value of its final statement when that is an expression.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<Value, RuntimeError> {
+        self.start_run();
+
+        if self.backend == Backend::Vm {
+            let function = self.compile(statements).map_err(|error| self.with_trace(error))?;
+
+            return self.run_compiled(function);
+        }
+
+        let result = match statements.split_last() {
+            Some((Stmt::Expression { expression }, rest)) => {
+                self.execute_statements(rest).and_then(|_| self.evaluate(expression))
+            },
+            _ => self.execute_statements(statements).map(|_| Value::Null),
+        };
+
+        return result.map_err(|error| self.with_trace(error));
+    }
+
+    // Calls a script or native function from host code or from inside a native.
+    pub fn call_value(&mut self, callee: Value, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        let line = self.frames.last().map(|frame| frame.line).unwrap_or(0);
+        self.start_run();
+
+        return self.call(line, callee, arguments);
+    }
+
+    // Limits apply to each run from the host; calls back into scripts from natives are part of
+    // the run they are made in.
+    fn start_run(&mut self) {
+        if self.frames.len() == 1 {
+            self.meter.start(&self.limits);
+        }
+    }
+
+    pub(crate) fn step(&mut self, line: usize) -> Result<(), RuntimeError> {
+        return self.meter.step(&self.limits, &self.cancel).map_err(|mut error| {
+            error.line = line;
+            error
+        });
+    }
+
+    // For natives that block, so the run's timeout and cancel handle still reach them.
+    pub(crate) fn sleep(&mut self, duration: Duration) -> Result<(), RuntimeError> {
+        return self.meter.sleep(duration, &self.limits, &self.cancel);
+    }
+
+    pub(crate) fn has_timeout(&self) -> bool {
+        return self.limits.timeout.is_some();
+    }
+
+    // The tree-walker doesn't know the line of every step, so its errors take the line of the
+    // last call the function made.
+    fn tree_step(&mut self) -> Result<(), RuntimeError> {
+        let line = self.frames.last().map(|frame| frame.line).unwrap_or(0);
+
+        return self.step(line);
+    }
+
+    fn execute_statements(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        for statement in statements {
+            self.execute(statement)?;
+
+            if self.returning.is_some() {
+                break;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Records the active call frames on an error the first time it unwinds through a call.
+    pub(crate) fn with_trace(&self, mut error: RuntimeError) -> RuntimeError {
+        if !error.trace.is_empty() {
+            return error;
+        }
+
+        let innermost = self.frames.len() - 1;
+
+        error.trace = self.frames.iter().enumerate()
+            .map(|(i, frame)| TraceFrame {
+                function: frame.function.clone(),
+                file: frame.file.clone(),
+                line: if i == innermost { error.line } else { frame.line },
+            })
+            .collect();
+
+        return error;
+    }
+
+    // The VM keeps these frames in step with its own, so tracebacks read the same on both backends.
+    pub(crate) fn push_frame(&mut self, function: &str, file: &str, line: usize) -> Result<(), RuntimeError> {
+        // The script's own frame doesn't count towards the depth.
+        if let Some(depth) = self.limits.call_depth.filter(|depth| self.frames.len() > *depth) {
+            return Err(RuntimeError::new(ErrorKind::CallDepthExceeded, format!("Call depth limit of {} exceeded", depth), line));
+        }
+
+        if self.frames.len() > self.max_depth {
+            return Err(RuntimeError::new(
+                ErrorKind::StackOverflow,
+                format!("Maximum call depth of {} exceeded", self.max_depth),
+                line,
+            ));
+        }
+
+        self.frames.push(CallFrame {
+            function: function.to_string(),
+            file: file.to_string(),
+            line,
+        });
+
+        return Ok(());
+    }
+
+    // A tail call reuses the frame of the function making it.
+    pub(crate) fn replace_frame(&mut self, function: &str, file: &str, line: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.function = function.to_string();
+            frame.file = file.to_string();
+            frame.line = line;
+        }
+    }
+
+    pub(crate) fn pop_frame(&mut self) {
+        self.frames.pop();
+    }
+
+    pub(crate) fn frame_count(&self) -> usize {
+        return self.frames.len();
+    }
+
+    pub(crate) fn truncate_frames(&mut self, count: usize) {
+        self.frames.truncate(count);
+    }
+
+    pub(crate) fn set_line(&mut self, line: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = line;
+        }
+    }
+
+    fn execute(&mut self, statement: &Stmt) -> Result<(), RuntimeError> {
+        self.tree_step()?;
+
+        match statement {
+            Stmt::Expression { expression } => {
+                self.evaluate(expression)?;
+            },
+            Stmt::Print { expression } => {
+                println!("{}", self.evaluate(expression)?.to_string());
+            },
+            Stmt::Let { keyword, pattern, initializer } => {
+                let value = match initializer {
+                    Some(initializer) => self.evaluate(initializer)?,
+                    None => Value::Null,
+                };
+
+                let mut bindings = vec![];
+                self.destructure(keyword, pattern, value, &mut bindings)?;
+
+                for (name, value) in bindings {
+                    self.environment.borrow_mut().define(name, value);
+                }
+            },
+            Stmt::Enum { name, variants } => {
+                let definition = EnumDefinition {
+                    name: name.lexeme.to_string(),
+                    variants: variants.iter()
+                        .map(|variant| (variant.name.lexeme.clone(), variant.fields.len()))
+                        .collect(),
+                };
+
+                self.environment.borrow_mut().define(name.lexeme.clone(), Value::Enum(Rc::new(definition)));
+            },
+            Stmt::Block { statements } => {
+                self.execute_block(statements, Environment::new_enclosed(self.environment.clone()))?;
+            },
+            Stmt::Function { name, params, body } => {
+                let function = Function {
+                    name: name.lexeme.to_string(),
+                    file: self.file.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                    closure: self.environment.clone(),
+                };
+
+                self.environment.borrow_mut().define(name.lexeme.clone(), Value::Function(Rc::new(function)));
+            },
+            Stmt::Return { keyword: _, value } => {
+                let value = match value {
+                    Some(value) if self.tail_calls => self.evaluate_tail(value)?,
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Null,
+                };
+
+                self.returning = Some(value);
+            },
+            Stmt::Import { keyword, path, alias, names } => {
+                if let Some(frame) = self.frames.last_mut() {
+                    frame.line = keyword.line_number;
+                }
+
+                let module = self.import_module(keyword.line_number, path)?;
+
+                if let Some(alias) = alias {
+                    self.environment.borrow_mut().define(alias.lexeme.clone(), Value::Module(module.clone()));
+                }
+
+                for name in names {
+                    let value = match module.exports.get(name.lexeme.as_str()) {
+                        Some(value) => value.clone(),
+                        None => return Err(error(name, ErrorKind::ImportError, &format!(
+                            "Module '{}' has no export '{}'",
+                            module.name,
+                            name.lexeme,
+                        ))),
+                    };
+
+                    self.environment.borrow_mut().define(name.lexeme.clone(), value);
+                }
+            },
+            Stmt::Export { declaration } => {
+                self.execute(declaration)?;
+            },
+            Stmt::Throw { keyword, value } => {
+                let value = self.evaluate(value)?;
+
+                return Err(RuntimeError::thrown(value, keyword.line_number));
+            },
+            Stmt::Try { body, catch_name, catch_body, finally_body } => {
+                let tail_calls = std::mem::replace(&mut self.tail_calls, false);
+                let result = self.execute_try(body, catch_name, catch_body, finally_body);
+                self.tail_calls = tail_calls;
+
+                return result;
+            },
+        }
+
+        return Ok(());
+    }
+
+    fn execute_try(&mut self, body: &[Stmt], catch_name: &Option<Token>, catch_body: &Option<Vec<Stmt>>, finally_body: &Option<Vec<Stmt>>) -> Result<(), RuntimeError> {
+        let mut result = self.execute_block(body, Environment::new_enclosed(self.environment.clone()));
+
+        // Exiting still runs finally blocks on the way out, but can't be caught. Reaching a
+        // limit doesn't run them either.
+        let catchable = matches!(&result, Err(error) if error.kind.is_catchable());
+
+        if matches!(&result, Err(error) if error.kind.is_termination()) {
+            return result;
+        }
+
+        if let (true, Err(error), Some(name), Some(catch_body)) = (catchable, &result, catch_name, catch_body) {
+            let mut environment = Environment::new_enclosed(self.environment.clone());
+            environment.define(name.lexeme.clone(), error.to_value());
+
+            result = self.execute_block(catch_body, environment);
+        }
+
+        if let Some(finally_body) = finally_body {
+            // A `return` inside try or catch must survive the finally block running.
+            let returning = self.returning.take();
+            self.execute_block(finally_body, Environment::new_enclosed(self.environment.clone()))?;
+
+            if self.returning.is_none() {
+                self.returning = returning;
+            }
+        }
+
+        return result;
+    }
+
+    fn execute_block(&mut self, statements: &[Stmt], environment: Environment) -> Result<(), RuntimeError> {
+        let previous = std::mem::replace(&mut self.environment, gc::environment(environment));
+        let result = self.execute_statements(statements);
+        self.environment = previous;
+
+        return result;
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        self.tree_step()?;
+
+        return match expr {
+            Expr::Literal { value } => Ok(Value::from_literal(value)),
+            Expr::Grouping { expression } => self.evaluate(expression),
+            Expr::Unary { operator, right } => {
+                let right = self.evaluate(right)?;
+
+                unary(&operator.token_type, operator.lexeme.as_str(), operator.line_number, right)
+            },
+            Expr::Binary { left, operator, right } => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+
+                binary(&operator.token_type, operator.lexeme.as_str(), operator.line_number, left, right)
+            },
+            Expr::Variable { name } => self.environment.borrow().get(name),
+            Expr::Call { callee, paren, arguments } => {
+                let (callee, arguments) = self.evaluate_call(callee, paren, arguments)?;
+
+                self.call(paren.line_number, callee, arguments)
+            },
+            Expr::Get { object, name } => {
+                let object = self.evaluate(object)?;
+
+                self.get(name, object)
+            },
+            Expr::List { elements } => {
+                let mut values = vec![];
+
+                for element in elements {
+                    values.push(self.evaluate(element)?);
+                }
+
+                Ok(Value::list(values))
+            },
+            Expr::Map { entries } => {
+                let mut values = BTreeMap::new();
+
+                for (key, value) in entries {
+                    values.insert(key.to_string(), self.evaluate(value)?);
+                }
+
+                Ok(Value::map(values))
+            },
+            Expr::Match { keyword, subject, arms } => {
+                let subject = self.evaluate(subject)?;
+
+                self.evaluate_match(keyword, subject, arms, false)
+            },
+        }
+    }
+
+    // Evaluates the value of a `return`. A call to a script function there, including one a
+    // `match` arm ends in, is left for `call_function` to make after the current call returns,
+    // so tail recursion runs in constant stack.
+    fn evaluate_tail(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        return match expr {
+            Expr::Grouping { expression } => self.evaluate_tail(expression),
+            Expr::Call { callee, paren, arguments } => match self.evaluate_call(callee, paren, arguments)? {
+                (Value::Function(function), arguments) => {
+                    self.tail_call = Some(TailCall { line: paren.line_number, function, arguments });
+
+                    Ok(Value::Null)
+                },
+                (callee, arguments) => self.call(paren.line_number, callee, arguments),
+            },
+            Expr::Match { keyword, subject, arms } => {
+                let subject = self.evaluate(subject)?;
+
+                self.evaluate_match(keyword, subject, arms, true)
+            },
+            expr => self.evaluate(expr),
+        }
+    }
+
+    fn evaluate_call(&mut self, callee: &Expr, paren: &Token, arguments: &[Expr]) -> Result<(Value, Vec<Value>), RuntimeError> {
+        let callee = self.evaluate(callee)?;
+        let mut values = vec![];
+
+        for argument in arguments {
+            values.push(self.evaluate(argument)?);
+        }
+
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = paren.line_number;
+        }
+
+        return Ok((callee, values));
+    }
+
+    pub(crate) fn import_module(&mut self, line: usize, path: &str) -> Result<Rc<Module>, RuntimeError> {
+        // Without the fs capability, scripts can only import from the directories the embedder
+        // chose: the search paths, which include the main script's directory.
+        if !self.capabilities.fs && !within_search_paths(path, &self.file, &self.search_paths) {
+            return Err(RuntimeError::new(ErrorKind::CapabilityDenied, format!("Capability denied: importing '{}' from outside the search path needs the fs capability", path), line));
+        }
+
+        let resolved = match resolve(path, &self.file, &self.search_paths) {
+            Ok(resolved) => resolved,
+            Err(message) => return match self.builtin_module(path) {
+                Some(module) => match module.denied {
+                    Some(capability) => Err(RuntimeError::new(ErrorKind::CapabilityDenied, format!("Capability denied: module '{}' needs the {} capability", path, capability), line)),
+                    None => Ok(module),
+                },
+                None => Err(RuntimeError::new(ErrorKind::ImportError, message, line)),
+            },
+        };
+
+        if let Some(module) = self.modules.get(&resolved) {
+            return Ok(module.clone());
+        }
+
+        if let Some(start) = self.loading.iter().position(|loading| *loading == resolved) {
+            let mut chain: Vec<String> = self.loading[start..].iter().map(|p| display_path(p)).collect();
+            chain.push(display_path(&resolved));
+
+            return Err(RuntimeError::new(ErrorKind::ImportError, format!("Import cycle detected: {}", chain.join(" -> ")), line));
+        }
+
+        let name = display_path(&resolved);
+        let (statements, warnings) = parse_module(&resolved)
+            .map_err(|message| RuntimeError::new(ErrorKind::ImportError, format!("In module '{}':\n{}", name, message), line))?;
+
+        for warning in warnings {
+            self.add_warning(warning);
+        }
+
+        let statements = self.optimize(statements);
+
+        let environment = gc::environment(Environment::new_enclosed(self.globals.clone()));
+        let previous_environment = std::mem::replace(&mut self.environment, environment.clone());
+        let previous_file = std::mem::replace(&mut self.file, name.clone());
+
+        self.loading.push(resolved.clone());
+        self.frames.push(CallFrame {
+            function: "<module>".to_string(),
+            file: name.clone(),
+            line: 0,
+        });
+
+        let result = match self.backend {
+            Backend::Tree => self.execute_statements(&statements),
+            Backend::Vm => compiler::compile(&statements, &name)
+                .and_then(|function| vm::run(self, function, environment.clone()))
+                .map(|_| ()),
+        };
+        let result = result.map_err(|error| self.with_trace(error));
+
+        self.frames.pop();
+        self.loading.pop();
+        self.file = previous_file;
+        self.environment = previous_environment;
+
+        result?;
+
+        let mut exports = BTreeMap::new();
+
+        for statement in &statements {
+            if let Stmt::Export { declaration } = statement {
+                for export in declaration.declared_names() {
+                    if let Some(value) = environment.borrow().lookup(&export) {
+                        exports.insert(export.to_string(), value);
+                    }
+                }
+            }
+        }
+
+        let module = Rc::new(Module { name, exports, denied: None });
+        self.modules.insert(resolved, module.clone());
+
+        return Ok(module);
+    }
+
+    // A module registered as a global, such as `math`, which a file of the same name on the
+    // search path shadows.
+    fn builtin_module(&self, name: &str) -> Option<Rc<Module>> {
+        return match Symbol::find(name).and_then(|name| self.globals.borrow().lookup(&name)) {
+            Some(Value::Module(module)) => Some(module),
+            _ => None,
+        };
+    }
+
+    // Calls made on `line`, which errors from the call are reported at.
+    pub(crate) fn call(&mut self, line: usize, callee: Value, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        return stacker::maybe_grow(RED_ZONE, STACK_SEGMENT, || self.call_on_stack(line, callee, arguments));
+    }
+
+    fn call_on_stack(&mut self, line: usize, callee: Value, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        return match callee {
+            Value::EnumConstructor(definition, variant) => {
+                let (variant, arity) = definition.variants[variant as usize].clone();
+
+                if arguments.len() != arity {
+                    return Err(RuntimeError::new(ErrorKind::TypeError, format!("Expected {} arguments but got {}", arity, arguments.len()), line));
+                }
+
+                Ok(Value::EnumValue(Rc::new(EnumValue { definition, variant, fields: arguments })))
+            },
+            Value::Function(function) => self.call_function(line, function, arguments),
+            Value::Closure(closure) => vm::call(self, line, closure, arguments),
+            Value::NativeFunction(native) => self.call_native(line, native, None, arguments),
+            Value::BoundMethod(bound) => self.call_native(line, bound.method.clone(), Some(bound.receiver.clone()), arguments),
+            value => Err(RuntimeError::new(ErrorKind::TypeError, format!("Can only call functions and constructors, got {}", value.type_name()), line)),
+        }
+    }
+
+    fn call_function(&mut self, line: usize, mut function: Rc<Function>, mut arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        check_arity(line, &function, &arguments)?;
+        self.push_frame(&function.name, &function.file, line)?;
+        let tail_calls = std::mem::replace(&mut self.tail_calls, true);
+
+        // Tail calls run in this loop, in the frame of the call they replace.
+        let result = loop {
+            let mut environment = Environment::new_enclosed(function.closure.clone());
+            for (param, argument) in function.params.iter().zip(arguments) {
+                environment.define(param.lexeme.clone(), argument);
+            }
+
+            if let Err(error) = self.execute_block(&function.body, environment) {
+                break Err(error);
+            }
+
+            let call = match self.tail_call.take() {
+                Some(call) => call,
+                None => break Ok(self.returning.take().unwrap_or(Value::Null)),
+            };
+
+            if let Err(error) = check_arity(call.line, &call.function, &call.arguments) {
+                break Err(error);
+            }
+
+            self.returning = None;
+            self.replace_frame(&call.function.name, &call.function.file, call.line);
+            function = call.function;
+            arguments = call.arguments;
+        };
+
+        let result = result.map_err(|error| self.with_trace(error));
+        self.tail_calls = tail_calls;
+        self.frames.pop();
+
+        return result;
+    }
+
+    fn call_native(&mut self, line: usize, native: Rc<NativeFunction>, receiver: Option<Value>, mut arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        if let Arity::Fixed(arity) = native.arity {
+            if arguments.len() != arity {
+                return Err(RuntimeError::new(ErrorKind::TypeError, format!(
+                    "{} expected {} arguments but got {}",
+                    native.name,
+                    arity,
+                    arguments.len(),
+                ), line));
+            }
+        }
+
+        // Methods receive the value they were read from as their first argument.
+        if let Some(receiver) = receiver {
+            arguments.insert(0, receiver);
+        }
+
+        self.push_frame(&native.name, "<native>", line)?;
+
+        // Natives don't know where they were called from, so their errors take the call's line.
+        let result = (native.function)(self, arguments).map_err(|mut error| {
+            if error.line == 0 {
+                error.line = line;
+            }
+
+            self.with_trace(error)
+        });
+
+        self.frames.pop();
+
+        return result;
+    }
+
+    pub(crate) fn get(&self, name: &Token, object: Value) -> Result<Value, RuntimeError> {
+        // Map keys shadow methods, so `e.message` still reads the key on an error map.
+        if let Value::Map(entries) = &object {
+            if let Some(value) = entries.borrow().get(name.lexeme.as_str()) {
+                return Ok(value.clone());
+            }
+        }
+
+        if let Value::Module(module) = &object {
+            if let Some(capability) = module.denied {
+                return Err(error(name, ErrorKind::CapabilityDenied, &format!("Capability denied: '{}.{}' needs the {} capability", module.name, name.lexeme, capability)));
+            }
+        }
+
+        if let Some(method) = self.methods.get(object.type_name()).and_then(|methods| methods.get(name.lexeme.as_str())) {
+            return Ok(Value::BoundMethod(Rc::new(BoundMethod { receiver: object, method: method.clone() })));
+        }
+
+        return match object {
+            Value::Enum(definition) => {
+                let (index, arity) = match definition.variants.iter().position(|(variant, _)| *variant == name.lexeme) {
+                    Some(index) => (index, definition.variants[index].1),
+                    None => return Err(error(name, ErrorKind::UndefinedProperty, &format!("Enum {} has no variant '{}'", definition.name, name.lexeme))),
+                };
+
+                if arity > 0 {
+                    return Ok(Value::EnumConstructor(definition, index as u32));
+                }
+
+                Ok(Value::EnumValue(Rc::new(EnumValue { definition, variant: name.lexeme.clone(), fields: vec![] })))
+            },
+            Value::Map(_) => Err(error(name, ErrorKind::UndefinedProperty, &format!("Map has no key '{}'", name.lexeme))),
+            Value::Module(module) => match module.exports.get(name.lexeme.as_str()) {
+                Some(value) => Ok(value.clone()),
+                None => Err(error(name, ErrorKind::UndefinedProperty, &format!("Module '{}' has no export '{}'", module.name, name.lexeme))),
+            },
+            value => Err(error(name, ErrorKind::TypeError, &format!("Cannot read property '{}' of {}", name.lexeme, value.type_name()))),
+        }
+    }
+
+    fn evaluate_match(&mut self, keyword: &Token, subject: Value, arms: &[MatchArm], tail: bool) -> Result<Value, RuntimeError> {
+        for arm in arms {
+            let mut bindings = vec![];
+
+            if ! self.match_pattern(&arm.pattern, &subject, &mut bindings)? {
+                continue;
+            }
+
+            let mut environment = Environment::new_enclosed(self.environment.clone());
+            for (name, value) in bindings {
+                environment.define(name, value);
+            }
+
+            let previous = std::mem::replace(&mut self.environment, gc::environment(environment));
+            let result = self.evaluate_arm(arm, tail);
+            self.environment = previous;
+
+            if let Some(value) = result? {
+                return Ok(value);
+            }
+        }
+
+        return Err(error(keyword, ErrorKind::MatchError, &format!("No match arm matched value {}", subject.to_string())));
+    }
+
+    fn evaluate_arm(&mut self, arm: &MatchArm, tail: bool) -> Result<Option<Value>, RuntimeError> {
+        if let Some(guard) = &arm.guard {
+            if ! self.evaluate(guard)?.is_truthy() {
+                return Ok(None);
+            }
+        }
+
+        return match tail {
+            true => Ok(Some(self.evaluate_tail(&arm.body)?)),
+            false => Ok(Some(self.evaluate(&arm.body)?)),
+        };
+    }
+
+    fn match_pattern(&mut self, pattern: &Pattern, value: &Value, bindings: &mut Vec<(Symbol, Value)>) -> Result<bool, RuntimeError> {
+        return match pattern {
+            Pattern::Wildcard => Ok(true),
+            Pattern::Literal { value: literal } => Ok(Value::from_literal(literal) == *value),
+            Pattern::Binding { name } => {
+                bindings.push((name.lexeme.clone(), value.clone()));
+                Ok(true)
+            },
+            Pattern::Alternative { alternatives } => {
+                for alternative in alternatives {
+                    let bound = bindings.len();
+
+                    if self.match_pattern(alternative, value, bindings)? {
+                        return Ok(true);
+                    }
+
+                    bindings.truncate(bound);
+                }
+
+                Ok(false)
+            },
+            Pattern::List { elements, rest } => {
+                let values = match value {
+                    Value::List(values) => values.borrow().clone(),
+                    _ => return Ok(false),
+                };
+
+                if values.len() < elements.len() || (rest.is_none() && values.len() != elements.len()) {
+                    return Ok(false);
+                }
+
+                for (pattern, value) in elements.iter().zip(values.iter()) {
+                    if ! self.match_pattern(pattern, value, bindings)? {
+                        return Ok(false);
+                    }
+                }
+
+                if let Some(rest) = rest {
+                    bindings.push((rest.lexeme.clone(), Value::list(values[elements.len()..].to_vec())));
+                }
+
+                Ok(true)
+            },
+            Pattern::Map { entries } => {
+                let values = match value {
+                    Value::Map(values) => values.borrow().clone(),
+                    _ => return Ok(false),
+                };
+
+                for entry in entries {
+                    let value = match (values.get(entry.key.lexeme.as_str()), &entry.default) {
+                        (Some(value), _) => value.clone(),
+                        (None, Some(default)) => self.evaluate(default)?,
+                        (None, None) => return Ok(false),
+                    };
+
+                    if ! self.match_pattern(&entry.pattern, &value, bindings)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            },
+            Pattern::Variant { enum_name, variant, fields } => {
+                let value = match value {
+                    Value::EnumValue(value) => value,
+                    _ => return Ok(false),
+                };
+
+                if variant.lexeme != value.variant || value.fields.len() != fields.len() {
+                    return Ok(false);
+                }
+
+                if let Some(enum_name) = enum_name {
+                    if enum_name.lexeme != value.definition.name.as_str() {
+                        return Ok(false);
+                    }
+                }
+
+                for (pattern, field) in fields.iter().zip(value.fields.iter()) {
+                    if ! self.match_pattern(pattern, field, bindings)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            },
+        }
+    }
+
+    fn destructure(&mut self, keyword: &Token, pattern: &Pattern, value: Value, bindings: &mut Vec<(Symbol, Value)>) -> Result<(), RuntimeError> {
+        match pattern {
+            Pattern::Wildcard => {},
+            Pattern::Binding { name } => bindings.push((name.lexeme.clone(), value)),
+            Pattern::List { elements, rest } => {
+                let values = match value {
+                    Value::List(values) => values.borrow().clone(),
+                    value => return Err(error(keyword, ErrorKind::DestructureError, &format!("Cannot destructure {} as a list", value.type_name()))),
+                };
+
+                if rest.is_none() && values.len() != elements.len() {
+                    return Err(error(keyword, ErrorKind::DestructureError, &format!("Expected a list of {} elements, got {}", elements.len(), values.len())));
+                }
+
+                if values.len() < elements.len() {
+                    return Err(error(keyword, ErrorKind::DestructureError, &format!("Expected a list of at least {} elements, got {}", elements.len(), values.len())));
+                }
+
+                for (pattern, value) in elements.iter().zip(values.iter()) {
+                    self.destructure(keyword, pattern, value.clone(), bindings)?;
+                }
+
+                if let Some(rest) = rest {
+                    bindings.push((rest.lexeme.clone(), Value::list(values[elements.len()..].to_vec())));
+                }
+            },
+            Pattern::Map { entries } => {
+                let values = match value {
+                    Value::Map(values) => values.borrow().clone(),
+                    value => return Err(error(keyword, ErrorKind::DestructureError, &format!("Cannot destructure {} as a map", value.type_name()))),
+                };
+
+                for entry in entries {
+                    let value = match (values.get(entry.key.lexeme.as_str()), &entry.default) {
+                        (Some(value), _) => value.clone(),
+                        (None, Some(default)) => self.evaluate(default)?,
+                        (None, None) => return Err(error(&entry.key, ErrorKind::DestructureError, &format!("Missing key '{}' in destructured map", entry.key.lexeme))),
+                    };
+
+                    self.destructure(keyword, &entry.pattern, value, bindings)?;
+                }
+            },
+            _ => return Err(error(keyword, ErrorKind::DestructureError, &format!("Cannot destructure with pattern {}", pattern.to_string()))),
+        }
+
+        return Ok(());
+    }
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+fn parse_module(path: &PathBuf) -> Result<(Vec<Stmt>, Vec<String>), String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let mut scanner = Scanner::new(&source);
+    let mut parser = Parser::new(scanner.scan_tokens()?);
+    let statements = parser.parse_statements()?;
+
+    return Ok((statements, parser.warnings));
+}
+
+// The operators take the operator's lexeme and line rather than its token, so the VM, which has
+// no tokens, doesn't need to make one.
+pub(crate) fn unary(operator: &TokenType, lexeme: &str, line: usize, right: Value) -> Result<Value, RuntimeError> {
+    return match (operator, right) {
+        (TokenType::Minus, Value::Number(n)) => Ok(Value::Number(-n)),
+        (TokenType::Bang, value) => Ok(Value::Boolean(!value.is_truthy())),
+        (_, value) => Err(RuntimeError::new(ErrorKind::TypeError, format!("Operand of '{}' must be a number, got {}", lexeme, value.type_name()), line)),
+    }
+}
+
+pub(crate) fn binary(operator: &TokenType, lexeme: &str, line: usize, left: Value, right: Value) -> Result<Value, RuntimeError> {
+    return match (operator, left, right) {
+        (TokenType::Plus, Value::Number(a), Value::Number(b)) => Ok(Value::Number(a + b)),
+        (TokenType::Plus, Value::StringValue(a), Value::StringValue(b)) => Ok(Value::from(format!("{}{}", a, b))),
+        (TokenType::Minus, Value::Number(a), Value::Number(b)) => Ok(Value::Number(a - b)),
+        (TokenType::Star, Value::Number(a), Value::Number(b)) => Ok(Value::Number(a * b)),
+        (TokenType::Slash, Value::Number(_), Value::Number(0.0)) => {
+            Err(RuntimeError::new(ErrorKind::DivisionByZero, "Division by zero".to_string(), line))
+        },
+        (TokenType::Slash, Value::Number(a), Value::Number(b)) => Ok(Value::Number(a / b)),
+        (TokenType::Greater, Value::Number(a), Value::Number(b)) => Ok(Value::Boolean(a > b)),
+        (TokenType::GreaterEqual, Value::Number(a), Value::Number(b)) => Ok(Value::Boolean(a >= b)),
+        (TokenType::Less, Value::Number(a), Value::Number(b)) => Ok(Value::Boolean(a < b)),
+        (TokenType::LessEqual, Value::Number(a), Value::Number(b)) => Ok(Value::Boolean(a <= b)),
+        (TokenType::EqualEqual, a, b) => Ok(Value::Boolean(a == b)),
+        (TokenType::BangEqual, a, b) => Ok(Value::Boolean(a != b)),
+        (_, a, b) => Err(RuntimeError::new(ErrorKind::TypeError, format!(
+            "Unsupported operand types for '{}': {} and {}",
+            lexeme,
+            a.type_name(),
+            b.type_name(),
+        ), line)),
+    }
+}
+
+fn check_arity(line: usize, function: &Function, arguments: &[Value]) -> Result<(), RuntimeError> {
+    if arguments.len() != function.params.len() {
+        return Err(RuntimeError::new(ErrorKind::TypeError, format!(
+            "{} expected {} arguments but got {}",
+            function.name,
+            function.params.len(),
+            arguments.len(),
+        ), line));
+    }
+
+    return Ok(());
+}
+
+pub(crate) fn error(token: &Token, kind: ErrorKind, message: &str) -> RuntimeError {
+    return RuntimeError::new(kind, message.to_string(), token.line_number);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_dir::TestDir;
+    use super::*;
+
+    fn run(source: &str, variable: &str) -> Result<Value, String> {
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let statements = parser.parse_statements().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(&statements).map_err(|e| e.to_string())?;
+
+        return interpreter.evaluate(&Expr::Variable {
+            name: Token::new(TokenType::Identifier, Symbol::intern(variable), None, 0),
+        }).map_err(|e| e.to_string());
+    }
+
+    fn evaluate(source: &str) -> Result<Value, RuntimeError> {
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let expression = parser.parse().unwrap();
+
+        return Interpreter::new().evaluate(&expression);
+    }
+
+    #[test]
+    fn test_match_literals_and_alternatives() {
+        let source = r#"match "b" { 1 => "one", "a" | "b" => "letter", _ => "other" }"#;
+
+        assert_eq!(evaluate(source).unwrap(), Value::from("letter"));
+    }
+
+    #[test]
+    fn test_match_list_binding_with_guard() {
+        let source = "match [1, 2] { [x, y] if x > y => x, [x, y] => y, _ => 0 }";
+
+        assert_eq!(evaluate(source).unwrap(), Value::Number(2.0));
+    }
+
+    #[test]
+    fn test_match_without_matching_arm() {
+        let source = "match 3 { 1 => 1, 2 => 2 }";
+
+        assert!(evaluate(source).is_err());
+    }
+
+    #[test]
+    fn test_let_destructures_list_with_rest() {
+        let source = "let [a, b, ...rest] = [1, 2, 3, 4]; let result = [b, a, rest];";
+
+        assert_eq!(run(source, "result").unwrap().to_string(), "[2, 1, [3, 4]]");
+    }
+
+    #[test]
+    fn test_let_destructures_map_with_defaults() {
+        let source = r#"let {name, port = 8080, host: h = "localhost"} = {name: "api", host: "example.com"}; let result = [name, port, h];"#;
+
+        assert_eq!(run(source, "result").unwrap().to_string(), r#"["api", 8080, "example.com"]"#);
+    }
+
+    #[test]
+    fn test_let_destructuring_shape_mismatch() {
+        assert_eq!(
+            run("let [a, b] = [1];", "a").unwrap_err(),
+            "DestructureError at line 1: Expected a list of 2 elements, got 1",
+        );
+        assert_eq!(
+            run("let {name} = {age: 3};", "name").unwrap_err(),
+            "DestructureError at line 1: Missing key 'name' in destructured map",
+        );
+        assert_eq!(
+            run("let [a] = 1;", "a").unwrap_err(),
+            "DestructureError at line 1: Cannot destructure number as a list",
+        );
+    }
+
+    #[test]
+    fn test_enum_constructors_equality_and_match() {
+        let source = r#"
+            enum Shape { Circle(r), Rect(w, h), Empty }
+            let shapes = [Shape.Circle(2), Shape.Rect(3, 4), Shape.Empty];
+            let [circle, rect, empty] = shapes;
+            let area = match rect { Circle(r) => 3 * r * r, Shape.Rect(w, h) => w * h, Shape.Empty => 0 };
+            let result = [circle == Shape.Circle(2), circle == Shape.Circle(3), area, empty];
+        "#;
+
+        assert_eq!(run(source, "result").unwrap().to_string(), "[true, false, 12, Shape.Empty]");
+        assert_eq!(run("enum E { A(x) } let e = E.A(1, 2);", "e").unwrap_err(), "TypeError at line 1: Expected 1 arguments but got 2");
+    }
+
+    fn thrown(source: &str) -> String {
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let statements = parser.parse_statements().unwrap();
+
+        return Interpreter::new().interpret(&statements).unwrap_err().to_value().to_string();
+    }
+
+    #[test]
+    fn test_runtime_errors_are_catchable() {
+        let source = "try { let x = 1 / 0; } catch (e) { throw [e.kind, e.message, e.line]; }";
+        assert_eq!(thrown(source), r#"["DivisionByZero", "Division by zero", 1]"#);
+
+        let source = "try { print missing; } catch (e) { throw e.kind; }";
+        assert_eq!(thrown(source), "UndefinedVariable");
+    }
+
+    #[test]
+    fn test_catch_receives_thrown_value() {
+        assert_eq!(thrown("try { throw {code: 1}; } catch (e) { throw e.code + 1; }"), "2");
+    }
+
+    #[test]
+    fn test_finally_always_runs() {
+        assert_eq!(thrown("try { let a = 1; } finally { throw \"done\"; }"), "done");
+        assert_eq!(thrown("try { throw \"first\"; } finally { throw \"second\"; }"), "second");
+    }
+
+    #[test]
+    fn test_functions_and_recursion() {
+        let source = r#"
+            fn factorial(n) {
+                return match n { 0 => 1, _ => n * factorial(n - 1) };
+            }
+            let result = factorial(5);
+        "#;
+
+        assert_eq!(run(source, "result").unwrap(), Value::Number(120.0));
+    }
+
+    #[test]
+    fn test_runtime_error_trace() {
+        let source = "fn inner(x) {\n  return x / 0;\n}\nfn outer() {\n  return inner(1) + 1;\n}\nouter();";
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let statements = parser.parse_statements().unwrap();
+
+        let error = Interpreter::with_file("trace.pltps").interpret(&statements).unwrap_err();
+
+        assert_eq!(error.report(), "Traceback (most recent call last):
+  File \"trace.pltps\", line 7, in <script>
+  File \"trace.pltps\", line 5, in outer
+  File \"trace.pltps\", line 2, in inner
+DivisionByZero: Division by zero");
+    }
+
+    fn module_fixture(name: &str, files: &[(&str, &str)]) -> TestDir {
+        let directory = TestDir::new(name);
+
+        for (file, source) in files {
+            let path = directory.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+
+        return directory;
+    }
+
+    fn run_file(path: &PathBuf) -> Result<Interpreter, RuntimeError> {
+        let file = path.to_str().unwrap();
+        let (statements, _) = parse_module(path).unwrap();
+
+        let mut interpreter = Interpreter::with_file(file);
+        interpreter.add_search_path(path.parent().unwrap().to_path_buf());
+        interpreter.interpret(&statements)?;
+
+        return Ok(interpreter);
+    }
+
+    #[test]
+    fn test_imports_are_cached_and_bound() {
+        let directory = module_fixture("imports", &[
+            ("main.pltps", r#"
+                import "lib/util.pltps" as util;
+                import { double } from "./lib/util";
+                import "lib/util" as again;
+                let result = [util.double(2), double(3), util == again, util.name];
+            "#),
+            ("lib/util.pltps", r#"
+                export fn double(x) { return x * 2; }
+                export let name = "util";
+                let hidden = 1;
+            "#),
+        ]);
+
+        let interpreter = run_file(&directory.join("main.pltps")).unwrap();
+
+        assert_eq!(interpreter.environment.borrow().lookup(&Symbol::intern("result")).unwrap().to_string(), r#"[4, 6, true, "util"]"#);
+    }
+
+    #[test]
+    fn test_import_cycle_is_reported() {
+        let directory = module_fixture("cycle", &[
+            ("main.pltps", "import \"./a\" as a;"),
+            ("a.pltps", "import \"./b\" as b;"),
+            ("b.pltps", "import \"./a\" as a;"),
+        ]);
+
+        let error = run_file(&directory.join("main.pltps")).err().unwrap();
+        let chain: Vec<&str> = error.message.split(" -> ").map(|p| p.rsplit('/').next().unwrap()).collect();
+
+        assert_eq!(error.kind, ErrorKind::ImportError);
+        assert!(error.message.starts_with("Import cycle detected: "));
+        assert_eq!(chain[1..], ["b.pltps", "a.pltps"]);
+    }
+
+    #[test]
+    fn test_sandboxed_imports_stay_in_the_search_path() {
+        let directory = module_fixture("sandboxed_imports", &[
+            ("app/main.pltps", ""),
+            ("app/lib/util.pltps", "export fn double(x) { return x * 2; }"),
+            ("secret.pltps", "export let key = 42;"),
+        ]);
+        let secret = directory.join("secret.pltps");
+
+        let import = |capabilities: Capabilities, source: &str| {
+            let statements = Parser::new(Scanner::new(source).scan_tokens().unwrap()).parse_statements().unwrap();
+            let mut interpreter = Interpreter::with_capabilities(capabilities);
+            interpreter.set_file(directory.join("app/main.pltps").to_str().unwrap());
+
+            return interpreter.interpret(&statements).map(|value| value.to_string());
+        };
+
+        assert_eq!(import(Capabilities::none(), r#"import { double } from "lib/util"; import "./lib/util" as util; [double(2), util.double(3)];"#).unwrap(), "[4, 6]");
+
+        for path in ["../secret", "../missing", "lib/../../secret", secret.to_str().unwrap()] {
+            let error = import(Capabilities::none(), &format!("import \"{}\" as m;", path)).unwrap_err();
+
+            assert_eq!(error.kind, ErrorKind::CapabilityDenied);
+            assert_eq!(error.message, format!("Capability denied: importing '{}' from outside the search path needs the fs capability", path));
+        }
+
+        assert_eq!(import(Capabilities::default(), r#"import { key } from "../secret"; key;"#).unwrap(), "42");
+    }
+
+    #[test]
+    fn test_running_another_script_replaces_its_directory() {
+        let mut interpreter = Interpreter::new();
+        interpreter.add_search_path(PathBuf::from("lib"));
+        interpreter.set_file("first/main.pltps");
+        interpreter.set_file("second/main.pltps");
+        interpreter.add_search_path(PathBuf::from("vendor"));
+        interpreter.set_file("main.pltps");
+
+        assert_eq!(interpreter.search_paths, [PathBuf::from("."), PathBuf::from("lib"), PathBuf::from("vendor")]);
+    }
+
+    #[test]
+    fn test_registered_natives() {
+        let source = "let result = [sum(1, 2, 3), sum(), twice(4)];";
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let statements = parser.parse_statements().unwrap();
+
+        let mut interpreter = Interpreter::new();
+        interpreter.register_native("sum", Arity::Variadic, |_, arguments| {
+            let mut total = 0.0;
+
+            for argument in arguments {
+                match argument {
+                    Value::Number(n) => total += n,
+                    value => return Err(RuntimeError::new(ErrorKind::TypeError, format!("Cannot sum {}", value.type_name()), 0)),
+                }
+            }
+
+            Ok(Value::Number(total))
+        });
+        interpreter.register_native("twice", Arity::Fixed(1), |_, arguments| {
+            Ok(Value::list(vec![arguments[0].clone(), arguments[0].clone()]))
+        });
+        interpreter.interpret(&statements).unwrap();
+
+        assert_eq!(interpreter.environment.borrow().lookup(&Symbol::intern("result")).unwrap().to_string(), "[6, 0, [4, 4]]");
+
+        let error = interpreter.interpret(&Parser::new(Scanner::new("sum(1, \"a\");").scan_tokens().unwrap()).parse_statements().unwrap()).unwrap_err();
+        assert_eq!(error.to_string(), "TypeError at line 1: Cannot sum string");
+
+        let error = interpreter.interpret(&Parser::new(Scanner::new("twice();").scan_tokens().unwrap()).parse_statements().unwrap()).unwrap_err();
+        assert_eq!(error.to_string(), "TypeError at line 1: twice expected 1 arguments but got 0");
+    }
+}
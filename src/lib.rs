@@ -0,0 +1,37 @@
+#![allow(clippy::needless_return, clippy::needless_arbitrary_self_type, clippy::inherent_to_string, clippy::enum_variant_names)]
+
+pub mod scanner;
+pub mod exception;
+pub mod token;
+pub mod literal_value;
+pub mod token_type;
+pub mod expr;
+pub mod parser;
+pub mod pattern;
+pub mod stmt;
+pub mod value;
+pub mod environment;
+pub mod interpreter;
+pub mod module;
+pub mod capabilities;
+pub mod limits;
+pub mod bytecode;
+pub mod chunk;
+pub mod compiler;
+pub mod disassembler;
+pub mod vm;
+pub mod symbol;
+pub mod optimizer;
+mod stdlib;
+mod convert;
+mod engine;
+mod gc;
+#[cfg(test)]
+mod test_dir;
+
+pub use crate::capabilities::Capabilities;
+pub use crate::engine::{Engine, EngineError};
+pub use crate::exception::{ErrorKind, RuntimeError};
+pub use crate::interpreter::{Backend, Interpreter, OptLevel};
+pub use crate::limits::{CancelHandle, Limits};
+pub use crate::value::{Arity, Value};
@@ -0,0 +1,251 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::gc;
+
+// Bounds on what one run of a script may use, for running code that isn't trusted. Going
+// over one ends the script with an error `catch` can't handle and `finally` blocks don't
+// run for. Nothing is limited by default.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Limits {
+    // Steps the script may take: VM instructions, or statements and expressions evaluated
+    // on the tree-walker.
+    pub steps: Option<u64>,
+    // Time since the run started.
+    pub timeout: Option<Duration>,
+    // Lists, maps, scopes and captured variables alive at once, as counted by the collector.
+    // The heap is per thread, so this counts every interpreter on it.
+    pub heap_objects: Option<usize>,
+    // Bytes allocated for strings and for the elements of lists and maps since the run
+    // started. Memory freed along the way still counts, so this bounds how much the run
+    // allocates rather than how much it holds at once. It's checked every step, and one step
+    // can go over by what it allocates before it's stopped.
+    pub allocated_bytes: Option<usize>,
+    // Calls nested inside each other. Unlike `Engine::set_max_depth`, this ends the script.
+    pub call_depth: Option<usize>,
+}
+
+// Stops a running script from another thread. Cancelling while nothing runs stops the next
+// run as soon as it starts.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    // Clears the request once it has stopped a script.
+    fn take(&self) -> bool {
+        return self.cancelled.swap(false, Ordering::Relaxed);
+    }
+}
+
+// The clock, the heap and cancelling are checked every this many steps, which keeps the
+// cost of a step down to counting it.
+const CHECK_INTERVAL: u64 = 1024;
+
+// Natives that block check the clock and cancelling at least this often.
+const SLEEP_SLICE: Duration = Duration::from_millis(10);
+
+// What the current run has used so far.
+#[derive(Debug, Default)]
+pub(crate) struct Meter {
+    steps: u64,
+    deadline: Option<Instant>,
+    bytes_at_start: usize,
+}
+
+impl Meter {
+    pub(crate) fn start(&mut self, limits: &Limits) {
+        self.steps = 0;
+        self.deadline = limits.timeout.map(|timeout| Instant::now() + timeout);
+        self.bytes_at_start = gc::allocated_bytes();
+    }
+
+    pub(crate) fn step(&mut self, limits: &Limits, cancel: &CancelHandle) -> Result<(), RuntimeError> {
+        self.steps += 1;
+
+        if let Some(steps) = limits.steps.filter(|steps| self.steps > *steps) {
+            return Err(terminate(ErrorKind::StepLimitExceeded, format!("Step limit of {} exceeded", steps)));
+        }
+
+        // Checked every step, as a single step can double a string.
+        if let Some(bytes) = limits.allocated_bytes {
+            if gc::allocated_bytes().wrapping_sub(self.bytes_at_start) > bytes {
+                return Err(terminate(ErrorKind::HeapLimitExceeded, format!("Allocation limit of {} bytes exceeded", bytes)));
+            }
+        }
+
+        if !self.steps.is_multiple_of(CHECK_INTERVAL) {
+            return Ok(());
+        }
+
+        self.check_clock(limits, cancel)?;
+
+        if let Some(objects) = limits.heap_objects {
+            // The heap's count includes garbage until a collection finds it.
+            if gc::allocated() > objects {
+                gc::collect();
+            }
+
+            if gc::allocated() > objects {
+                return Err(terminate(ErrorKind::HeapLimitExceeded, format!("Heap limit of {} objects exceeded", objects)));
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Sleeps in slices, so a script blocked in a native still stops at its deadline or when
+    // it is cancelled.
+    pub(crate) fn sleep(&self, duration: Duration, limits: &Limits, cancel: &CancelHandle) -> Result<(), RuntimeError> {
+        // Too far off to represent is as good as forever.
+        let wake = Instant::now().checked_add(duration);
+
+        loop {
+            self.check_clock(limits, cancel)?;
+
+            let now = Instant::now();
+            if wake.is_some_and(|wake| now >= wake) {
+                return Ok(());
+            }
+
+            thread::sleep(wake.map_or(SLEEP_SLICE, |wake| wake - now).min(SLEEP_SLICE));
+        }
+    }
+
+    fn check_clock(&self, limits: &Limits, cancel: &CancelHandle) -> Result<(), RuntimeError> {
+        if cancel.take() {
+            return Err(terminate(ErrorKind::Cancelled, "Script was cancelled".to_string()));
+        }
+
+        if let (Some(deadline), Some(timeout)) = (self.deadline, limits.timeout) {
+            if Instant::now() >= deadline {
+                return Err(terminate(ErrorKind::Timeout, format!("Timed out after {:?}", timeout)));
+            }
+        }
+
+        return Ok(());
+    }
+}
+
+fn terminate(kind: ErrorKind, message: String) -> RuntimeError {
+    return RuntimeError::new(kind, message, 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capabilities::Capabilities;
+    use crate::engine::{Engine, EngineError};
+    use crate::interpreter::Backend;
+    use super::*;
+
+    // Runs the script on both backends and returns the kind of error that stopped it, after
+    // checking `catch` didn't handle it and `finally` didn't run.
+    fn stopped_by(limits: Limits, source: &str) -> ErrorKind {
+        let mut kinds = vec![];
+
+        for backend in [Backend::Tree, Backend::Vm] {
+            let mut engine = Engine::new();
+            engine.set_backend(backend);
+            engine.set_limits(limits.clone());
+            engine.eval("let log = [];").unwrap();
+
+            let source = format!("{}\nfn guarded() {{ try {{ return run(); }} catch (e) {{ log.push(\"caught\"); }} finally {{ log.push(\"finally\"); }} }}", source);
+            engine.eval(&source).unwrap();
+
+            match engine.call("guarded", vec![]) {
+                Err(EngineError::Runtime(error)) => kinds.push(error.kind),
+                result => panic!("expected the script to be stopped, got {:?}", result),
+            }
+
+            assert_eq!(engine.get_global("log").unwrap().to_string(), "[]");
+        }
+
+        assert_eq!(kinds[0], kinds[1]);
+
+        return kinds.remove(0);
+    }
+
+    const SPIN: &str = "fn spin(n) { return spin(n + 1); }\nfn run() { return spin(0); }";
+
+    #[test]
+    fn test_step_limit_and_timeout() {
+        let steps = Limits { steps: Some(10_000), ..Limits::default() };
+        assert_eq!(stopped_by(steps, SPIN), ErrorKind::StepLimitExceeded);
+
+        let timeout = Limits { timeout: Some(Duration::from_millis(20)), ..Limits::default() };
+        assert_eq!(stopped_by(timeout, SPIN), ErrorKind::Timeout);
+    }
+
+    #[test]
+    fn test_heap_and_call_depth_limits() {
+        let heap = Limits { heap_objects: Some(500), ..Limits::default() };
+        let grow = "fn grow(xs, n) { return grow([xs, n], n + 1); }\nfn run() { return grow([], 0); }";
+        assert_eq!(stopped_by(heap, grow), ErrorKind::HeapLimitExceeded);
+
+        let bytes = Limits { allocated_bytes: Some(1 << 14), ..Limits::default() };
+        let double = "fn double(s) { return double(s + s); }\nfn run() { return double(\"x\"); }";
+        assert_eq!(stopped_by(bytes.clone(), double), ErrorKind::HeapLimitExceeded);
+
+        let push = "fn fill(xs) { xs.push(xs.len()); return fill(xs); }\nfn run() { return fill([]); }";
+        assert_eq!(stopped_by(bytes, push), ErrorKind::HeapLimitExceeded);
+
+        let depth = Limits { call_depth: Some(30), ..Limits::default() };
+        let recurse = "fn down(n) { return 1 + down(n - 1); }\nfn run() { return down(0); }";
+        assert_eq!(stopped_by(depth, recurse), ErrorKind::CallDepthExceeded);
+    }
+
+    #[test]
+    fn test_blocking_natives_stop_at_the_deadline() {
+        let started = Instant::now();
+        let timeout = Limits { timeout: Some(Duration::from_millis(50)), ..Limits::default() };
+        assert_eq!(stopped_by(timeout.clone(), "fn run() { time.sleep(3000); return 1; }"), ErrorKind::Timeout);
+
+        let mut engine = Engine::with_capabilities(Capabilities::all());
+        engine.set_limits(timeout);
+
+        match engine.eval(r#"process.run("sleep", ["3"]);"#) {
+            Err(EngineError::Runtime(error)) => assert_eq!(error.kind, ErrorKind::Timeout),
+            result => panic!("expected the script to time out, got {:?}", result),
+        }
+
+        match engine.eval("input();") {
+            Err(EngineError::Runtime(error)) => assert_eq!(error.message, "input can't wait for stdin under a timeout"),
+            result => panic!("expected input to be refused, got {:?}", result),
+        }
+
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_cancelling_from_another_thread() {
+        let sleep = "time.sleep(3000);".to_string();
+
+        for source in [format!("{}\nrun();", SPIN), sleep] {
+            for backend in [Backend::Tree, Backend::Vm] {
+                let mut engine = Engine::new();
+                engine.set_backend(backend);
+
+                let handle = engine.cancel_handle();
+                let canceller = thread::spawn(move || {
+                    thread::sleep(Duration::from_millis(20));
+                    handle.cancel();
+                });
+
+                match engine.eval(&source) {
+                    Err(EngineError::Runtime(error)) => assert_eq!(error.kind, ErrorKind::Cancelled),
+                    result => panic!("expected the script to be cancelled, got {:?}", result),
+                }
+
+                canceller.join().unwrap();
+                assert_eq!(engine.eval("1 + 1;").unwrap().to_string(), "2");
+            }
+        }
+    }
+}
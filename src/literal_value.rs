@@ -1,10 +1,12 @@
+use crate::symbol::Symbol;
+
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum LiteralValue {
     IntegerValue(i64),
     FloatValue(f64),
-    StringValue(String),
-    IdentifierValue(String),
+    StringValue(Symbol),
+    IdentifierValue(Symbol),
 }
 
 impl std::fmt::Display for LiteralValue {
@@ -1,40 +1,112 @@
-mod scanner;
-mod exception;
-mod token;
-mod literal_value;
-mod token_type;
-mod expr;
-mod parser;
-
-use crate::scanner::*;
+#![allow(clippy::needless_return)]
 
 use std::{env, fs, io};
 use std::io::{stdout, Write};
+use std::path::PathBuf;
 use std::process::exit;
-use crate::parser::Parser;
+use platypus_lang::{Backend, Capabilities, Engine, EngineError, OptLevel, Value};
+
+#[derive(Default)]
+struct Options {
+    capabilities: Capabilities,
+    backend: Backend,
+    opt_level: OptLevel,
+    trace: bool,
+    dump_bytecode: bool,
+    gc_threshold: Option<usize>,
+    max_depth: Option<usize>,
+}
 
 fn main() -> Result<(), String> {
-    let args: Vec<String> = env::args().collect();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = Options::default();
+    let mut rest = args.as_slice();
+
+    // Interpreter flags come before the script; everything after it belongs to the script.
+    while let Some(flag) = rest.first().filter(|arg| arg.starts_with('-')) {
+        match flag.as_str() {
+            "--allow-run" => options.capabilities.subprocess = true,
+            "--backend=tree" => options.backend = Backend::Tree,
+            "--backend=vm" => options.backend = Backend::Vm,
+            "-O0" => options.opt_level = OptLevel::O0,
+            "-O1" => options.opt_level = OptLevel::O1,
+            // Only the VM has instructions to trace.
+            "--trace" => {
+                options.trace = true;
+                options.backend = Backend::Vm;
+            },
+            "--dump-bytecode" => options.dump_bytecode = true,
+            flag if flag.starts_with("--gc-threshold=") => match flag["--gc-threshold=".len()..].parse() {
+                Ok(threshold) => options.gc_threshold = Some(threshold),
+                Err(_) => usage(),
+            },
+            flag if flag.starts_with("--max-depth=") => match flag["--max-depth=".len()..].parse() {
+                Ok(depth) => options.max_depth = Some(depth),
+                Err(_) => usage(),
+            },
+            _ => usage(),
+        }
+
+        rest = &rest[1..];
+    }
+
+    if rest.first().is_some_and(|command| command == "compile") {
+        return compile(&rest[1..], &options);
+    }
 
-    return match args.len() {
-        1 => run_prompt(),
-        2 => {
-            match run_file(&args[1]) {
+    return match rest.split_first() {
+        None => run_prompt(&options),
+        Some((path, _)) if options.dump_bytecode => {
+            match dump_bytecode(path, &options) {
+                Ok(bytecode) => print!("{}", bytecode),
+                Err(error) => {
+                    println!("Error: \n{}", error.to_string());
+                    exit(1);
+                }
+            }
+
+            Ok(())
+        },
+        Some((path, script_args)) => {
+            match run_file(path, script_args.to_vec(), &options) {
                 Ok(_) => exit(0),
-                Err(msg) => {
-                    println!("Error: \n{}", msg);
+                Err(EngineError::Exit(code)) => exit(code),
+                Err(error) => {
+                    println!("Error: \n{}", error.to_string());
                     exit(1);
                 }
             }
         }
-        _ => {
-            println!("Usage: `platypus [script]` or `platypus`");
-            exit(64);
-        }
     };
 }
 
-fn run_prompt() -> Result<(), String> {
+fn usage() -> ! {
+    println!("Usage: `platypus [--allow-run] [--backend=tree|vm] [-O0|-O1] [--trace] [--dump-bytecode] [--gc-threshold=objects] [--max-depth=calls] [script [args...]]`, `platypus compile script -o out.pltc` or `platypus`");
+    exit(64);
+}
+
+// `platypus compile in.pltps -o out.pltc`, where the output defaults to the input with a `.pltc` extension.
+fn compile(args: &[String], options: &Options) -> Result<(), String> {
+    let (input, output) = match args {
+        [input] => (input, PathBuf::from(input).with_extension("pltc")),
+        [input, flag, output] if flag == "-o" => (input, PathBuf::from(output)),
+        _ => usage(),
+    };
+
+    let mut engine = new_engine(options);
+    match engine.compile_file(input).and_then(|bytes| fs::write(&output, bytes).map_err(|e| EngineError::Io(format!("{}: {}", output.display(), e)))) {
+        Ok(()) => exit(0),
+        Err(error) => {
+            println!("Error: \n{}", error.to_string());
+            exit(1);
+        }
+    }
+}
+
+fn run_prompt(options: &Options) -> Result<(), String> {
+    let mut engine = new_engine(options);
+    engine.add_search_path(PathBuf::from("."));
+
     loop {
         println!("platypus> ");
         stdout().flush().expect("TODO: panic message");
@@ -45,26 +117,50 @@ fn run_prompt() -> Result<(), String> {
             return Ok(());
         }
 
-        match run(&input) {
-            Ok(_) => (),
-            Err(msg) => println!("{}", msg),
+        match engine.eval(&input) {
+            Ok(Value::Null) => (),
+            Ok(value) => println!("{}", value.to_string()),
+            Err(EngineError::Exit(code)) => exit(code),
+            Err(error) => println!("{}", error.to_string()),
         }
     }
 }
 
-fn run_file(path: &str) -> Result<(), String> {
-    return match fs::read_to_string(path) {
-        Err(msg) => Err(msg.to_string()),
-        Ok(input) => run(&input),
-    }
+fn run_file(path: &str, args: Vec<String>, options: &Options) -> Result<(), EngineError> {
+    let mut engine = new_engine(options);
+    engine.set_args(args);
+
+    return engine.run_file(path).map(|_| ());
 }
 
-fn run(input: &str) -> Result<(), String> {
-    let mut scanner = Scanner::new(input);
-    let tokens = scanner.scan_tokens()?;
-    let mut parser = Parser::new(tokens);
+fn dump_bytecode(path: &str, options: &Options) -> Result<String, EngineError> {
+    let source = fs::read_to_string(path).map_err(|e| EngineError::Io(format!("{}: {}", path, e)))?;
+    let mut engine = new_engine(options);
 
-    parser.parse().unwrap().print();
+    return engine.disassemble(&source);
+}
+
+fn new_engine(options: &Options) -> Engine {
+    let mut engine = Engine::with_capabilities(options.capabilities.clone());
+    engine.set_backend(options.backend);
+    engine.set_opt_level(options.opt_level);
+    engine.set_trace(options.trace);
+    // Warnings come out before the code they're about runs, so they aren't lost after its output.
+    engine.set_warning_handler(|warning| println!("{}", warning));
+
+    if let Some(threshold) = options.gc_threshold {
+        engine.set_gc_threshold(threshold);
+    }
+
+    if let Some(depth) = options.max_depth {
+        engine.set_max_depth(depth);
+    }
+
+    if let Some(paths) = env::var_os("PLATYPUS_PATH") {
+        for path in env::split_paths(&paths) {
+            engine.add_search_path(path);
+        }
+    }
 
-    return Ok(());
+    return engine;
 }
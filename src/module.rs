@@ -0,0 +1,101 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use crate::value::Value;
+
+#[derive(Debug)]
+pub struct Module {
+    pub name: String,
+    pub exports: BTreeMap<String, Value>,
+    // Set on the stand-in for a builtin module whose capability is turned off, naming the
+    // capability. Reading from or importing it raises a `CapabilityDenied` error.
+    pub denied: Option<&'static str>,
+}
+
+// Paths starting with `./` or `../` are relative to the importing file, anything else
+// is looked up in each search path in order. A missing extension defaults to `.pltps`.
+pub fn resolve(path: &str, importer: &str, search_paths: &[PathBuf]) -> Result<PathBuf, String> {
+    let mut relative = PathBuf::from(path);
+
+    if relative.extension().is_none() {
+        relative.set_extension("pltps");
+    }
+
+    let candidates: Vec<PathBuf> = if relative.is_absolute() {
+        vec![relative]
+    } else if path.starts_with("./") || path.starts_with("../") {
+        vec![directory_of(importer).join(&relative)]
+    } else {
+        search_paths.iter().map(|directory| directory.join(&relative)).collect()
+    };
+
+    for candidate in &candidates {
+        if candidate.is_file() {
+            return candidate.canonicalize().map_err(|e| e.to_string());
+        }
+    }
+
+    let searched: Vec<String> = candidates.iter().map(|c| c.display().to_string()).collect();
+
+    return Err(format!("Cannot find module '{}' (looked for {})", path, searched.join(", ")));
+}
+
+// Whether every file `resolve` could try for an import lies inside one of the search paths.
+// Decided from the paths alone, so refusing an import doesn't tell the script whether the
+// file exists.
+pub fn within_search_paths(path: &str, importer: &str, search_paths: &[PathBuf]) -> bool {
+    let relative = Path::new(path);
+    let roots: Vec<PathBuf> = search_paths.iter().map(|directory| normalize(directory)).collect();
+    let inside = |candidate: PathBuf| {
+        let candidate = normalize(&candidate);
+        return roots.iter().any(|root| candidate.starts_with(root));
+    };
+
+    if relative.is_absolute() {
+        return false;
+    } else if path.starts_with("./") || path.starts_with("../") {
+        return inside(directory_of(importer).join(relative));
+    } else {
+        return search_paths.iter().all(|directory| inside(directory.join(relative)));
+    }
+}
+
+// Makes a path absolute and removes its `.` and `..` components without touching the
+// filesystem.
+fn normalize(path: &Path) -> PathBuf {
+    let absolute = match env::current_dir() {
+        Ok(current) => current.join(path),
+        Err(_) => path.to_path_buf(),
+    };
+    let mut normalized = PathBuf::new();
+
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            component => normalized.push(component),
+        }
+    }
+
+    return normalized;
+}
+
+pub fn directory_of(file: &str) -> PathBuf {
+    return match Path::new(file).parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// Shortens a resolved module path for diagnostics by dropping the working directory.
+pub fn display_path(path: &Path) -> String {
+    if let Ok(current) = env::current_dir() {
+        if let Ok(relative) = path.strip_prefix(current) {
+            return relative.display().to_string();
+        }
+    }
+
+    return path.display().to_string();
+}
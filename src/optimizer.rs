@@ -0,0 +1,302 @@
+use std::rc::Rc;
+use crate::exception::RuntimeError;
+use crate::expr::{Expr, ExpressionLiteralValue, MatchArm};
+use crate::interpreter::{binary, unary};
+use crate::pattern::Pattern;
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::value::Value;
+
+// Simplifies a program before it runs, on either backend:
+//  - operators on literals are evaluated, so `-123 * (420.69)` becomes one number and `!true`
+//    becomes `false`. Operations that would fail are left for the runtime to report;
+//  - statements after a `return` or `throw` are removed;
+//  - match arms whose guard is always false are removed, and guards that are always true
+//    dropped. There's no `if` statement, so these stand in for `if (false) { ... }`;
+//  - a match on a literal becomes the arm it always takes.
+// Removing code the programmer wrote is worth telling them about, so those produce warnings.
+pub struct Optimizer {
+    pub warnings: Vec<String>,
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self {
+            warnings: vec![],
+        }
+    }
+
+    pub fn optimize(&mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        let mut optimized = vec![];
+        let mut statements = statements.into_iter().peekable();
+
+        while let Some(statement) = statements.next() {
+            let statement = self.statement(statement);
+
+            let exit = match &statement {
+                Stmt::Return { keyword, value: _ } | Stmt::Throw { keyword, value: _ } => Some(keyword.clone()),
+                _ => None,
+            };
+
+            optimized.push(statement);
+
+            if let (Some(keyword), Some(_)) = (exit, statements.peek()) {
+                self.warnings.push(format!("Warning at line {}: unreachable code after {}", keyword.line_number, keyword.lexeme));
+                break;
+            }
+        }
+
+        return optimized;
+    }
+
+    fn statement(&mut self, statement: Stmt) -> Stmt {
+        return match statement {
+            Stmt::Expression { expression } => Stmt::Expression { expression: self.expression(expression) },
+            Stmt::Print { expression } => Stmt::Print { expression: self.expression(expression) },
+            Stmt::Let { keyword, pattern, initializer } => Stmt::Let {
+                keyword,
+                pattern,
+                initializer: initializer.map(|initializer| self.expression(initializer)),
+            },
+            Stmt::Block { statements } => Stmt::Block { statements: self.optimize(statements) },
+            Stmt::Function { name, params, body } => {
+                // Freshly parsed bodies aren't shared; anything else is left as it is.
+                let body = match Rc::try_unwrap(body) {
+                    Ok(body) => Rc::new(self.optimize(body)),
+                    Err(body) => body,
+                };
+
+                Stmt::Function { name, params, body }
+            },
+            Stmt::Return { keyword, value } => Stmt::Return { keyword, value: value.map(|value| self.expression(value)) },
+            Stmt::Export { declaration } => Stmt::Export { declaration: Box::new(self.statement(*declaration)) },
+            Stmt::Throw { keyword, value } => Stmt::Throw { keyword, value: self.expression(value) },
+            Stmt::Try { body, catch_name, catch_body, finally_body } => Stmt::Try {
+                body: self.optimize(body),
+                catch_name,
+                catch_body: catch_body.map(|body| self.optimize(body)),
+                finally_body: finally_body.map(|body| self.optimize(body)),
+            },
+            statement @ (Stmt::Enum { .. } | Stmt::Import { .. }) => statement,
+        }
+    }
+
+    fn expression(&mut self, expression: Expr) -> Expr {
+        return match expression {
+            Expr::Binary { left, operator, right } => {
+                let left = self.expression(*left);
+                let right = self.expression(*right);
+
+                match (literal_value(&left), literal_value(&right)) {
+                    (Some(a), Some(b)) => match fold(binary(&operator.token_type, operator.lexeme.as_str(), operator.line_number, a, b)) {
+                        Some(value) => Expr::Literal { value },
+                        None => Expr::Binary { left: Box::new(left), operator, right: Box::new(right) },
+                    },
+                    _ => Expr::Binary { left: Box::new(left), operator, right: Box::new(right) },
+                }
+            },
+            Expr::Grouping { expression } => match self.expression(*expression) {
+                literal @ Expr::Literal { .. } => literal,
+                expression => Expr::Grouping { expression: Box::new(expression) },
+            },
+            Expr::Unary { operator, right } => {
+                let right = self.expression(*right);
+
+                match literal_value(&right).and_then(|value| fold(unary(&operator.token_type, operator.lexeme.as_str(), operator.line_number, value))) {
+                    Some(value) => Expr::Literal { value },
+                    None => Expr::Unary { operator, right: Box::new(right) },
+                }
+            },
+            Expr::Call { callee, paren, arguments } => Expr::Call {
+                callee: Box::new(self.expression(*callee)),
+                paren,
+                arguments: arguments.into_iter().map(|argument| self.expression(argument)).collect(),
+            },
+            Expr::Get { object, name } => Expr::Get { object: Box::new(self.expression(*object)), name },
+            Expr::List { elements } => Expr::List {
+                elements: elements.into_iter().map(|element| self.expression(element)).collect(),
+            },
+            Expr::Map { entries } => Expr::Map {
+                entries: entries.into_iter().map(|(key, value)| (key, self.expression(value))).collect(),
+            },
+            Expr::Match { keyword, subject, arms } => {
+                let subject = self.expression(*subject);
+                let mut optimized = vec![];
+
+                for arm in arms {
+                    let arm = MatchArm {
+                        pattern: arm.pattern,
+                        guard: arm.guard.map(|guard| self.expression(guard)),
+                        body: self.expression(arm.body),
+                    };
+
+                    if let Some(arm) = self.constant_guard(&keyword, arm) {
+                        optimized.push(arm);
+                    }
+                }
+
+                self.constant_match(keyword, subject, optimized)
+            },
+            expression @ (Expr::Literal { .. } | Expr::Variable { .. }) => expression,
+        }
+    }
+
+    // A guard that is a literal always or never lets its arm run, so the arm either loses the
+    // guard or is removed. Without an `if` statement, this is how a branch that can't run
+    // looks: `_ if false => ...`.
+    fn constant_guard(&mut self, keyword: &Token, arm: MatchArm) -> Option<MatchArm> {
+        return match arm.guard.as_ref().and_then(literal_value) {
+            Some(guard) if !guard.is_truthy() => {
+                self.warnings.push(format!("Warning at line {}: unreachable match arm {}, its guard is always false", keyword.line_number, arm.pattern.to_string()));
+                None
+            },
+            Some(_) => Some(MatchArm { guard: None, ..arm }),
+            None => Some(arm),
+        }
+    }
+
+    // Replaces a match on a literal with the arm it takes, when every arm up to that one is a
+    // literal or `_` without a guard, so which arm runs is certain.
+    fn constant_match(&mut self, keyword: Token, subject: Expr, mut arms: Vec<MatchArm>) -> Expr {
+        // The first arm that doesn't certainly fail has to certainly match.
+        let taken = literal_value(&subject).and_then(|value| {
+            let outcomes: Vec<Option<bool>> = arms.iter().map(|arm| arm_matches(arm, &value)).collect();
+
+            outcomes.iter().position(|outcome| *outcome != Some(false)).filter(|index| outcomes[*index] == Some(true))
+        });
+
+        let taken = match taken {
+            Some(index) => index,
+            None => return Expr::Match { keyword, subject: Box::new(subject), arms },
+        };
+
+        for (index, arm) in arms.iter().enumerate() {
+            if index != taken {
+                self.warnings.push(format!("Warning at line {}: unreachable match arm {}", keyword.line_number, arm.pattern.to_string()));
+            }
+        }
+
+        return arms.swap_remove(taken).body;
+    }
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+fn literal_value(expression: &Expr) -> Option<Value> {
+    return match expression {
+        Expr::Literal { value } => Some(Value::from_literal(value)),
+        _ => None,
+    }
+}
+
+// Turns the result of evaluating an operator back into a literal, unless it failed.
+fn fold(result: Result<Value, RuntimeError>) -> Option<ExpressionLiteralValue> {
+    return match result {
+        Ok(Value::Number(n)) => Some(ExpressionLiteralValue::Number(n)),
+        Ok(Value::StringValue(s)) => Some(ExpressionLiteralValue::StringValue(Symbol::intern(&s))),
+        Ok(Value::Boolean(true)) => Some(ExpressionLiteralValue::True),
+        Ok(Value::Boolean(false)) => Some(ExpressionLiteralValue::False),
+        Ok(Value::Null) => Some(ExpressionLiteralValue::Null),
+        _ => None,
+    }
+}
+
+// Whether an arm certainly matches the value, certainly doesn't, or None when that depends on
+// more than the value.
+fn arm_matches(arm: &MatchArm, value: &Value) -> Option<bool> {
+    let matches = match &arm.pattern {
+        Pattern::Wildcard => true,
+        Pattern::Literal { value: literal } => Value::from_literal(literal) == *value,
+        _ => return None,
+    };
+
+    return match (matches, &arm.guard) {
+        (true, Some(_)) => None,
+        (matches, _) => Some(matches),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interpreter::{Backend, Interpreter};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use super::*;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        return Parser::new(Scanner::new(source).scan_tokens().unwrap()).parse_statements().unwrap();
+    }
+
+    fn optimize(source: &str) -> (String, Vec<String>) {
+        let mut optimizer = Optimizer::new();
+        let statements: Vec<String> = optimizer.optimize(parse(source)).iter().map(|s| s.to_string()).collect();
+
+        return (statements.join(" "), optimizer.warnings);
+    }
+
+    fn run(statements: Vec<Stmt>, backend: Backend) -> String {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_backend(backend);
+
+        return match interpreter.interpret(&statements) {
+            Ok(value) => value.to_string(),
+            Err(error) => error.report(),
+        }
+    }
+
+    #[test]
+    fn test_constant_folding() {
+        assert_eq!(optimize("-123 * (420.69);").0, "-51744.87");
+        assert_eq!(optimize("[!true, !null, 1 + 2 == 3, \"a\" + \"b\", (1 < 2) != false];").0, "[false true true ab true]");
+        assert_eq!(optimize("x + 2 * 3;").0, "(+ x 6)");
+        assert_eq!(optimize("-\"a\"; 1 / 0;").0, "(- a) (/ 1 0)");
+        assert_eq!(optimize("match 2 { 1 => \"one\", 2 => \"two\", _ => \"many\" };"), (
+            "two".to_string(),
+            vec!["Warning at line 1: unreachable match arm 1".to_string(), "Warning at line 1: unreachable match arm _".to_string()],
+        ));
+        assert_eq!(optimize("match true { x if x => 1, _ => 2 };").1, Vec::<String>::new());
+        assert_eq!(optimize("match x { [a] if 1 > 2 => a, _ if !false => 1, _ => 2 };"), (
+            "(match x (_ => 1) (_ => 2))".to_string(),
+            vec!["Warning at line 1: unreachable match arm [a], its guard is always false".to_string()],
+        ));
+        assert_eq!(optimize("match 2 { n if null => n, 2 if true => \"two\", _ => 0 };").0, "two");
+    }
+
+    #[test]
+    fn test_unreachable_code_is_removed() {
+        let (optimized, warnings) = optimize("fn f() {\n  return 1;\n  print 2;\n}\nfn g() { throw \"no\"; let x = 1; }");
+
+        assert_eq!(optimized, "(fn f () (return 1)) (fn g () (throw no))");
+        assert_eq!(warnings, vec![
+            "Warning at line 2: unreachable code after return".to_string(),
+            "Warning at line 5: unreachable code after throw".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn test_optimized_programs_behave_the_same() {
+        let programs = [
+            "fn area(r) { return 3 * r * r; } [area(2), 2 * (3 + 4), !(1 > 2)];",
+            "fn first(xs) { return match xs { [x, ..._] => x, _ => null }; return 0; } [first([1, 2]), first([])];",
+            "fn f() { return match \"b\" { \"a\" => 1, \"b\" => 2 }; } f();",
+            "fn f() { throw 1 + 1; print \"never\"; } f();",
+            "let x = 1 / 0;",
+            "match 3 { 1 => 1, 2 => 2 };",
+            "fn f(x) { return match x { n if 2 < 1 => n, [n] if true => n, _ => 0 }; } [f(1), f([2])];",
+            "match 3 { n if false => n };",
+        ];
+
+        for program in programs {
+            for backend in [Backend::Tree, Backend::Vm] {
+                let optimized = Optimizer::new().optimize(parse(program));
+
+                assert_eq!(run(optimized, backend), run(parse(program), backend), "optimizing changed {}", program);
+            }
+        }
+    }
+}
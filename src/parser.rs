@@ -1,18 +1,33 @@
-use crate::expr::{Expr, ExpressionLiteralValue};
-use crate::expr::Expr::{Binary, Grouping, Literal, Unary};
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::expr::{Expr, ExpressionLiteralValue, MatchArm};
+use crate::expr::Expr::{Binary, Call, Get, Grouping, List, Literal, Map, Match, Unary, Variable};
+use crate::pattern::{MapPatternEntry, Pattern};
+use crate::literal_value::LiteralValue;
+use crate::stmt::{EnumVariant, Stmt};
+use crate::symbol::Symbol;
 use crate::token::Token;
 use crate::token_type::TokenType;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    // The variants of each enum declared so far, with their field counts.
+    enums: HashMap<Symbol, Vec<(Symbol, usize)>>,
+    function_depth: usize,
+    block_depth: usize,
+    pub warnings: Vec<String>,
 }
 
 impl Parser {
     pub fn new (tokens: Vec<Token>) -> Self {
         Self {
             tokens,
-            current: 0
+            current: 0,
+            enums: HashMap::new(),
+            function_depth: 0,
+            block_depth: 0,
+            warnings: vec![],
         }
     }
 
@@ -20,6 +35,291 @@ impl Parser {
         return self.expression();
     }
 
+    pub fn parse_statements(&mut self) -> Result<Vec<Stmt>, String> {
+        let mut statements = vec![];
+        let mut errors = vec![];
+
+        while ! self.is_at_end() {
+            match self.declaration() {
+                Ok(statement) => statements.push(statement),
+                Err(msg) => {
+                    errors.push(msg);
+                    self.synchronize();
+                }
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors.join("\n"));
+        }
+
+        return Ok(statements);
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, String> {
+        if self.match_token_type(vec![TokenType::Let]) {
+            return self.let_declaration();
+        }
+
+        if self.match_token_type(vec![TokenType::Enum]) {
+            return self.enum_declaration();
+        }
+
+        if self.match_token_type(vec![TokenType::Fn]) {
+            return self.function_declaration();
+        }
+
+        if self.match_token_type(vec![TokenType::Import]) {
+            return self.import_declaration();
+        }
+
+        if self.match_token_type(vec![TokenType::Export]) {
+            let keyword = self.previous();
+
+            if self.block_depth > 0 {
+                return Err(self.error(&keyword, "Exports are only allowed at the top level of a module"));
+            }
+
+            let declaration = match self.peek().token_type {
+                TokenType::Let | TokenType::Fn | TokenType::Enum => self.declaration()?,
+                _ => return Err(self.error(&keyword, "Expected 'let', 'fn' or 'enum' after 'export'")),
+            };
+
+            return Ok(Stmt::Export { declaration: Box::from(declaration) });
+        }
+
+        return self.statement();
+    }
+
+    fn let_declaration(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        let pattern = self.pattern()?;
+
+        if ! pattern.is_destructuring() {
+            return Err(self.error(&keyword, "Only names, lists and maps can be destructured by let"));
+        }
+
+        let initializer = if self.match_token_type(vec![TokenType::Equal]) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+
+        self.consume_token(TokenType::Semicolon, "Expected ';' after variable declaration")?;
+
+        return Ok(Stmt::Let { keyword, pattern: Box::from(pattern), initializer });
+    }
+
+    fn import_declaration(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+        let mut alias = None;
+        let mut names = vec![];
+
+        let path = if self.match_token_type(vec![TokenType::LeftBrace]) {
+            while ! self.check(TokenType::RightBrace) && ! self.is_at_end() {
+                names.push(self.consume_token(TokenType::Identifier, "Expected imported name")?);
+
+                if ! self.match_token_type(vec![TokenType::Comma]) {
+                    break;
+                }
+            }
+
+            self.consume_token(TokenType::RightBrace, "Expected '}' after imported names")?;
+            self.consume_contextual_keyword("from")?;
+            self.import_path()?
+        } else {
+            let path = self.import_path()?;
+            self.consume_contextual_keyword("as")?;
+            alias = Some(self.consume_token(TokenType::Identifier, "Expected module alias after 'as'")?);
+            path
+        };
+
+        self.consume_token(TokenType::Semicolon, "Expected ';' after import")?;
+
+        return Ok(Stmt::Import { keyword, path, alias, names });
+    }
+
+    fn import_path(&mut self) -> Result<String, String> {
+        let token = self.consume_token(TokenType::String, "Expected module path string")?;
+
+        return match token.literal {
+            Some(LiteralValue::StringValue(path)) => Ok(path.to_string()),
+            _ => Err(self.error(&token, "Expected module path string")),
+        }
+    }
+
+    // `as` and `from` are only keywords inside imports, so they stay usable as names elsewhere.
+    fn consume_contextual_keyword(&mut self, keyword: &str) -> Result<Token, String> {
+        let token = self.peek();
+
+        if token.token_type != TokenType::Identifier || token.lexeme != keyword {
+            return Err(self.error(&token, &format!("Expected '{}'", keyword)));
+        }
+
+        return Ok(self.advance());
+    }
+
+    fn function_declaration(&mut self) -> Result<Stmt, String> {
+        let name = self.consume_token(TokenType::Identifier, "Expected function name")?;
+        self.consume_token(TokenType::LeftParen, "Expected '(' after function name")?;
+
+        let mut params = vec![];
+
+        while ! self.check(TokenType::RightParen) && ! self.is_at_end() {
+            params.push(self.consume_token(TokenType::Identifier, "Expected parameter name")?);
+
+            if ! self.match_token_type(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume_token(TokenType::RightParen, "Expected ')' after parameters")?;
+        self.consume_token(TokenType::LeftBrace, "Expected '{' before function body")?;
+
+        self.function_depth += 1;
+        let body = self.block();
+        self.function_depth -= 1;
+
+        return Ok(Stmt::Function { name, params, body: Rc::new(body?) });
+    }
+
+    fn try_statement(&mut self) -> Result<Stmt, String> {
+        let keyword = self.previous();
+
+        self.consume_token(TokenType::LeftBrace, "Expected '{' after 'try'")?;
+        let body = self.block()?;
+
+        let mut catch_name = None;
+        let mut catch_body = None;
+        let mut finally_body = None;
+
+        if self.match_token_type(vec![TokenType::Catch]) {
+            self.consume_token(TokenType::LeftParen, "Expected '(' after 'catch'")?;
+            catch_name = Some(self.consume_token(TokenType::Identifier, "Expected error name")?);
+            self.consume_token(TokenType::RightParen, "Expected ')' after error name")?;
+            self.consume_token(TokenType::LeftBrace, "Expected '{' before catch body")?;
+            catch_body = Some(self.block()?);
+        }
+
+        if self.match_token_type(vec![TokenType::Finally]) {
+            self.consume_token(TokenType::LeftBrace, "Expected '{' after 'finally'")?;
+            finally_body = Some(self.block()?);
+        }
+
+        if catch_body.is_none() && finally_body.is_none() {
+            return Err(self.error(&keyword, "Expected 'catch' or 'finally' after try block"));
+        }
+
+        return Ok(Stmt::Try { body, catch_name, catch_body, finally_body });
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, String> {
+        self.block_depth += 1;
+        let statements = self.block_statements();
+        self.block_depth -= 1;
+
+        return statements;
+    }
+
+    fn block_statements(&mut self) -> Result<Vec<Stmt>, String> {
+        let mut statements = vec![];
+
+        while ! self.check(TokenType::RightBrace) && ! self.is_at_end() {
+            statements.push(self.declaration()?);
+        }
+
+        self.consume_token(TokenType::RightBrace, "Expected '}' after block")?;
+
+        return Ok(statements);
+    }
+
+    fn enum_declaration(&mut self) -> Result<Stmt, String> {
+        let name = self.consume_token(TokenType::Identifier, "Expected enum name")?;
+        self.consume_token(TokenType::LeftBrace, "Expected '{' before enum variants")?;
+
+        let mut variants = vec![];
+
+        while ! self.check(TokenType::RightBrace) && ! self.is_at_end() {
+            let variant = self.consume_token(TokenType::Identifier, "Expected variant name")?;
+            let mut fields = vec![];
+
+            if self.match_token_type(vec![TokenType::LeftParen]) {
+                while ! self.check(TokenType::RightParen) && ! self.is_at_end() {
+                    fields.push(self.consume_token(TokenType::Identifier, "Expected field name")?);
+
+                    if ! self.match_token_type(vec![TokenType::Comma]) {
+                        break;
+                    }
+                }
+
+                self.consume_token(TokenType::RightParen, "Expected ')' after variant fields")?;
+            }
+
+            variants.push(EnumVariant { name: variant, fields });
+
+            if ! self.match_token_type(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume_token(TokenType::RightBrace, "Expected '}' after enum variants")?;
+
+        self.enums.insert(
+            name.lexeme.clone(),
+            variants.iter().map(|variant| (variant.name.lexeme.clone(), variant.fields.len())).collect(),
+        );
+
+        return Ok(Stmt::Enum { name, variants });
+    }
+
+    fn statement(&mut self) -> Result<Stmt, String> {
+        if self.match_token_type(vec![TokenType::LeftBrace]) {
+            return Ok(Stmt::Block { statements: self.block()? });
+        }
+
+        if self.match_token_type(vec![TokenType::Throw]) {
+            let keyword = self.previous();
+            let value = self.expression()?;
+            self.consume_token(TokenType::Semicolon, "Expected ';' after thrown value")?;
+
+            return Ok(Stmt::Throw { keyword, value });
+        }
+
+        if self.match_token_type(vec![TokenType::Try]) {
+            return self.try_statement();
+        }
+
+        if self.match_token_type(vec![TokenType::Return]) {
+            let keyword = self.previous();
+
+            if self.function_depth == 0 {
+                return Err(self.error(&keyword, "Can't return from top-level code"));
+            }
+
+            let value = if self.check(TokenType::Semicolon) {
+                None
+            } else {
+                Some(self.expression()?)
+            };
+
+            self.consume_token(TokenType::Semicolon, "Expected ';' after return value")?;
+
+            return Ok(Stmt::Return { keyword, value });
+        }
+
+        if self.match_token_type(vec![TokenType::Print]) {
+            let expression = self.expression()?;
+            self.consume_token(TokenType::Semicolon, "Expected ';' after value")?;
+
+            return Ok(Stmt::Print { expression });
+        }
+
+        let expression = self.expression()?;
+        self.consume_token(TokenType::Semicolon, "Expected ';' after expression")?;
+
+        return Ok(Stmt::Expression { expression });
+    }
+
     fn expression(&mut self) -> Result<Expr, String> {
         return self.equality();
     }
@@ -142,46 +442,473 @@ impl Parser {
             })
         }
 
-        return self.primary();
+        return self.call();
+    }
+
+    fn call(&mut self) -> Result<Expr, String> {
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.match_token_type(vec![TokenType::LeftParen]) {
+                let arguments = self.arguments()?;
+                let paren = self.consume_token(TokenType::RightParen, "Expected ')' after arguments")?;
+
+                expr = Call {
+                    callee: Box::from(expr),
+                    paren,
+                    arguments,
+                };
+            } else if self.match_token_type(vec![TokenType::Dot]) {
+                let name = self.consume_token(TokenType::Identifier, "Expected property name after '.'")?;
+
+                expr = Get {
+                    object: Box::from(expr),
+                    name,
+                };
+            } else {
+                break;
+            }
+        }
+
+        return Ok(expr);
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Expr>, String> {
+        let mut arguments = vec![];
+
+        while ! self.check(TokenType::RightParen) && ! self.is_at_end() {
+            arguments.push(self.expression()?);
+
+            if ! self.match_token_type(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+
+        return Ok(arguments);
     }
 
     fn primary(&mut self) -> Result<Expr, String> {
         let token = self.peek();
 
-        let result;
-
-        match token.token_type {
+        let result = match token.token_type {
             TokenType::LeftParen => {
                 self.advance();
                 let expr = self.expression()?;
-                self.consume_token(TokenType::RightParen, "Expected ')'");
+                self.consume_token(TokenType::RightParen, "Expected ')'")?;
 
-                result = Grouping {
+                Grouping {
                     expression: Box::from(expr),
                 }
             },
             TokenType::False | TokenType::True | TokenType::Null | TokenType::Number | TokenType::String => {
                 self.advance();
-                result = Literal {
+                Literal {
                     value: ExpressionLiteralValue::from_token(token),
                 }
+            },
+            TokenType::Identifier => {
+                self.advance();
+                Variable {
+                    name: token,
+                }
+            },
+            TokenType::LeftBracket => {
+                self.advance();
+                List {
+                    elements: self.list_elements()?,
+                }
+            },
+            TokenType::LeftBrace => {
+                self.advance();
+                Map {
+                    entries: self.map_entries()?,
+                }
+            },
+            TokenType::Match => {
+                self.advance();
+                self.match_expression()?
+            },
+            _ => return Err(self.error(&token, "Expected expression")),
+        };
+
+        return Ok(result);
+    }
+
+    fn list_elements(&mut self) -> Result<Vec<Expr>, String> {
+        let mut elements = vec![];
+
+        while ! self.check(TokenType::RightBracket) && ! self.is_at_end() {
+            elements.push(self.expression()?);
+
+            if ! self.match_token_type(vec![TokenType::Comma]) {
+                break;
             }
-            _ => return Err("Expected expression".to_string()),
         }
 
+        self.consume_token(TokenType::RightBracket, "Expected ']' after list elements")?;
+
+        return Ok(elements);
+    }
+
+    fn map_entries(&mut self) -> Result<Vec<(Symbol, Expr)>, String> {
+        let mut entries = vec![];
+
+        while ! self.check(TokenType::RightBrace) && ! self.is_at_end() {
+            let key = self.advance();
+
+            let key = match (&key.token_type, &key.literal) {
+                (TokenType::Identifier, _) => key.lexeme,
+                (TokenType::String, Some(LiteralValue::StringValue(s))) => s.clone(),
+                _ => return Err(self.error(&key, "Expected identifier or string as map key")),
+            };
+
+            self.consume_token(TokenType::Colon, "Expected ':' after map key")?;
+            entries.push((key, self.expression()?));
+
+            if ! self.match_token_type(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume_token(TokenType::RightBrace, "Expected '}' after map entries")?;
+
+        return Ok(entries);
+    }
+
+    fn match_expression(&mut self) -> Result<Expr, String> {
+        let keyword = self.previous();
+        let subject = self.expression()?;
+
+        self.consume_token(TokenType::LeftBrace, "Expected '{' after match subject")?;
+
+        let mut arms = vec![];
+
+        while ! self.check(TokenType::RightBrace) && ! self.is_at_end() {
+            let pattern = self.pattern()?;
+
+            let guard = if self.match_token_type(vec![TokenType::If]) {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+
+            self.consume_token(TokenType::FatArrow, "Expected '=>' after match pattern")?;
+            let body = self.expression()?;
+
+            arms.push(MatchArm { pattern, guard, body });
+
+            if ! self.match_token_type(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume_token(TokenType::RightBrace, "Expected '}' after match arms")?;
+        self.check_exhaustiveness(&keyword, &arms);
+
+        return Ok(Match {
+            keyword,
+            subject: Box::from(subject),
+            arms,
+        });
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, String> {
+        let mut alternatives = vec![self.single_pattern()?];
+
+        while self.match_token_type(vec![TokenType::Pipe]) {
+            alternatives.push(self.single_pattern()?);
+        }
+
+        if alternatives.len() == 1 {
+            return Ok(alternatives.remove(0));
+        }
+
+        return Ok(Pattern::Alternative { alternatives });
+    }
+
+    fn single_pattern(&mut self) -> Result<Pattern, String> {
+        let token = self.peek();
+
+        let result = match token.token_type {
+            TokenType::False | TokenType::True | TokenType::Null | TokenType::Number | TokenType::String => {
+                self.advance();
+                Pattern::Literal {
+                    value: ExpressionLiteralValue::from_token(token),
+                }
+            },
+            TokenType::Minus => {
+                self.advance();
+                let number = self.consume_token(TokenType::Number, "Expected number after '-' in pattern")?;
+
+                match ExpressionLiteralValue::from_token(number) {
+                    ExpressionLiteralValue::Number(n) => Pattern::Literal {
+                        value: ExpressionLiteralValue::Number(-n),
+                    },
+                    _ => unreachable!(),
+                }
+            },
+            TokenType::LeftBracket => {
+                self.advance();
+                let mut elements = vec![];
+                let mut rest = None;
+
+                while ! self.check(TokenType::RightBracket) && ! self.is_at_end() {
+                    if self.match_token_type(vec![TokenType::Ellipsis]) {
+                        rest = Some(self.consume_token(TokenType::Identifier, "Expected name after '...'")?);
+                        break;
+                    }
+
+                    elements.push(self.pattern()?);
+
+                    if ! self.match_token_type(vec![TokenType::Comma]) {
+                        break;
+                    }
+                }
+
+                self.consume_token(TokenType::RightBracket, "Expected ']' after list pattern")?;
+
+                Pattern::List { elements, rest }
+            },
+            TokenType::LeftBrace => {
+                self.advance();
+                Pattern::Map {
+                    entries: self.map_pattern_entries()?,
+                }
+            },
+            TokenType::Identifier if token.lexeme == "_" => {
+                self.advance();
+                Pattern::Wildcard
+            },
+            TokenType::Identifier => {
+                self.advance();
+
+                // There are no class instances to match yet, and matching on the fields alone
+                // would ignore the name.
+                if self.check(TokenType::LeftBrace) {
+                    return Err(self.error(&token, &format!("Class patterns aren't supported yet; match a map with {{...}} instead of {} {{...}}", token.lexeme)));
+                }
+
+                if self.match_token_type(vec![TokenType::Dot]) {
+                    let variant = self.consume_token(TokenType::Identifier, "Expected variant name after '.'")?;
+
+                    Pattern::Variant {
+                        enum_name: Some(token),
+                        variant,
+                        fields: self.variant_pattern_fields()?,
+                    }
+                } else if self.check(TokenType::LeftParen) {
+                    self.warn_if_ambiguous(&token);
+
+                    Pattern::Variant {
+                        enum_name: None,
+                        variant: token,
+                        fields: self.variant_pattern_fields()?,
+                    }
+                } else {
+                    self.bare_name_pattern(token)
+                }
+            },
+            _ => return Err(self.error(&token, "Expected pattern")),
+        };
+
         return Ok(result);
     }
 
-    fn consume_token(&mut self, token_type: TokenType, message: &str) -> Result<(), String> {
+    // A bare name binds whatever it matches, unless it names a variant without fields of an
+    // enum declared above. Binding a name shared with a variant that has fields is almost
+    // certainly a mistake, so that warns.
+    fn bare_name_pattern(&mut self, name: Token) -> Pattern {
+        let field_counts: Vec<usize> = self.enums.values()
+            .flatten()
+            .filter(|(variant, _)| *variant == name.lexeme)
+            .map(|(_, fields)| *fields)
+            .collect();
+
+        if field_counts.contains(&0) {
+            self.warn_if_ambiguous(&name);
+            return Pattern::Variant { enum_name: None, variant: name, fields: vec![] };
+        }
+
+        if !field_counts.is_empty() {
+            self.warnings.push(format!(
+                "Warning at line {}: {} binds a new name; write {}(...) to match the variant",
+                name.line_number, name.lexeme, name.lexeme,
+            ));
+        }
+
+        return Pattern::Binding { name };
+    }
+
+    // Enums declaring a variant with this name, sorted by name.
+    fn enums_with_variant(&self, variant: &Symbol) -> Vec<Symbol> {
+        let mut enums: Vec<Symbol> = self.enums.iter()
+            .filter(|(_, variants)| variants.iter().any(|(name, _)| name == variant))
+            .map(|(enum_name, _)| enum_name.clone())
+            .collect();
+
+        enums.sort();
+        return enums;
+    }
+
+    // An unqualified variant pattern matches that variant of any enum.
+    fn warn_if_ambiguous(&mut self, variant: &Token) {
+        let enums = self.enums_with_variant(&variant.lexeme);
+
+        if enums.len() > 1 {
+            let names: Vec<&str> = enums.iter().map(|name| name.as_str()).collect();
+
+            self.warnings.push(format!(
+                "Warning at line {}: variant {} is declared by {}; qualify it as {}.{}",
+                variant.line_number, variant.lexeme, names.join(" and "), names[0], variant.lexeme,
+            ));
+        }
+    }
+
+    fn map_pattern_entries(&mut self) -> Result<Vec<MapPatternEntry>, String> {
+        let mut entries = vec![];
+
+        while ! self.check(TokenType::RightBrace) && ! self.is_at_end() {
+            let key = self.consume_token(TokenType::Identifier, "Expected key name in map pattern")?;
+
+            let pattern = if self.match_token_type(vec![TokenType::Colon]) {
+                self.pattern()?
+            } else {
+                Pattern::Binding { name: key.clone() }
+            };
+
+            let default = if self.match_token_type(vec![TokenType::Equal]) {
+                Some(self.expression()?)
+            } else {
+                None
+            };
+
+            entries.push(MapPatternEntry { key, pattern, default });
+
+            if ! self.match_token_type(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume_token(TokenType::RightBrace, "Expected '}' after map pattern")?;
+
+        return Ok(entries);
+    }
+
+    fn variant_pattern_fields(&mut self) -> Result<Vec<Pattern>, String> {
+        let mut fields = vec![];
+
+        if ! self.match_token_type(vec![TokenType::LeftParen]) {
+            return Ok(fields);
+        }
+
+        while ! self.check(TokenType::RightParen) && ! self.is_at_end() {
+            fields.push(self.pattern()?);
+
+            if ! self.match_token_type(vec![TokenType::Comma]) {
+                break;
+            }
+        }
+
+        self.consume_token(TokenType::RightParen, "Expected ')' after variant pattern fields")?;
+
+        return Ok(fields);
+    }
+
+    fn check_exhaustiveness(&mut self, keyword: &Token, arms: &[MatchArm]) {
+        if arms.iter().any(|arm| arm.guard.is_none() && arm.pattern.is_irrefutable()) {
+            return;
+        }
+
+        let mut domain: Vec<String> = vec![];
+        let mut covered: Vec<String> = vec![];
+
+        for arm in arms {
+            let mut cases = vec![];
+
+            if ! self.finite_cases(&arm.pattern, &mut domain, &mut cases) {
+                return;
+            }
+
+            if arm.guard.is_none() {
+                covered.append(&mut cases);
+            }
+        }
+
+        let missing: Vec<String> = domain.into_iter()
+            .filter(|case| ! covered.contains(case))
+            .collect();
+
+        if !missing.is_empty() {
+            self.warnings.push(format!(
+                "Warning at line {}: non-exhaustive match, missing {}",
+                keyword.line_number,
+                missing.join(", "),
+            ));
+        }
+    }
+
+    // Collects the cases a pattern covers when it ranges over a finite set of values.
+    // Returns false when the pattern can't be reasoned about that way.
+    fn finite_cases(&self, pattern: &Pattern, domain: &mut Vec<String>, cases: &mut Vec<String>) -> bool {
+        return match pattern {
+            Pattern::Literal { value: ExpressionLiteralValue::True } | Pattern::Literal { value: ExpressionLiteralValue::False } => {
+                if domain.is_empty() {
+                    domain.push("true".to_string());
+                    domain.push("false".to_string());
+                }
+
+                cases.push(pattern.to_string());
+                domain.contains(&pattern.to_string())
+            },
+            Pattern::Variant { enum_name, variant, fields } => {
+                let enum_name = match enum_name {
+                    Some(enum_name) => enum_name.lexeme.clone(),
+                    // Ambiguous names were warned about when they were parsed.
+                    None => match &self.enums_with_variant(&variant.lexeme)[..] {
+                        [enum_name] => enum_name.clone(),
+                        _ => return false,
+                    },
+                };
+
+                let variants = match self.enums.get(&enum_name) {
+                    Some(variants) => variants,
+                    None => return false,
+                };
+
+                if domain.is_empty() {
+                    for (name, _) in variants {
+                        domain.push(format!("{}.{}", enum_name, name));
+                    }
+                }
+
+                let case = format!("{}.{}", enum_name, variant.lexeme);
+
+                // A variant whose payload is only partially matched doesn't cover the whole case.
+                if fields.iter().all(|field| field.is_irrefutable()) {
+                    cases.push(case.clone());
+                }
+
+                domain.contains(&case)
+            },
+            Pattern::Alternative { alternatives } => {
+                alternatives.iter().all(|alternative| self.finite_cases(alternative, domain, cases))
+            },
+            _ => false,
+        }
+    }
+
+    fn consume_token(&mut self, token_type: TokenType, message: &str) -> Result<Token, String> {
         let token = self.peek();
 
         if token.token_type != token_type {
-            return Err(message.to_string());
+            return Err(self.error(&token, message));
         }
 
-        self.advance();
+        return Ok(self.advance());
+    }
 
-        return Ok(())
+    fn error(&self, token: &Token, message: &str) -> String {
+        return format!("Error at line {}: {}", token.line_number, message);
     }
 
     fn synchronize(&mut self) {
@@ -193,9 +920,11 @@ impl Parser {
             }
 
             match self.peek().token_type {
-                TokenType::Class | TokenType::Fn | TokenType::Let | TokenType::For | TokenType::If | TokenType::While | TokenType::Print | TokenType::Return => return,
+                TokenType::Class | TokenType::Enum | TokenType::Fn | TokenType::Let | TokenType::Import | TokenType::Export | TokenType::For | TokenType::If | TokenType::While | TokenType::Print | TokenType::Return | TokenType::Throw | TokenType::Try => return,
                 _ => (),
             }
+
+            self.advance();
         }
     }
 }
@@ -210,25 +939,25 @@ mod tests {
     fn test_addition() {
             let one = Token {
                 token_type: TokenType::Number,
-                lexeme: "1".to_string(),
+                lexeme: Symbol::intern("1"),
                 literal: Some(IntegerValue(1)),
                 line_number: 0,
             };
             let plus = Token {
                 token_type: TokenType::Plus,
-                lexeme: "+".to_string(),
+                lexeme: Symbol::intern("+"),
                 literal: None,
                 line_number: 0,
             };
             let two = Token {
                 token_type: TokenType::Number,
-                lexeme: "2".to_string(),
+                lexeme: Symbol::intern("2"),
                 literal: Some(IntegerValue(2)),
                 line_number: 0,
             };
             let semicolon = Token {
                 token_type: TokenType::Number,
-                lexeme: "2".to_string(),
+                lexeme: Symbol::intern("2"),
                 literal: Some(IntegerValue(2)),
                 line_number: 0,
             };
@@ -252,4 +981,69 @@ mod tests {
 
         assert_eq!(string_expression, "(== (+ 1 2) (+ 5 7))")
     }
+
+    #[test]
+    fn test_match_expression() {
+        let source = r#"match value { 1 => "one", "a" | "b" => "letter", [x, y] if x > y => x, { x, y: 0 } => x, _ => null }"#;
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let string_expression = parser.parse().unwrap().to_string();
+
+        assert_eq!(
+            string_expression,
+            "(match value (1 => one) ((| a b) => letter) ([x y] if (> x y) => x) ({x: x y: 0} => x) (_ => null))"
+        );
+        assert!(parser.warnings.is_empty());
+
+        let mut parser = Parser::new(Scanner::new("match value { Point { x, y } => x }").scan_tokens().unwrap());
+        assert_eq!(parser.parse().err().unwrap(), "Error at line 1: Class patterns aren't supported yet; match a map with {...} instead of Point {...}");
+    }
+
+    #[test]
+    fn test_non_exhaustive_boolean_match_warns() {
+        let source = "match ready { true => 1 }";
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        parser.parse().unwrap();
+
+        assert_eq!(parser.warnings, vec!["Warning at line 1: non-exhaustive match, missing false".to_string()]);
+    }
+
+    #[test]
+    fn test_destructuring_let() {
+        let source = "let [a, ...rest] = xs; let {port = 8080, name: n} = cfg;";
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let statements: Vec<String> = parser.parse_statements().unwrap().iter().map(|s| s.to_string()).collect();
+
+        assert_eq!(statements, vec!["(let [a ...rest] xs)", "(let {port: port = 8080 name: n} cfg)"]);
+        assert!(Parser::new(Scanner::new("let 1 = x;").scan_tokens().unwrap()).parse_statements().is_err());
+    }
+
+    #[test]
+    fn test_non_exhaustive_enum_match_warns() {
+        let source = "enum Shape { Circle(r), Rect(w, h), Empty } print match s { Circle(r) => r, Shape.Rect(1, h) => h };";
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        parser.parse_statements().unwrap();
+
+        assert_eq!(parser.warnings, vec!["Warning at line 1: non-exhaustive match, missing Shape.Rect, Shape.Empty".to_string()]);
+    }
+
+    #[test]
+    fn test_bare_variant_names_in_patterns() {
+        let source = "enum Shape { Circle(r), Empty } print match s { Empty => 0, Circle => 1 };";
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        parser.parse_statements().unwrap();
+
+        assert_eq!(parser.warnings, vec!["Warning at line 1: Circle binds a new name; write Circle(...) to match the variant".to_string()]);
+
+        let source = "enum Shape { Empty } enum Tree { Leaf, Empty } print match s { Empty => 0 };";
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        parser.parse_statements().unwrap();
+
+        assert_eq!(parser.warnings, vec!["Warning at line 1: variant Empty is declared by Shape and Tree; qualify it as Shape.Empty".to_string()]);
+    }
 }
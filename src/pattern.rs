@@ -0,0 +1,128 @@
+use crate::expr::{Expr, ExpressionLiteralValue};
+use crate::symbol::Symbol;
+use crate::token::Token;
+
+pub struct MapPatternEntry {
+    pub key: Token,
+    pub pattern: Pattern,
+    pub default: Option<Expr>,
+}
+
+pub enum Pattern {
+    Wildcard,
+
+    Literal {
+        value: ExpressionLiteralValue,
+    },
+
+    Binding {
+        name: Token,
+    },
+
+    Alternative {
+        alternatives: Vec<Pattern>,
+    },
+
+    List {
+        elements: Vec<Pattern>,
+        rest: Option<Token>,
+    },
+
+    Map {
+        entries: Vec<MapPatternEntry>,
+    },
+
+    Variant {
+        enum_name: Option<Token>,
+        variant: Token,
+        fields: Vec<Pattern>,
+    },
+}
+
+impl Pattern {
+    pub fn to_string(&self) -> String {
+        return match self {
+            Pattern::Wildcard => String::from("_"),
+            Pattern::Literal { value } => value.to_string(),
+            Pattern::Binding { name } => name.lexeme.to_string(),
+            Pattern::Alternative { alternatives } => {
+                format!("(| {})", join(alternatives.iter().map(|p| p.to_string()).collect()))
+            },
+            Pattern::List { elements, rest } => {
+                let mut elements: Vec<String> = elements.iter().map(|p| p.to_string()).collect();
+
+                if let Some(rest) = rest {
+                    elements.push(format!("...{}", rest.lexeme));
+                }
+
+                format!("[{}]", join(elements))
+            },
+            Pattern::Map { entries } => {
+                let entries = entries.iter()
+                    .map(|entry| match &entry.default {
+                        Some(default) => format!("{}: {} = {}", entry.key.lexeme, entry.pattern.to_string(), default.to_string()),
+                        None => format!("{}: {}", entry.key.lexeme, entry.pattern.to_string()),
+                    })
+                    .collect();
+
+                format!("{{{}}}", join(entries))
+            },
+            Pattern::Variant { enum_name, variant, fields } => {
+                let name = match enum_name {
+                    Some(enum_name) => format!("{}.{}", enum_name.lexeme, variant.lexeme),
+                    None => variant.lexeme.to_string(),
+                };
+
+                match fields.len() {
+                    0 => name,
+                    _ => format!("({} {})", name, join(fields.iter().map(|p| p.to_string()).collect())),
+                }
+            },
+        }
+    }
+
+    // Whether the pattern matches every possible value, so arms after it can never be reached.
+    pub fn is_irrefutable(&self) -> bool {
+        return match self {
+            Pattern::Wildcard | Pattern::Binding { .. } => true,
+            Pattern::Alternative { alternatives } => alternatives.iter().any(|p| p.is_irrefutable()),
+            _ => false,
+        }
+    }
+
+    pub fn bound_names(&self) -> Vec<Symbol> {
+        return match self {
+            Pattern::Wildcard | Pattern::Literal { .. } => vec![],
+            Pattern::Binding { name } => vec![name.lexeme.clone()],
+            Pattern::Alternative { alternatives } => match alternatives.first() {
+                Some(alternative) => alternative.bound_names(),
+                None => vec![],
+            },
+            Pattern::List { elements, rest } => {
+                let mut names: Vec<Symbol> = elements.iter().flat_map(|p| p.bound_names()).collect();
+
+                if let Some(rest) = rest {
+                    names.push(rest.lexeme.clone());
+                }
+
+                names
+            },
+            Pattern::Map { entries } => entries.iter().flat_map(|entry| entry.pattern.bound_names()).collect(),
+            Pattern::Variant { enum_name: _, variant: _, fields } => fields.iter().flat_map(|p| p.bound_names()).collect(),
+        }
+    }
+
+    // Whether the pattern only binds names and takes lists and maps apart, as `let` requires.
+    pub fn is_destructuring(&self) -> bool {
+        return match self {
+            Pattern::Wildcard | Pattern::Binding { .. } => true,
+            Pattern::List { elements, rest: _ } => elements.iter().all(|p| p.is_destructuring()),
+            Pattern::Map { entries } => entries.iter().all(|entry| entry.pattern.is_destructuring()),
+            _ => false,
+        }
+    }
+}
+
+fn join(parts: Vec<String>) -> String {
+    return parts.join(" ");
+}
@@ -1,8 +1,8 @@
 use std::collections::HashMap;
-use std::iter::Iterator;
 use crate::exception::Exception;
 use crate::literal_value::LiteralValue;
 use crate::literal_value::LiteralValue::{FloatValue, StringValue};
+use crate::symbol::Symbol;
 use crate::token::Token;
 use crate::token_type::TokenType;
 
@@ -32,7 +32,9 @@ impl Scanner {
                 ("false", TokenType::False),
                 ("null", TokenType::Null),
                 ("if", TokenType::If),
+                ("match", TokenType::Match),
                 ("else", TokenType::Else),
+                ("enum", TokenType::Enum),
                 ("return", TokenType::Return),
                 ("print", TokenType::Print),
                 ("while", TokenType::While),
@@ -42,6 +44,12 @@ impl Scanner {
                 ("extends", TokenType::Extends),
                 ("fn", TokenType::Fn),
                 ("class", TokenType::Class),
+                ("throw", TokenType::Throw),
+                ("try", TokenType::Try),
+                ("catch", TokenType::Catch),
+                ("finally", TokenType::Finally),
+                ("import", TokenType::Import),
+                ("export", TokenType::Export),
             ])
         }
     }
@@ -67,18 +75,18 @@ impl Scanner {
         self.tokens.push(
             Token {
                 token_type: TokenType::Eof,
-                lexeme: "".to_string(),
+                lexeme: Symbol::intern(""),
                 literal: None,
-                line_number: 0,
+                line_number: self.line,
             }
         );
 
-        if errors.len() > 0 {
+        if !errors.is_empty() {
             let mut message = "".to_string();
-            let _ = errors.iter().map(|x| {
-                message.push_str(x);
-                message.push_str("\n");
-            });
+            for error in errors {
+                message.push_str(&error);
+                message.push('\n');
+            }
             return Err(message);
         }
 
@@ -93,12 +101,34 @@ impl Scanner {
             ')' => self.add_token(TokenType::RightParen),
             '{' => self.add_token(TokenType::LeftBrace),
             '}' => self.add_token(TokenType::RightBrace),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
             ',' => self.add_token(TokenType::Comma),
-            '.' => self.add_token(TokenType::Dot),
+            ':' => self.add_token(TokenType::Colon),
+            '.' => {
+                let token = if self.peek() == '.' && self.peek_next() == '.' {
+                    self.advance();
+                    self.advance();
+                    TokenType::Ellipsis
+                } else {
+                    TokenType::Dot
+                };
+
+                self.add_token(token)
+            },
             '-' => self.add_token(TokenType::Minus),
             '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
             '*' => self.add_token(TokenType::Star),
+            '|' => {
+                let token = if self.char_match('|') {
+                    TokenType::Or
+                } else {
+                    TokenType::Pipe
+                };
+
+                self.add_token(token)
+            },
             '!' => {
                 let token = if self.char_match('=') {
                     TokenType::BangEqual
@@ -111,6 +141,8 @@ impl Scanner {
             '=' => {
                 let token = if self.char_match('=') {
                     TokenType::EqualEqual
+                } else if self.char_match('>') {
+                    TokenType::FatArrow
                 } else {
                     TokenType::Equal
                 };
@@ -159,6 +191,10 @@ impl Scanner {
                     return self.number();
                 }
 
+                if c == 'r' && (self.peek() == '"' || self.peek() == '#') {
+                    return self.raw_string();
+                }
+
                 if is_alpha(c) {
                     return self.identifier();
                 }
@@ -187,7 +223,7 @@ impl Scanner {
 
         self.tokens.push(Token {
             token_type,
-            lexeme: self.current_text(),
+            lexeme: Symbol::intern(self.current_text()),
             literal,
             line_number: self.line,
         });
@@ -204,7 +240,7 @@ impl Scanner {
             return '\0';
         }
 
-        return self.source.chars().nth(self.current).unwrap();
+        return self.source.as_bytes()[self.current] as char;
     }
 
     fn char_match(self: &mut Self, char: char) -> bool {
@@ -243,19 +279,20 @@ impl Scanner {
             return '\n';
         }
 
-        return self.source.chars().nth(self.current + 1).unwrap();
+        return self.source.as_bytes()[self.current + 1] as char;
     }
 
-    fn current_text(&self) -> String {
+    fn current_text(&self) -> &str {
         return self.text(self.start, self.current);
     }
 
-    fn text(&self, start: usize, end: usize) -> String {
-        return String::from(&self.source[start..end]);
+    fn text(&self, start: usize, end: usize) -> &str {
+        return &self.source[start..end];
     }
 
+    // Stops before the newline, so `scan_token` counts the line.
     fn single_line_comment(&mut self) -> Result<(), String> {
-        while ! self.char_match('\n') {
+        while self.peek() != '\n' && ! self.is_at_end() {
             self.advance();
         }
 
@@ -263,7 +300,14 @@ impl Scanner {
     }
 
     fn multi_line_comment(&mut self) -> Result<(), String> {
-        while self.peek() != '*' && self.peek_next() != '/' {
+        while ! (self.peek() == '*' && self.peek_next() == '/') {
+            if self.is_at_end() {
+                return Exception::throw("Unterminated comment".to_string(), self.line);
+            }
+
+            if self.peek() == '\n' {
+                self.line += 1;
+            }
             self.advance();
         }
 
@@ -291,6 +335,39 @@ impl Scanner {
         self.advance();
 
         let value = self.text(self.start + 1, self.current - 1);
+        return self.add_token_literal(TokenType::String, Some(StringValue(Symbol::intern(value))));
+    }
+
+    // `r"..."` or `r#"..."#`: the hashes let the string contain `"` without ending it.
+    fn raw_string(&mut self) -> Result<(), String> {
+        let mut hashes = 0;
+
+        while self.char_match('#') {
+            hashes += 1;
+        }
+
+        if ! self.char_match('"') {
+            return Exception::throw("Expected '\"' to start raw string".to_string(), self.line);
+        }
+
+        let content_start = self.current;
+        let terminator = format!("\"{}", "#".repeat(hashes));
+
+        // Compared as bytes, since `current` steps through multi-byte characters one byte at a time.
+        while ! self.source.as_bytes()[self.current..].starts_with(terminator.as_bytes()) {
+            if self.is_at_end() {
+                return Exception::throw("Unterminated raw string".to_string(), self.line);
+            }
+
+            if self.source.as_bytes()[self.current] == b'\n' {
+                self.line += 1;
+            }
+            self.advance();
+        }
+
+        let value = Symbol::intern(self.text(content_start, self.current));
+        self.current += terminator.len();
+
         return self.add_token_literal(TokenType::String, Some(StringValue(value)));
     }
 
@@ -311,13 +388,13 @@ impl Scanner {
 fn is_alpha(ch: char) -> bool {
     let uch = ch as u8;
 
-    return (uch >= 'a' as u8 && uch <= 'z' as u8)
-        || (uch >= 'A' as u8 && uch <= 'Z' as u8)
+    return uch.is_ascii_lowercase()
+        || uch.is_ascii_uppercase()
         || ch == '_';
 }
 
 fn is_digit(ch: char) -> bool {
-    return (ch as u8) >= ('0' as u8) && (ch as u8) <= ('9' as u8);
+    return (ch as u8).is_ascii_digit();
 }
 
 fn is_alpha_numeric(ch: char) -> bool {
@@ -0,0 +1,77 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{argument, module, native};
+
+pub fn register(interpreter: &mut Interpreter) {
+    let fs = module("fs", vec![
+        ("read_text", native("fs.read_text", Arity::Fixed(1), |_, arguments| {
+            let path: String = argument(&arguments, 0)?;
+            let text = fs::read_to_string(&path).map_err(|e| failed(&path, e))?;
+
+            Ok(Value::from(text))
+        })),
+        ("write_text", native("fs.write_text", Arity::Fixed(2), |_, arguments| {
+            let path: String = argument(&arguments, 0)?;
+            let text: String = argument(&arguments, 1)?;
+            fs::write(&path, text).map_err(|e| failed(&path, e))?;
+
+            Ok(Value::Null)
+        })),
+        ("append", native("fs.append", Arity::Fixed(2), |_, arguments| {
+            let path: String = argument(&arguments, 0)?;
+            let text: String = argument(&arguments, 1)?;
+
+            OpenOptions::new().create(true).append(true).open(&path)
+                .and_then(|mut file| file.write_all(text.as_bytes()))
+                .map_err(|e| failed(&path, e))?;
+
+            Ok(Value::Null)
+        })),
+        ("exists", native("fs.exists", Arity::Fixed(1), |_, arguments| {
+            let path: String = argument(&arguments, 0)?;
+            Ok(Value::Boolean(Path::new(&path).exists()))
+        })),
+        // Entry names only, sorted so scripts see the same order on every platform.
+        ("list_dir", native("fs.list_dir", Arity::Fixed(1), |_, arguments| {
+            let path: String = argument(&arguments, 0)?;
+            let mut names = vec![];
+
+            for entry in fs::read_dir(&path).map_err(|e| failed(&path, e))? {
+                let entry = entry.map_err(|e| failed(&path, e))?;
+                names.push(entry.file_name().to_string_lossy().to_string());
+            }
+
+            names.sort();
+
+            Ok(Value::from(names))
+        })),
+        // Creates missing parent directories too, and succeeds if the directory already exists.
+        ("mkdir", native("fs.mkdir", Arity::Fixed(1), |_, arguments| {
+            let path: String = argument(&arguments, 0)?;
+            fs::create_dir_all(&path).map_err(|e| failed(&path, e))?;
+
+            Ok(Value::Null)
+        })),
+        // Removes a file or an empty directory; non-empty directories are an error.
+        ("remove", native("fs.remove", Arity::Fixed(1), |_, arguments| {
+            let path: String = argument(&arguments, 0)?;
+
+            match Path::new(&path).is_dir() {
+                true => fs::remove_dir(&path),
+                false => fs::remove_file(&path),
+            }.map_err(|e| failed(&path, e))?;
+
+            Ok(Value::Null)
+        })),
+    ]);
+
+    interpreter.register_global("fs", fs);
+}
+
+fn failed(path: &str, error: io::Error) -> RuntimeError {
+    return RuntimeError::new(ErrorKind::IoError, format!("{}: {}", path, error), 0);
+}
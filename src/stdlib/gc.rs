@@ -0,0 +1,27 @@
+use std::collections::BTreeMap;
+use crate::gc;
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{module, native};
+
+pub fn register(interpreter: &mut Interpreter) {
+    let gc = module("gc", vec![
+        // Returns the number of objects freed.
+        ("collect", native("gc.collect", Arity::Fixed(0), |_, _| {
+            Ok(Value::Number(gc::collect() as f64))
+        })),
+        ("stats", native("gc.stats", Arity::Fixed(0), |_, _| {
+            let stats = gc::stats();
+
+            Ok(Value::map(BTreeMap::from([
+                ("collections".to_string(), Value::Number(stats.collections as f64)),
+                ("freed".to_string(), Value::Number(stats.freed as f64)),
+                ("live".to_string(), Value::Number(stats.live as f64)),
+                ("threshold".to_string(), Value::Number(stats.threshold as f64)),
+                ("next_collection".to_string(), Value::Number(stats.next_collection as f64)),
+            ])))
+        })),
+    ]);
+
+    interpreter.register_global("gc", gc);
+}
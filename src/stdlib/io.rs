@@ -0,0 +1,64 @@
+use std::io::{self, BufRead, Write};
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{argument, module, native};
+
+pub fn register(interpreter: &mut Interpreter) {
+    let io = module("io", vec![
+        ("input", native("io.input", Arity::Variadic, input)),
+        ("print", native("io.print", Arity::Fixed(1), |_, arguments| {
+            println!("{}", arguments[0].to_string());
+            Ok(Value::Null)
+        })),
+        ("write", native("io.write", Arity::Fixed(1), |_, arguments| {
+            print!("{}", arguments[0].to_string());
+            io::stdout().flush().map_err(failed)?;
+            Ok(Value::Null)
+        })),
+        ("eprint", native("io.eprint", Arity::Fixed(1), eprint)),
+    ]);
+
+    interpreter.register_global("io", io);
+    interpreter.register_native("input", Arity::Variadic, input);
+    interpreter.register_native("eprint", Arity::Fixed(1), eprint);
+}
+
+// Reads one line from stdin without its line ending, showing an optional prompt first.
+// Returns null once stdin is exhausted. A read from stdin can't be interrupted, so it isn't
+// allowed under a timeout.
+fn input(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    if arguments.len() > 1 {
+        return Err(RuntimeError::new(ErrorKind::TypeError, format!("input expected at most 1 arguments but got {}", arguments.len()), 0));
+    }
+
+    if interpreter.has_timeout() {
+        return Err(RuntimeError::new(ErrorKind::IoError, "input can't wait for stdin under a timeout".to_string(), 0));
+    }
+
+    if !arguments.is_empty() {
+        let prompt: String = argument(&arguments, 0)?;
+        print!("{}", prompt);
+        io::stdout().flush().map_err(failed)?;
+    }
+
+    let mut line = String::new();
+
+    if io::stdin().lock().read_line(&mut line).map_err(failed)? == 0 {
+        return Ok(Value::Null);
+    }
+
+    let trimmed = line.strip_suffix('\n').unwrap_or(&line);
+    let trimmed = trimmed.strip_suffix('\r').unwrap_or(trimmed);
+
+    return Ok(Value::from(trimmed));
+}
+
+fn eprint(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    eprintln!("{}", arguments[0].to_string());
+    return Ok(Value::Null);
+}
+
+fn failed(error: io::Error) -> RuntimeError {
+    return RuntimeError::new(ErrorKind::IoError, error.to_string(), 0);
+}
@@ -0,0 +1,409 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{argument, module, native};
+
+pub fn register(interpreter: &mut Interpreter) {
+    let json = module("json", vec![
+        ("parse", native("json.parse", Arity::Fixed(1), |_, arguments| {
+            let text: String = argument(&arguments, 0)?;
+            parse(&text).map_err(|message| RuntimeError::new(ErrorKind::ValueError, message, 0))
+        })),
+        // `json.stringify(value)` is compact, `json.stringify(value, 2)` pretty-prints with that many
+        // spaces. Like JavaScript, indents over 10 spaces are clamped to 10.
+        ("stringify", native("json.stringify", Arity::Variadic, |_, arguments| {
+            let indent = match arguments.len() {
+                1 => None,
+                2 => match &arguments[1] {
+                    Value::Null => None,
+                    _ => Some(super::index(&arguments, 1)?.min(MAX_INDENT)),
+                },
+                n => return Err(RuntimeError::new(ErrorKind::TypeError, format!("json.stringify expected 1 or 2 arguments but got {}", n), 0)),
+            };
+
+            let mut writer = Writer { output: String::new(), indent, open: vec![] };
+            writer.write(&arguments[0], 0)?;
+
+            Ok(Value::from(writer.output))
+        })),
+    ]);
+
+    interpreter.register_global("json", json);
+}
+
+const MAX_INDENT: usize = 10;
+
+// Arrays and objects nested deeper than this are rejected rather than parsed by recursing
+// until the stack runs out.
+const MAX_DEPTH: usize = 512;
+
+pub fn parse(text: &str) -> Result<Value, String> {
+    let mut parser = JsonParser { chars: text.chars().collect(), current: 0, line: 1, column: 1, depth: 0 };
+
+    parser.skip_whitespace();
+    let value = parser.value()?;
+    parser.skip_whitespace();
+
+    if parser.peek().is_some() {
+        return Err(parser.error("unexpected trailing characters"));
+    }
+
+    return Ok(value);
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    current: usize,
+    line: usize,
+    column: usize,
+    // Arrays and objects open around the current position.
+    depth: usize,
+}
+
+impl JsonParser {
+    fn value(&mut self) -> Result<Value, String> {
+        return match self.peek() {
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => Ok(Value::from(self.string()?)),
+            Some('t') => self.keyword("true", Value::Boolean(true)),
+            Some('f') => self.keyword("false", Value::Boolean(false)),
+            Some('n') => self.keyword("null", Value::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(self.error(&format!("unexpected character '{}'", c))),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        if self.depth == MAX_DEPTH {
+            return Err(self.error(&format!("nested deeper than {} levels", MAX_DEPTH)));
+        }
+
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+
+        return value;
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.advance();
+        let mut entries = BTreeMap::new();
+
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.advance();
+            return Ok(Value::map(entries));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("expected string key"));
+            }
+
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            let value = self.value()?;
+            entries.insert(key, value);
+            self.skip_whitespace();
+
+            match self.advance() {
+                Some(',') => continue,
+                Some('}') => return Ok(Value::map(entries)),
+                _ => return Err(self.error_before("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.advance();
+        let mut elements = vec![];
+
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.advance();
+            return Ok(Value::list(elements));
+        }
+
+        loop {
+            self.skip_whitespace();
+            elements.push(self.value()?);
+            self.skip_whitespace();
+
+            match self.advance() {
+                Some(',') => continue,
+                Some(']') => return Ok(Value::list(elements)),
+                _ => return Err(self.error_before("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.advance();
+        let mut s = String::new();
+
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(s),
+                Some('\\') => match self.advance() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('/') => s.push('/'),
+                    Some('b') => s.push('\u{8}'),
+                    Some('f') => s.push('\u{c}'),
+                    Some('n') => s.push('\n'),
+                    Some('r') => s.push('\r'),
+                    Some('t') => s.push('\t'),
+                    Some('u') => s.push(self.unicode_escape()?),
+                    _ => return Err(self.error_before("invalid escape sequence")),
+                },
+                Some(c) if (c as u32) < 0x20 => return Err(self.error_before("control character in string")),
+                Some(c) => s.push(c),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+
+        // Characters outside the basic plane arrive as a surrogate pair of two escapes.
+        if (0xD800..0xDC00).contains(&high) {
+            if self.advance() != Some('\\') || self.advance() != Some('u') {
+                return Err(self.error_before("unpaired surrogate in string"));
+            }
+
+            let low = self.hex4()?;
+            if !(0xDC00..0xE000).contains(&low) {
+                return Err(self.error_before("unpaired surrogate in string"));
+            }
+
+            let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+            return char::from_u32(code).ok_or_else(|| self.error_before("invalid unicode escape"));
+        }
+
+        return char::from_u32(high).ok_or_else(|| self.error_before("unpaired surrogate in string"));
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+
+        for _ in 0..4 {
+            match self.advance().and_then(|c| c.to_digit(16)) {
+                Some(digit) => code = code * 16 + digit,
+                None => return Err(self.error_before("invalid unicode escape")),
+            }
+        }
+
+        return Ok(code);
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.current;
+        let (line, column) = (self.line, self.column);
+
+        if self.peek() == Some('-') {
+            self.advance();
+        }
+
+        match self.peek() {
+            Some('0') => { self.advance(); },
+            Some(c) if c.is_ascii_digit() => self.digits(),
+            _ => return Err(self.error("expected digit")),
+        }
+
+        if self.peek() == Some('.') {
+            self.advance();
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return Err(self.error("expected digit after '.'"));
+            }
+            self.digits();
+        }
+
+        if matches!(self.peek(), Some('e') | Some('E')) {
+            self.advance();
+            if matches!(self.peek(), Some('+') | Some('-')) {
+                self.advance();
+            }
+            if !self.peek().is_some_and(|c| c.is_ascii_digit()) {
+                return Err(self.error("expected digit in exponent"));
+            }
+            self.digits();
+        }
+
+        let text: String = self.chars[start..self.current].iter().collect();
+
+        return text.parse::<f64>()
+            .map(Value::Number)
+            .map_err(|_| format!("Invalid JSON at line {}, column {}: invalid number", line, column));
+    }
+
+    fn digits(&mut self) {
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.advance();
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for expected in word.chars() {
+            if self.peek() != Some(expected) {
+                return Err(self.error(&format!("expected '{}'", word)));
+            }
+            self.advance();
+        }
+
+        return Ok(value);
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("expected '{}'", expected)));
+        }
+
+        self.advance();
+        return Ok(());
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t') | Some('\n') | Some('\r')) {
+            self.advance();
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        return self.chars.get(self.current).copied();
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.current += 1;
+
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        return Some(c);
+    }
+
+    fn error(&self, message: &str) -> String {
+        return format!("Invalid JSON at line {}, column {}: {}", self.line, self.column, message);
+    }
+
+    // For errors found after consuming the offending character, so the column points at it.
+    fn error_before(&self, message: &str) -> String {
+        return format!("Invalid JSON at line {}, column {}: {}", self.line, self.column.saturating_sub(1).max(1), message);
+    }
+}
+
+struct Writer {
+    output: String,
+    indent: Option<usize>,
+    // Collections being written, to reject values that contain themselves.
+    open: Vec<*const ()>,
+}
+
+impl Writer {
+    fn write(&mut self, value: &Value, depth: usize) -> Result<(), RuntimeError> {
+        match value {
+            Value::Null => self.output.push_str("null"),
+            Value::Boolean(b) => self.output.push_str(&b.to_string()),
+            Value::Number(n) if n.is_finite() => self.output.push_str(&n.to_string()),
+            Value::Number(n) => return Err(unserializable(&format!("number {}", n))),
+            Value::StringValue(s) => self.string(s),
+            Value::List(elements) => {
+                self.enter(Rc::as_ptr(elements) as *const ())?;
+                let elements = elements.borrow();
+
+                self.output.push('[');
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        self.output.push(',');
+                    }
+                    self.newline(depth + 1);
+                    self.write(element, depth + 1)?;
+                }
+                if !elements.is_empty() {
+                    self.newline(depth);
+                }
+                self.output.push(']');
+
+                self.open.pop();
+            },
+            Value::Map(entries) => {
+                self.enter(Rc::as_ptr(entries) as *const ())?;
+                let entries = entries.borrow();
+
+                self.output.push('{');
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.output.push(',');
+                    }
+                    self.newline(depth + 1);
+                    self.string(key);
+                    self.output.push_str(if self.indent.is_some() { ": " } else { ":" });
+                    self.write(value, depth + 1)?;
+                }
+                if !entries.is_empty() {
+                    self.newline(depth);
+                }
+                self.output.push('}');
+
+                self.open.pop();
+            },
+            value => return Err(unserializable(value.type_name())),
+        }
+
+        return Ok(());
+    }
+
+    fn enter(&mut self, collection: *const ()) -> Result<(), RuntimeError> {
+        if self.open.contains(&collection) {
+            return Err(RuntimeError::new(ErrorKind::ValueError, "Cannot serialize a value that contains itself to JSON".to_string(), 0));
+        }
+
+        if self.open.len() == MAX_DEPTH {
+            return Err(RuntimeError::new(ErrorKind::ValueError, format!("Cannot serialize values nested deeper than {} levels to JSON", MAX_DEPTH), 0));
+        }
+
+        self.open.push(collection);
+        return Ok(());
+    }
+
+    fn newline(&mut self, depth: usize) {
+        if let Some(indent) = self.indent {
+            self.output.push('\n');
+            self.output.push_str(&" ".repeat(indent * depth));
+        }
+    }
+
+    fn string(&mut self, s: &str) {
+        self.output.push('"');
+
+        for c in s.chars() {
+            match c {
+                '"' => self.output.push_str("\\\""),
+                '\\' => self.output.push_str("\\\\"),
+                '\n' => self.output.push_str("\\n"),
+                '\r' => self.output.push_str("\\r"),
+                '\t' => self.output.push_str("\\t"),
+                c if (c as u32) < 0x20 => self.output.push_str(&format!("\\u{:04x}", c as u32)),
+                c => self.output.push(c),
+            }
+        }
+
+        self.output.push('"');
+    }
+}
+
+fn unserializable(what: &str) -> RuntimeError {
+    return RuntimeError::new(ErrorKind::TypeError, format!("Cannot serialize {} to JSON", what), 0);
+}
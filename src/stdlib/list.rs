@@ -0,0 +1,157 @@
+use std::cmp::Ordering;
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::gc;
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{index, list};
+
+pub fn register(interpreter: &mut Interpreter) {
+    interpreter.register_method("list", "len", Arity::Fixed(0), |_, arguments| {
+        Ok(Value::Number(list(&arguments[0])?.borrow().len() as f64))
+    });
+
+    interpreter.register_method("list", "push", Arity::Fixed(1), |_, arguments| {
+        gc::charge(size_of::<Value>());
+        list(&arguments[0])?.borrow_mut().push(arguments[1].clone());
+        Ok(Value::Null)
+    });
+
+    // Returns the removed element, or null when the list is empty.
+    interpreter.register_method("list", "pop", Arity::Fixed(0), |_, arguments| {
+        Ok(list(&arguments[0])?.borrow_mut().pop().unwrap_or(Value::Null))
+    });
+
+    interpreter.register_method("list", "insert", Arity::Fixed(2), |_, arguments| {
+        let elements = list(&arguments[0])?;
+        let position = index(&arguments, 1)?;
+
+        if position > elements.borrow().len() {
+            return Err(out_of_range(position, elements.borrow().len()));
+        }
+
+        gc::charge(size_of::<Value>());
+        elements.borrow_mut().insert(position, arguments[2].clone());
+        Ok(Value::Null)
+    });
+
+    interpreter.register_method("list", "remove", Arity::Fixed(1), |_, arguments| {
+        let elements = list(&arguments[0])?;
+        let position = index(&arguments, 1)?;
+
+        if position >= elements.borrow().len() {
+            return Err(out_of_range(position, elements.borrow().len()));
+        }
+
+        let removed = elements.borrow_mut().remove(position);
+        Ok(removed)
+    });
+
+    interpreter.register_method("list", "reverse", Arity::Fixed(0), |_, arguments| {
+        list(&arguments[0])?.borrow_mut().reverse();
+        Ok(arguments[0].clone())
+    });
+
+    // Sorts in place and returns the list. Without a comparator numbers and strings sort
+    // naturally; a comparator gets two elements and returns a negative, zero or positive number.
+    interpreter.register_method("list", "sort", Arity::Variadic, |interpreter, arguments| {
+        if arguments.len() > 2 {
+            return Err(RuntimeError::new(ErrorKind::TypeError, format!("list.sort expected at most 1 arguments but got {}", arguments.len() - 1), 0));
+        }
+
+        let elements = list(&arguments[0])?;
+        let unsorted = elements.borrow().clone();
+
+        let sorted = match arguments.get(1) {
+            Some(comparator) => merge_sort(unsorted, &mut |a, b| {
+                match interpreter.call_value(comparator.clone(), vec![a.clone(), b.clone()])? {
+                    Value::Number(n) => Ok(n.partial_cmp(&0.0).unwrap_or(Ordering::Equal)),
+                    value => Err(RuntimeError::new(ErrorKind::TypeError, format!("Comparator must return a number, got {}", value.type_name()), 0)),
+                }
+            })?,
+            None => merge_sort(unsorted, &mut compare)?,
+        };
+
+        *elements.borrow_mut() = sorted;
+        Ok(arguments[0].clone())
+    });
+
+    // Callbacks may modify the list, so they run over a copy of its elements.
+    interpreter.register_method("list", "map", Arity::Fixed(1), |interpreter, arguments| {
+        let elements = list(&arguments[0])?.borrow().clone();
+        let mut mapped = vec![];
+
+        for element in elements {
+            mapped.push(interpreter.call_value(arguments[1].clone(), vec![element])?);
+        }
+
+        Ok(Value::list(mapped))
+    });
+
+    interpreter.register_method("list", "filter", Arity::Fixed(1), |interpreter, arguments| {
+        let elements = list(&arguments[0])?.borrow().clone();
+        let mut kept = vec![];
+
+        for element in elements {
+            if interpreter.call_value(arguments[1].clone(), vec![element.clone()])?.is_truthy() {
+                kept.push(element);
+            }
+        }
+
+        Ok(Value::list(kept))
+    });
+
+    interpreter.register_method("list", "reduce", Arity::Fixed(2), |interpreter, arguments| {
+        let elements = list(&arguments[0])?.borrow().clone();
+        let mut accumulator = arguments[2].clone();
+
+        for element in elements {
+            accumulator = interpreter.call_value(arguments[1].clone(), vec![accumulator, element])?;
+        }
+
+        Ok(accumulator)
+    });
+}
+
+fn out_of_range(position: usize, len: usize) -> RuntimeError {
+    return RuntimeError::new(ErrorKind::IndexError, format!("Index {} out of range for list of length {}", position, len), 0);
+}
+
+fn compare(a: &Value, b: &Value) -> Result<Ordering, RuntimeError> {
+    return match (a, b) {
+        (Value::Number(a), Value::Number(b)) => Ok(a.partial_cmp(b).unwrap_or(Ordering::Equal)),
+        (Value::StringValue(a), Value::StringValue(b)) => Ok(a.cmp(b)),
+        (a, b) => Err(RuntimeError::new(ErrorKind::TypeError, format!("Cannot compare {} with {}", a.type_name(), b.type_name()), 0)),
+    }
+}
+
+// A stable merge sort that stops at the first comparator error. The standard library's
+// sorts can't propagate errors and may panic on a comparator that isn't a total order.
+fn merge_sort<F>(mut elements: Vec<Value>, compare: &mut F) -> Result<Vec<Value>, RuntimeError>
+where
+    F: FnMut(&Value, &Value) -> Result<Ordering, RuntimeError>,
+{
+    if elements.len() <= 1 {
+        return Ok(elements);
+    }
+
+    let right = elements.split_off(elements.len() / 2);
+    let left = merge_sort(elements, compare)?;
+    let right = merge_sort(right, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        if compare(b, a)? == Ordering::Less {
+            merged.push(right.next().unwrap());
+        } else {
+            merged.push(left.next().unwrap());
+        }
+    }
+
+    merged.extend(left);
+    merged.extend(right);
+
+    return Ok(merged);
+}
@@ -0,0 +1,39 @@
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{argument, map};
+
+pub fn register(interpreter: &mut Interpreter) {
+    interpreter.register_method("map", "len", Arity::Fixed(0), |_, arguments| {
+        Ok(Value::Number(map(&arguments[0])?.borrow().len() as f64))
+    });
+
+    interpreter.register_method("map", "keys", Arity::Fixed(0), |_, arguments| {
+        let keys = map(&arguments[0])?.borrow().keys().map(|key| Value::from(key.clone())).collect();
+        Ok(Value::list(keys))
+    });
+
+    interpreter.register_method("map", "values", Arity::Fixed(0), |_, arguments| {
+        let values = map(&arguments[0])?.borrow().values().cloned().collect();
+        Ok(Value::list(values))
+    });
+
+    // Each entry is a `[key, value]` pair, ready to destructure with `let [key, value] = ...`.
+    interpreter.register_method("map", "entries", Arity::Fixed(0), |_, arguments| {
+        let entries = map(&arguments[0])?.borrow().iter()
+            .map(|(key, value)| Value::list(vec![Value::from(key.clone()), value.clone()]))
+            .collect();
+
+        Ok(Value::list(entries))
+    });
+
+    interpreter.register_method("map", "has", Arity::Fixed(1), |_, arguments| {
+        let key: String = argument(&arguments, 1)?;
+        Ok(Value::Boolean(map(&arguments[0])?.borrow().contains_key(&key)))
+    });
+
+    // Returns whether the key was present.
+    interpreter.register_method("map", "delete", Arity::Fixed(1), |_, arguments| {
+        let key: String = argument(&arguments, 1)?;
+        Ok(Value::Boolean(map(&arguments[0])?.borrow_mut().remove(&key).is_some()))
+    });
+}
@@ -0,0 +1,96 @@
+use std::cell::Cell;
+use std::f64::consts::{E, PI};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{argument, module, native};
+
+pub fn register(interpreter: &mut Interpreter) {
+    let seed = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+    let state = Rc::new(Cell::new(scramble(seed)));
+    let random_state = state.clone();
+
+    let math = module("math", vec![
+        ("pi", Value::Number(PI)),
+        ("e", Value::Number(E)),
+        ("floor", unary("math.floor", f64::floor)),
+        ("ceil", unary("math.ceil", f64::ceil)),
+        ("round", unary("math.round", f64::round)),
+        ("sqrt", unary("math.sqrt", f64::sqrt)),
+        ("abs", unary("math.abs", f64::abs)),
+        ("sin", unary("math.sin", f64::sin)),
+        ("cos", unary("math.cos", f64::cos)),
+        ("tan", unary("math.tan", f64::tan)),
+        ("asin", unary("math.asin", f64::asin)),
+        ("acos", unary("math.acos", f64::acos)),
+        ("atan", unary("math.atan", f64::atan)),
+        ("atan2", binary("math.atan2", f64::atan2)),
+        ("pow", binary("math.pow", f64::powf)),
+        ("min", fold("math.min", f64::min)),
+        ("max", fold("math.max", f64::max)),
+        ("random", native("math.random", Arity::Fixed(0), move |_, _| {
+            Ok(Value::Number(next(&random_state)))
+        })),
+        ("seed", native("math.seed", Arity::Fixed(1), move |_, arguments| {
+            let seed: f64 = argument(&arguments, 0)?;
+            state.set(scramble(seed.to_bits()));
+
+            Ok(Value::Null)
+        })),
+    ]);
+
+    interpreter.register_global("math", math);
+}
+
+fn unary(name: &str, function: fn(f64) -> f64) -> Value {
+    return native(name, Arity::Fixed(1), move |_, arguments| {
+        Ok(Value::Number(function(argument(&arguments, 0)?)))
+    });
+}
+
+fn binary(name: &str, function: fn(f64, f64) -> f64) -> Value {
+    return native(name, Arity::Fixed(2), move |_, arguments| {
+        Ok(Value::Number(function(argument(&arguments, 0)?, argument(&arguments, 1)?)))
+    });
+}
+
+fn fold(name: &str, function: fn(f64, f64) -> f64) -> Value {
+    let message = format!("{} expects at least one argument", name);
+
+    return native(name, Arity::Variadic, move |_, arguments| {
+        let mut result: f64 = match arguments.first() {
+            Some(_) => argument(&arguments, 0)?,
+            None => return Err(RuntimeError::new(ErrorKind::TypeError, message.clone(), 0)),
+        };
+
+        for position in 1..arguments.len() {
+            result = function(result, argument(&arguments, position)?);
+        }
+
+        Ok(Value::Number(result))
+    });
+}
+
+// Spreads the bits of a seed so nearby seeds start far apart, and never yields zero,
+// which would stall xorshift forever.
+fn scramble(seed: u64) -> u64 {
+    let mut x = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+
+    return if x == 0 { 1 } else { x };
+}
+
+// xorshift64*, returning a float in [0, 1).
+fn next(state: &Cell<u64>) -> f64 {
+    let mut x = state.get();
+    x ^= x >> 12;
+    x ^= x << 25;
+    x ^= x >> 27;
+    state.set(x);
+
+    return (x.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 11) as f64 / (1u64 << 53) as f64;
+}
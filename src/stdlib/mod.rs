@@ -0,0 +1,363 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::module::Module;
+use crate::value::{Arity, NativeFunction, Value};
+
+mod fs;
+mod gc;
+mod io;
+mod json;
+mod list;
+mod map;
+mod math;
+mod process;
+mod regex;
+mod string;
+mod time;
+
+pub fn register_defaults(interpreter: &mut Interpreter) {
+    interpreter.register_native("clock", Arity::Fixed(0), |_, _| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+
+        Ok(Value::Number(now.as_secs_f64()))
+    });
+
+    math::register(interpreter);
+    string::register(interpreter);
+    list::register(interpreter);
+    map::register(interpreter);
+    json::register(interpreter);
+    time::register(interpreter);
+    regex::register(interpreter);
+    gc::register(interpreter);
+
+    match interpreter.capabilities().io {
+        true => io::register(interpreter),
+        false => deny(interpreter, "io", "io", &["input", "eprint"]),
+    }
+
+    match interpreter.capabilities().fs {
+        true => fs::register(interpreter),
+        false => deny(interpreter, "fs", "fs", &[]),
+    }
+
+    match interpreter.capabilities().process {
+        true => process::register(interpreter, vec![]),
+        false => deny(interpreter, "process", "process", &[]),
+    }
+}
+
+// Makes `args` visible to the script as `process.args`.
+pub fn set_process_args(interpreter: &mut Interpreter, args: Vec<String>) {
+    if interpreter.capabilities().process {
+        process::register(interpreter, args);
+    }
+}
+
+// Builds a module value out of natives and constants, for builtin modules like `math`.
+fn module(name: &str, members: Vec<(&str, Value)>) -> Value {
+    let exports = members.into_iter()
+        .map(|(member, value)| (member.to_string(), value))
+        .collect();
+
+    return Value::Module(Rc::new(Module { name: name.to_string(), exports, denied: None }));
+}
+
+// Stands in for a builtin module and global functions whose capability is turned off, so
+// using them says why they're unavailable instead of that they don't exist.
+fn deny(interpreter: &mut Interpreter, capability: &'static str, name: &str, functions: &[&str]) {
+    let module = Module { name: name.to_string(), exports: BTreeMap::new(), denied: Some(capability) };
+    interpreter.register_global(name, Value::Module(Rc::new(module)));
+
+    for function in functions {
+        interpreter.register_global(function, denied_native(function, capability));
+    }
+}
+
+fn denied_native(name: &str, capability: &'static str) -> Value {
+    let message = format!("'{}' needs the {} capability", name, capability);
+
+    return native(name, Arity::Variadic, move |_, _| Err(capability_denied(&message)));
+}
+
+pub(crate) fn capability_denied(message: &str) -> RuntimeError {
+    return RuntimeError::new(ErrorKind::CapabilityDenied, format!("Capability denied: {}", message), 0);
+}
+
+fn native<F>(name: &str, arity: Arity, function: F) -> Value
+where
+    F: Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+{
+    return Value::NativeFunction(Rc::new(NativeFunction {
+        name: name.to_string(),
+        arity,
+        function: Box::new(function),
+    }));
+}
+
+fn argument<T: TryFrom<Value, Error = RuntimeError>>(arguments: &[Value], index: usize) -> Result<T, RuntimeError> {
+    return match arguments.get(index) {
+        Some(value) => T::try_from(value.clone()),
+        None => Err(RuntimeError::new(ErrorKind::TypeError, format!("Missing argument {}", index + 1), 0)),
+    }
+}
+
+// A whole, non-negative number usable as a position in a list or string.
+fn index(arguments: &[Value], position: usize) -> Result<usize, RuntimeError> {
+    let n: f64 = argument(arguments, position)?;
+
+    if n < 0.0 || n.fract() != 0.0 {
+        return Err(RuntimeError::new(ErrorKind::IndexError, format!("Invalid index {}", n), 0));
+    }
+
+    return Ok(n as usize);
+}
+
+fn list(value: &Value) -> Result<Rc<RefCell<Vec<Value>>>, RuntimeError> {
+    return match value {
+        Value::List(elements) => Ok(elements.clone()),
+        value => Err(RuntimeError::new(ErrorKind::TypeError, format!("Expected list, got {}", value.type_name()), 0)),
+    }
+}
+
+fn map(value: &Value) -> Result<Rc<RefCell<BTreeMap<String, Value>>>, RuntimeError> {
+    return match value {
+        Value::Map(entries) => Ok(entries.clone()),
+        value => Err(RuntimeError::new(ErrorKind::TypeError, format!("Expected map, got {}", value.type_name()), 0)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::capabilities::Capabilities;
+    use crate::engine::{Engine, EngineError};
+    use crate::interpreter::{Backend, Interpreter};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::test_dir::TestDir;
+    use crate::value::Value;
+
+    fn run(source: &str) -> String {
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let statements = parser.parse_statements().unwrap();
+
+        return match Interpreter::new().interpret(&statements) {
+            Ok(value) => value.to_string(),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_math() {
+        assert_eq!(run("[math.floor(2.7), math.ceil(2.1), math.sqrt(16), math.pow(2, 10), math.abs(-3)];"), "[2, 3, 4, 1024, 3]");
+        assert_eq!(run("[math.min(3, 1, 2), math.max(3, 1, 2), math.round(math.cos(math.pi))];"), "[1, 3, -1]");
+        assert_eq!(run("math.min();"), "TypeError at line 1: math.min expects at least one argument");
+    }
+
+    #[test]
+    fn test_seeded_random_is_repeatable() {
+        let source = "math.seed(42); let a = math.random(); math.seed(42); let b = math.random(); [a == b, a >= 0, a < 1];";
+        assert_eq!(run(source), "[true, true, true]");
+    }
+
+    #[test]
+    fn test_string_methods() {
+        assert_eq!(run(r#""a,b,c".split(",");"#), r#"["a", "b", "c"]"#);
+        assert_eq!(run(r#"", ".join([1, "two"]);"#), "1, two");
+        assert_eq!(run(r#"[" hi ".trim(), "Hi".upper(), "Hi".lower(), "aXa".replace("a", "b")];"#), r#"["hi", "HI", "hi", "bXb"]"#);
+        assert_eq!(run(r#"["hello".len(), "hello".contains("ell"), "hello".starts_with("he"), "hello".find("l"), "hello".find("z")];"#), "[5, true, true, 2, null]");
+    }
+
+    #[test]
+    fn test_list_methods() {
+        let source = "let xs = [3, 1, 2]; xs.push(4); let last = xs.pop(); xs.insert(0, 5); let removed = xs.remove(1); [xs, last, removed];";
+        assert_eq!(run(source), "[[5, 1, 2], 4, 3]");
+
+        assert_eq!(run("[3, 1, 2].sort();"), "[1, 2, 3]");
+        assert_eq!(run("fn desc(a, b) { return b - a; } [3, 1, 2].sort(desc);"), "[3, 2, 1]");
+        assert_eq!(run("[1, 2, 3].reverse();"), "[3, 2, 1]");
+        assert_eq!(run("[1, \"a\"].sort();"), "TypeError at line 1: Cannot compare string with number");
+        assert_eq!(run("[1].remove(3);"), "IndexError at line 1: Index 3 out of range for list of length 1");
+    }
+
+    #[test]
+    fn test_list_higher_order_methods() {
+        let source = "fn double(x) { return x * 2; } fn big(x) { return x > 1; } fn add(a, b) { return a + b; } \
+            let xs = [1, 2, 3]; [xs.map(double), xs.filter(big), xs.reduce(add, 0)];";
+        assert_eq!(run(source), "[[2, 4, 6], [2, 3], 6]");
+    }
+
+    #[test]
+    fn test_map_methods() {
+        let source = "let m = {b: 2, a: 1}; let deleted = m.delete(\"b\"); [m.keys(), m.values(), m.entries(), m.has(\"a\"), m.has(\"b\"), deleted, m.len()];";
+        assert_eq!(run(source), "[[\"a\"], [1], [[\"a\", 1]], true, false, true, 1]");
+
+        assert_eq!(run("let m = {len: 7}; m.len;"), "7");
+    }
+
+    #[test]
+    fn test_fs_round_trip() {
+        let directory = TestDir::new("fs");
+        let source = format!(r#"
+            let root = "{}";
+            fs.mkdir(root + "/nested");
+            fs.write_text(root + "/notes.txt", "one");
+            fs.append(root + "/notes.txt", ", two");
+            let text = fs.read_text(root + "/notes.txt");
+            let listed = fs.list_dir(root);
+            fs.remove(root + "/notes.txt");
+            [text, listed, fs.exists(root + "/notes.txt")];
+        "#, directory.display());
+
+        assert_eq!(run(&source), r#"["one, two", ["nested", "notes.txt"], false]"#);
+    }
+
+    #[test]
+    fn test_fs_errors_are_catchable() {
+        let source = r#"try { fs.read_text("/definitely/not/here.txt"); } catch (e) { throw e.kind; }"#;
+
+        assert_eq!(run(source), "Error at line 1: IoError");
+    }
+
+    #[test]
+    fn test_disabled_capabilities_are_denied() {
+        let cases = [
+            (r#"fs.read_text("secrets.txt");"#, "CapabilityDenied at line 1: Capability denied: 'fs.read_text' needs the fs capability"),
+            ("process.args;", "CapabilityDenied at line 1: Capability denied: 'process.args' needs the process capability"),
+            ("input();", "CapabilityDenied at line 1: Capability denied: 'input' needs the io capability"),
+            (r#"import "fs" as files;"#, "CapabilityDenied at line 1: Capability denied: module 'fs' needs the fs capability"),
+            (r#"import { exit } from "process";"#, "CapabilityDenied at line 1: Capability denied: module 'process' needs the process capability"),
+            (r#"try { fs.exists("/"); } catch (e) { throw e.kind; }"#, "Error at line 1: CapabilityDenied"),
+            (r#"import { sqrt } from "math"; import "json" as j; [sqrt(16), j.stringify([1])];"#, "[4, \"[1]\"]"),
+        ];
+
+        for backend in [Backend::Tree, Backend::Vm] {
+            for (source, expected) in cases {
+                let mut engine = Engine::with_capabilities(Capabilities::none());
+                engine.set_backend(backend);
+
+                let result = match engine.eval(source) {
+                    Ok(value) => value.to_string(),
+                    Err(EngineError::Runtime(error)) => error.to_string(),
+                    Err(error) => panic!("{:?}", error),
+                };
+
+                assert_eq!(result, expected, "{:?} on {:?}", source, backend);
+            }
+        }
+    }
+
+    // String literals have no escapes, so JSON text with quotes comes in through a global.
+    fn run_with_text(source: &str, text: &str) -> String {
+        let mut scanner = Scanner::new(source);
+        let statements = Parser::new(scanner.scan_tokens().unwrap()).parse_statements().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_global("text", Value::from(text));
+
+        return match interpreter.interpret(&statements) {
+            Ok(value) => value.to_string(),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_json_parse() {
+        let text = r#"{"name": "platypus", "tags": [1, 2.5, -3e2, true, null], "escaped": "a\nb é 🦆"}"#;
+        assert_eq!(run_with_text("json.parse(text);", text), "{escaped: \"a\\nb é 🦆\", name: \"platypus\", tags: [1, 2.5, -300, true, null]}");
+
+        assert_eq!(run_with_text("json.parse(text);", "[1,\n  2,]"), "ValueError at line 1: Invalid JSON at line 2, column 5: unexpected character ']'");
+        assert_eq!(run_with_text("json.parse(text);", r#"{"a" 1}"#), "ValueError at line 1: Invalid JSON at line 1, column 6: expected ':'");
+        assert_eq!(run_with_text("json.parse(text);", "[1] 2"), "ValueError at line 1: Invalid JSON at line 1, column 5: unexpected trailing characters");
+
+        let deep = format!("{}{}", "[".repeat(512), "]".repeat(512));
+        assert_eq!(run_with_text("json.stringify(json.parse(text)) == text;", &deep), "true");
+        let deeper = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert_eq!(run_with_text("json.parse(text);", &deeper), "ValueError at line 1: Invalid JSON at line 1, column 513: nested deeper than 512 levels");
+    }
+
+    #[test]
+    fn test_json_stringify() {
+        assert_eq!(run(r#"json.stringify({b: [1, "two"], a: null});"#), r#"{"a":null,"b":[1,"two"]}"#);
+        assert_eq!(run("json.stringify({a: [1], b: {}}, 2);"), "{\n  \"a\": [\n    1\n  ],\n  \"b\": {}\n}");
+        assert_eq!(run("json.stringify([1], 1000000000000000000);"), format!("[\n{}1\n]", " ".repeat(10)));
+        assert_eq!(run_with_text("json.stringify(text);", "say \"hi\"\n"), r#""say \"hi\"\n""#);
+        assert_eq!(run("fn f() {} json.stringify([f]);"), "TypeError at line 1: Cannot serialize function to JSON");
+        assert_eq!(run("let xs = []; xs.push(xs); json.stringify(xs);"), "ValueError at line 1: Cannot serialize a value that contains itself to JSON");
+        assert_eq!(run("fn wrap(x, n) { return match n { 0 => x, _ => wrap([x], n - 1) }; } json.stringify(wrap(1, 513));"), "ValueError at line 1: Cannot serialize values nested deeper than 512 levels to JSON");
+    }
+
+    #[test]
+    fn test_time_format_and_parse() {
+        assert_eq!(run("time.format(0);"), "1970-01-01T00:00:00Z");
+        assert_eq!(run("time.format(951782400.25);"), "2000-02-29T00:00:00.250Z");
+        assert_eq!(run(r#"time.parse("2000-02-29T00:00:00.250Z");"#), "951782400.25");
+        assert_eq!(run(r#"time.parse("2024-06-01T12:30:00+02:00") == time.parse("2024-06-01T10:30:00Z");"#), "true");
+        assert_eq!(run(r#"time.format(time.parse("1969-12-31"));"#), "1969-12-31T00:00:00Z");
+        assert_eq!(run(r#"time.parse("2023-02-29");"#), "ValueError at line 1: Invalid ISO-8601 time '2023-02-29'");
+    }
+
+    #[test]
+    fn test_monotonic_time_advances_across_sleep() {
+        assert_eq!(run("let start = time.monotonic(); time.sleep(5); time.monotonic() - start >= 0.005;"), "true");
+        assert_eq!(run("time.sleep(-1);"), "ValueError at line 1: Cannot sleep for -1 ms");
+        assert_eq!(run("let n = 1000000000000000000000000000000; try { time.sleep(n * n); } catch (e) { throw e.kind; }"), "Error at line 1: ValueError");
+    }
+
+    #[test]
+    fn test_process_args_and_env() {
+        let mut scanner = Scanner::new(r#"[process.args, process.env("PLATYPUS_TEST_UNSET_VARIABLE")];"#);
+        let statements = Parser::new(scanner.scan_tokens().unwrap()).parse_statements().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_args(vec!["a".to_string(), "b".to_string()]);
+
+        assert_eq!(interpreter.interpret(&statements).unwrap().to_string(), r#"[["a", "b"], null]"#);
+    }
+
+    #[test]
+    fn test_process_exit_skips_catch_but_runs_finally() {
+        let mut scanner = Scanner::new("let log = []; try { process.exit(3); } catch (e) { log.push(\"catch\"); } finally { log.push(\"finally\"); }");
+        let statements = Parser::new(scanner.scan_tokens().unwrap()).parse_statements().unwrap();
+        let mut interpreter = Interpreter::new();
+        let error = interpreter.interpret(&statements).unwrap_err();
+
+        assert_eq!(error.exit_code, Some(3));
+        assert_eq!(interpreter.get_global("log").unwrap().to_string(), r#"["finally"]"#);
+    }
+
+    #[test]
+    fn test_process_run_requires_subprocess_capability() {
+        assert_eq!(run(r#"process.run("echo", ["hi"]);"#), "CapabilityDenied at line 1: Capability denied: 'process.run' needs the subprocess capability");
+
+        let mut scanner = Scanner::new(r#"let result = process.run("echo", ["hi"]); [result.status, result.stdout];"#);
+        let statements = Parser::new(scanner.scan_tokens().unwrap()).parse_statements().unwrap();
+        let capabilities = Capabilities { subprocess: true, ..Capabilities::default() };
+
+        assert_eq!(Interpreter::with_capabilities(capabilities).interpret(&statements).unwrap().to_string(), "[0, \"hi\\n\"]");
+    }
+
+    #[test]
+    fn test_regex_functions_and_methods() {
+        assert_eq!(run(r#"regex.is_match(r"^\d+$", "2024");"#), "true");
+        assert_eq!(run(r#"regex.find(r"\d+", "abc 42 7");"#), r#"{end: 6, start: 4, text: "42"}"#);
+        assert_eq!(run(r#"let digits = regex.compile(r"\d+"); digits.find_all("1 22 333").len();"#), "3");
+        assert_eq!(run(r#"regex.compile(r"\s*,\s*").split("a , b,c");"#), r#"["a", "b", "c"]"#);
+        assert_eq!(run(r#"regex.replace(r"(\w+)@(\w+)", "me@host you@there", "$2:$1");"#), "host:me there:you");
+    }
+
+    #[test]
+    fn test_regex_captures() {
+        let source = r#"regex.captures(r"(?P<level>[A-Z]+): (\w+)?(x)?", "ERROR: disk");"#;
+        assert_eq!(run(source), r#"{0: "ERROR: disk", 1: "ERROR", 2: "disk", 3: null, level: "ERROR"}"#);
+        assert_eq!(run(r#"regex.captures("z", "abc");"#), "null");
+    }
+
+    #[test]
+    fn test_regex_compile_errors_are_catchable() {
+        assert_eq!(run(r#"try { regex.compile("(unclosed"); } catch (e) { throw e.kind; }"#), "Error at line 1: ValueError");
+    }
+}
@@ -0,0 +1,94 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{argument, denied_native, list, module, native};
+
+pub fn register(interpreter: &mut Interpreter, args: Vec<String>) {
+    let mut members = vec![
+        ("args", Value::from(args)),
+        // Returns null for variables that are unset or not valid unicode.
+        ("env", native("process.env", Arity::Fixed(1), |_, arguments| {
+            let name: String = argument(&arguments, 0)?;
+            Ok(Value::from(env::var(name).ok()))
+        })),
+        ("exit", native("process.exit", Arity::Variadic, |_, arguments| {
+            let code = match arguments.len() {
+                0 => 0.0,
+                1 => argument(&arguments, 0)?,
+                n => return Err(RuntimeError::new(ErrorKind::TypeError, format!("process.exit expected at most 1 arguments but got {}", n), 0)),
+            };
+
+            Err(RuntimeError::exit(code as i32))
+        })),
+    ];
+
+    match interpreter.capabilities().subprocess {
+        true => members.push(("run", native("process.run", Arity::Variadic, run))),
+        false => members.push(("run", denied_native("process.run", "subprocess"))),
+    }
+
+    interpreter.register_global("process", module("process", members));
+}
+
+// Runs a program to completion and returns `{status, stdout, stderr}`. The status is null
+// when the program was killed by a signal.
+fn run(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let program: String = argument(&arguments, 0)?;
+    let mut command = Command::new(&program);
+
+    match arguments.len() {
+        1 => (),
+        2 => {
+            for arg in list(&arguments[1])?.borrow().iter() {
+                command.arg(String::try_from(arg.clone())?);
+            }
+        },
+        n => return Err(RuntimeError::new(ErrorKind::TypeError, format!("process.run expected 1 or 2 arguments but got {}", n), 0)),
+    }
+
+    let failed = |e: std::io::Error| RuntimeError::new(ErrorKind::IoError, format!("{}: {}", program, e), 0);
+    let mut child = command.stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map_err(failed)?;
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    // Waits in slices, so the run's timeout and cancel handle stop the program too.
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(failed)? {
+            break status;
+        }
+
+        if let Err(error) = interpreter.sleep(WAIT_SLICE) {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(error);
+        }
+    };
+
+    return Ok(Value::map(BTreeMap::from([
+        ("status".to_string(), Value::from(status.code().map(|code| code as i64))),
+        ("stdout".to_string(), Value::from(String::from_utf8_lossy(&stdout.join().unwrap_or_default()).to_string())),
+        ("stderr".to_string(), Value::from(String::from_utf8_lossy(&stderr.join().unwrap_or_default()).to_string())),
+    ])));
+}
+
+const WAIT_SLICE: Duration = Duration::from_millis(2);
+
+// Drains a pipe on its own thread, so a program filling one pipe doesn't block on it while
+// the other is being read.
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    return thread::spawn(move || {
+        let mut bytes = vec![];
+
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut bytes);
+        }
+
+        bytes
+    });
+}
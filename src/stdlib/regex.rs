@@ -0,0 +1,115 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use regex::{Match, Regex};
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{argument, module, native};
+
+type RegexFn = fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError>;
+
+// Every operation takes the pattern first, so the same function serves as `regex.find(pattern, text)`
+// with a pattern string or compiled regex, and as the method `compiled.find(text)`.
+const OPERATIONS: [(&str, usize, RegexFn); 6] = [
+    ("is_match", 1, is_match),
+    ("find", 1, find),
+    ("find_all", 1, find_all),
+    ("captures", 1, captures),
+    ("replace", 2, replace),
+    ("split", 1, split),
+];
+
+pub fn register(interpreter: &mut Interpreter) {
+    let mut members = vec![
+        ("compile", native("regex.compile", Arity::Fixed(1), |_, arguments| {
+            Ok(Value::Regex(compiled(&arguments[0])?))
+        })),
+    ];
+
+    for (name, arity, function) in OPERATIONS {
+        members.push((name, native(&format!("regex.{}", name), Arity::Fixed(arity + 1), function)));
+        interpreter.register_method("regex", name, Arity::Fixed(arity), function);
+    }
+
+    interpreter.register_global("regex", module("regex", members));
+}
+
+fn compiled(pattern: &Value) -> Result<Rc<Regex>, RuntimeError> {
+    return match pattern {
+        Value::Regex(regex) => Ok(regex.clone()),
+        Value::StringValue(pattern) => Regex::new(pattern)
+            .map(Rc::new)
+            .map_err(|e| RuntimeError::new(ErrorKind::ValueError, format!("Invalid regex: {}", e), 0)),
+        value => Err(RuntimeError::new(ErrorKind::TypeError, format!("Expected regex or string, got {}", value.type_name()), 0)),
+    }
+}
+
+fn is_match(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let text: String = argument(&arguments, 1)?;
+    return Ok(Value::Boolean(compiled(&arguments[0])?.is_match(&text)));
+}
+
+// Returns `{text, start, end}` for the first match, or null.
+fn find(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let text: String = argument(&arguments, 1)?;
+    return Ok(compiled(&arguments[0])?.find(&text).map(|m| match_value(&text, m)).unwrap_or(Value::Null));
+}
+
+fn find_all(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let text: String = argument(&arguments, 1)?;
+    let matches = compiled(&arguments[0])?.find_iter(&text).map(|m| match_value(&text, m)).collect();
+
+    return Ok(Value::list(matches));
+}
+
+// Returns a map from group number and group name to the captured text, or null when
+// nothing matches. Groups that didn't take part in the match are null.
+fn captures(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let text: String = argument(&arguments, 1)?;
+    let regex = compiled(&arguments[0])?;
+
+    let captures = match regex.captures(&text) {
+        Some(captures) => captures,
+        None => return Ok(Value::Null),
+    };
+
+    let mut groups = BTreeMap::new();
+
+    for (i, name) in regex.capture_names().enumerate() {
+        let captured = Value::from(captures.get(i).map(|m| m.as_str()));
+
+        if let Some(name) = name {
+            groups.insert(name.to_string(), captured.clone());
+        }
+
+        groups.insert(i.to_string(), captured);
+    }
+
+    return Ok(Value::map(groups));
+}
+
+// Replaces every match. The replacement can refer to groups as `$1` or `${name}`.
+fn replace(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let text: String = argument(&arguments, 1)?;
+    let replacement: String = argument(&arguments, 2)?;
+
+    return Ok(Value::from(compiled(&arguments[0])?.replace_all(&text, replacement.as_str()).to_string()));
+}
+
+fn split(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let text: String = argument(&arguments, 1)?;
+    let parts: Vec<&str> = compiled(&arguments[0])?.split(&text).collect();
+
+    return Ok(Value::from(parts.into_iter().map(String::from).collect::<Vec<String>>()));
+}
+
+// Positions are in characters, matching `string.find`.
+fn match_value(text: &str, m: Match) -> Value {
+    let start = text[..m.start()].chars().count();
+
+    return Value::map(BTreeMap::from([
+        ("text".to_string(), Value::from(m.as_str())),
+        ("start".to_string(), Value::Number(start as f64)),
+        ("end".to_string(), Value::Number((start + m.as_str().chars().count()) as f64)),
+    ]));
+}
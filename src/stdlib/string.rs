@@ -0,0 +1,85 @@
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{argument, list};
+
+pub fn register(interpreter: &mut Interpreter) {
+    interpreter.register_method("string", "len", Arity::Fixed(0), |_, arguments| {
+        let s: String = argument(&arguments, 0)?;
+        Ok(Value::Number(s.chars().count() as f64))
+    });
+
+    interpreter.register_method("string", "split", Arity::Fixed(1), |_, arguments| {
+        let s: String = argument(&arguments, 0)?;
+        let separator: String = argument(&arguments, 1)?;
+
+        // An empty separator splits into characters rather than yielding empty pieces at each end.
+        let parts: Vec<Value> = match separator.is_empty() {
+            true => s.chars().map(|c| Value::from(c.to_string())).collect(),
+            false => s.split(separator.as_str()).map(Value::from).collect(),
+        };
+
+        Ok(Value::list(parts))
+    });
+
+    interpreter.register_method("string", "join", Arity::Fixed(1), |_, arguments| {
+        let separator: String = argument(&arguments, 0)?;
+        let parts: Vec<String> = list(&arguments[1])?.borrow().iter().map(|part| part.to_string()).collect();
+
+        Ok(Value::from(parts.join(&separator)))
+    });
+
+    interpreter.register_method("string", "trim", Arity::Fixed(0), |_, arguments| {
+        let s: String = argument(&arguments, 0)?;
+        Ok(Value::from(s.trim()))
+    });
+
+    interpreter.register_method("string", "replace", Arity::Fixed(2), |_, arguments| {
+        let s: String = argument(&arguments, 0)?;
+        let from: String = argument(&arguments, 1)?;
+        let to: String = argument(&arguments, 2)?;
+
+        Ok(Value::from(s.replace(&from, &to)))
+    });
+
+    interpreter.register_method("string", "upper", Arity::Fixed(0), |_, arguments| {
+        let s: String = argument(&arguments, 0)?;
+        Ok(Value::from(s.to_uppercase()))
+    });
+
+    interpreter.register_method("string", "lower", Arity::Fixed(0), |_, arguments| {
+        let s: String = argument(&arguments, 0)?;
+        Ok(Value::from(s.to_lowercase()))
+    });
+
+    interpreter.register_method("string", "contains", Arity::Fixed(1), |_, arguments| {
+        let s: String = argument(&arguments, 0)?;
+        let needle: String = argument(&arguments, 1)?;
+
+        Ok(Value::Boolean(s.contains(&needle)))
+    });
+
+    interpreter.register_method("string", "starts_with", Arity::Fixed(1), |_, arguments| {
+        let s: String = argument(&arguments, 0)?;
+        let prefix: String = argument(&arguments, 1)?;
+
+        Ok(Value::Boolean(s.starts_with(&prefix)))
+    });
+
+    interpreter.register_method("string", "ends_with", Arity::Fixed(1), |_, arguments| {
+        let s: String = argument(&arguments, 0)?;
+        let suffix: String = argument(&arguments, 1)?;
+
+        Ok(Value::Boolean(s.ends_with(&suffix)))
+    });
+
+    // Returns the character index of the first match, or null when there is none.
+    interpreter.register_method("string", "find", Arity::Fixed(1), |_, arguments| {
+        let s: String = argument(&arguments, 0)?;
+        let needle: String = argument(&arguments, 1)?;
+
+        Ok(match s.find(&needle) {
+            Some(byte) => Value::Number(s[..byte].chars().count() as f64),
+            None => Value::Null,
+        })
+    });
+}
@@ -0,0 +1,210 @@
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::interpreter::Interpreter;
+use crate::value::{Arity, Value};
+use super::{argument, module, native};
+
+pub fn register(interpreter: &mut Interpreter) {
+    let start = Instant::now();
+
+    let time = module("time", vec![
+        ("now", native("time.now", Arity::Fixed(0), |_, _| {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            Ok(Value::Number(now.as_secs_f64()))
+        })),
+        // Seconds since the interpreter started. Never goes backwards, so it's the one to time code with.
+        ("monotonic", native("time.monotonic", Arity::Fixed(0), move |_, _| {
+            Ok(Value::Number(start.elapsed().as_secs_f64()))
+        })),
+        ("sleep", native("time.sleep", Arity::Fixed(1), |interpreter, arguments| {
+            let ms: f64 = argument(&arguments, 0)?;
+
+            // Rejects negative, infinite and NaN times, and ones too long for a `Duration`.
+            let duration = Duration::try_from_secs_f64(ms / 1000.0)
+                .map_err(|_| RuntimeError::new(ErrorKind::ValueError, format!("Cannot sleep for {} ms", ms), 0))?;
+
+            interpreter.sleep(duration)?;
+            Ok(Value::Null)
+        })),
+        ("format", native("time.format", Arity::Fixed(1), |_, arguments| {
+            let seconds: f64 = argument(&arguments, 0)?;
+            format(seconds).map(Value::from)
+        })),
+        ("parse", native("time.parse", Arity::Fixed(1), |_, arguments| {
+            let text: String = argument(&arguments, 0)?;
+            parse(&text).map(Value::Number)
+        })),
+    ]);
+
+    interpreter.register_global("time", time);
+}
+
+// Formats epoch seconds as an ISO-8601 UTC timestamp, with milliseconds only when there are any.
+fn format(seconds: f64) -> Result<String, RuntimeError> {
+    if !seconds.is_finite() {
+        return Err(RuntimeError::new(ErrorKind::ValueError, format!("Cannot format {} as a time", seconds), 0));
+    }
+
+    let millis = (seconds * 1000.0).round() as i64;
+    let days = millis.div_euclid(86_400_000);
+    let of_day = millis.rem_euclid(86_400_000);
+    let (year, month, day) = civil_from_days(days);
+
+    let (hour, minute, second, milli) = (of_day / 3_600_000, of_day / 60_000 % 60, of_day / 1000 % 60, of_day % 1000);
+    let mut formatted = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, hour, minute, second);
+
+    if milli != 0 {
+        formatted.push_str(&format!(".{:03}", milli));
+    }
+
+    formatted.push('Z');
+
+    return Ok(formatted);
+}
+
+// Accepts `YYYY-MM-DD`, optionally followed by `THH:MM[:SS[.fff]]` and a `Z` or `±HH:MM` offset.
+// Times without an offset are taken as UTC.
+fn parse(text: &str) -> Result<f64, RuntimeError> {
+    let invalid = || RuntimeError::new(ErrorKind::ValueError, format!("Invalid ISO-8601 time '{}'", text), 0);
+    let mut reader = Reader { bytes: text.as_bytes(), current: 0 };
+
+    let year = reader.number(4).ok_or_else(invalid)?;
+    reader.expect(b'-').ok_or_else(invalid)?;
+    let month = reader.number(2).ok_or_else(invalid)?;
+    reader.expect(b'-').ok_or_else(invalid)?;
+    let day = reader.number(2).ok_or_else(invalid)?;
+
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+
+    let mut seconds = days_from_civil(year, month, day) as f64 * 86_400.0;
+
+    if reader.peek().is_some() {
+        if !matches!(reader.peek(), Some(b'T') | Some(b't') | Some(b' ')) {
+            return Err(invalid());
+        }
+        reader.current += 1;
+
+        let hour = reader.number(2).ok_or_else(invalid)?;
+        reader.expect(b':').ok_or_else(invalid)?;
+        let minute = reader.number(2).ok_or_else(invalid)?;
+        let mut second = 0.0;
+
+        if reader.peek() == Some(b':') {
+            reader.current += 1;
+            second = reader.number(2).ok_or_else(invalid)? as f64;
+
+            if reader.peek() == Some(b'.') {
+                reader.current += 1;
+                let start = reader.current;
+                while reader.peek().is_some_and(|b| b.is_ascii_digit()) {
+                    reader.current += 1;
+                }
+                if reader.current == start {
+                    return Err(invalid());
+                }
+                second += format!("0.{}", &text[start..reader.current]).parse::<f64>().map_err(|_| invalid())?;
+            }
+        }
+
+        if hour > 23 || minute > 59 || second >= 61.0 {
+            return Err(invalid());
+        }
+
+        seconds += (hour * 3600 + minute * 60) as f64 + second;
+
+        match reader.peek() {
+            None => (),
+            Some(b'Z') | Some(b'z') => reader.current += 1,
+            Some(sign @ (b'+' | b'-')) => {
+                reader.current += 1;
+                let offset_hours = reader.number(2).ok_or_else(invalid)?;
+                reader.expect(b':').ok_or_else(invalid)?;
+                let offset_minutes = reader.number(2).ok_or_else(invalid)?;
+                let offset = (offset_hours * 3600 + offset_minutes * 60) as f64;
+
+                // Local time minus its offset gives UTC.
+                seconds += if sign == b'+' { -offset } else { offset };
+            },
+            Some(_) => return Err(invalid()),
+        }
+    }
+
+    if reader.peek().is_some() {
+        return Err(invalid());
+    }
+
+    return Ok(seconds);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    current: usize,
+}
+
+impl Reader<'_> {
+    fn peek(&self) -> Option<u8> {
+        return self.bytes.get(self.current).copied();
+    }
+
+    fn number(&mut self, digits: usize) -> Option<i64> {
+        let mut value = 0;
+
+        for _ in 0..digits {
+            let digit = self.peek().filter(|b| b.is_ascii_digit())?;
+            value = value * 10 + (digit - b'0') as i64;
+            self.current += 1;
+        }
+
+        return Some(value);
+    }
+
+    fn expect(&mut self, expected: u8) -> Option<()> {
+        if self.peek() != Some(expected) {
+            return None;
+        }
+
+        self.current += 1;
+        return Some(());
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    return year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    return match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date, and back again.
+// See http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    return era * 146_097 + day_of_era - 719_468;
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    return (year, month, day);
+}
@@ -0,0 +1,143 @@
+use std::rc::Rc;
+use crate::expr::Expr;
+use crate::pattern::Pattern;
+use crate::symbol::Symbol;
+use crate::token::Token;
+
+pub struct EnumVariant {
+    pub name: Token,
+    pub fields: Vec<Token>,
+}
+
+pub enum Stmt {
+    Expression {
+        expression: Expr,
+    },
+
+    Print {
+        expression: Expr,
+    },
+
+    Let {
+        keyword: Token,
+        pattern: Box<Pattern>,
+        initializer: Option<Expr>,
+    },
+
+    Enum {
+        name: Token,
+        variants: Vec<EnumVariant>,
+    },
+
+    Block {
+        statements: Vec<Stmt>,
+    },
+
+    Function {
+        name: Token,
+        params: Vec<Token>,
+        body: Rc<Vec<Stmt>>,
+    },
+
+    Return {
+        keyword: Token,
+        value: Option<Expr>,
+    },
+
+    Import {
+        keyword: Token,
+        path: String,
+        alias: Option<Token>,
+        names: Vec<Token>,
+    },
+
+    Export {
+        declaration: Box<Stmt>,
+    },
+
+    Throw {
+        keyword: Token,
+        value: Expr,
+    },
+
+    Try {
+        body: Vec<Stmt>,
+        catch_name: Option<Token>,
+        catch_body: Option<Vec<Stmt>>,
+        finally_body: Option<Vec<Stmt>>,
+    },
+}
+
+impl Stmt {
+    // Names a declaration introduces into its scope, used to collect a module's exports.
+    pub fn declared_names(&self) -> Vec<Symbol> {
+        return match self {
+            Stmt::Let { keyword: _, pattern, initializer: _ } => pattern.bound_names(),
+            Stmt::Enum { name, variants: _ } | Stmt::Function { name, params: _, body: _ } => vec![name.lexeme.clone()],
+            _ => vec![],
+        }
+    }
+}
+
+impl Stmt {
+    pub fn to_string(&self) -> String {
+        return match self {
+            Stmt::Expression { expression } => expression.to_string(),
+            Stmt::Print { expression } => format!("(print {})", expression.to_string()),
+            Stmt::Let { keyword: _, pattern, initializer } => match initializer {
+                Some(initializer) => format!("(let {} {})", pattern.to_string(), initializer.to_string()),
+                None => format!("(let {})", pattern.to_string()),
+            },
+            Stmt::Enum { name, variants } => {
+                let variants: Vec<String> = variants.iter()
+                    .map(|variant| match variant.fields.len() {
+                        0 => variant.name.lexeme.to_string(),
+                        _ => {
+                            let fields: Vec<String> = variant.fields.iter().map(|f| f.lexeme.to_string()).collect();
+                            format!("({} {})", variant.name.lexeme, fields.join(" "))
+                        },
+                    })
+                    .collect();
+
+                format!("(enum {} {})", name.lexeme, variants.join(" "))
+            },
+            Stmt::Block { statements } => format!("(block {})", block_to_string(statements)),
+            Stmt::Function { name, params, body } => {
+                let params: Vec<String> = params.iter().map(|p| p.lexeme.to_string()).collect();
+                format!("(fn {} ({}) {})", name.lexeme, params.join(" "), block_to_string(body))
+            },
+            Stmt::Return { keyword: _, value } => match value {
+                Some(value) => format!("(return {})", value.to_string()),
+                None => String::from("(return)"),
+            },
+            Stmt::Import { keyword: _, path, alias, names } => match alias {
+                Some(alias) => format!("(import {:?} as {})", path, alias.lexeme),
+                None => {
+                    let names: Vec<String> = names.iter().map(|n| n.lexeme.to_string()).collect();
+                    format!("(import ({}) from {:?})", names.join(" "), path)
+                },
+            },
+            Stmt::Export { declaration } => format!("(export {})", declaration.to_string()),
+            Stmt::Throw { keyword: _, value } => format!("(throw {})", value.to_string()),
+            Stmt::Try { body, catch_name, catch_body, finally_body } => {
+                let mut result = format!("(try (block {})", block_to_string(body));
+
+                if let (Some(name), Some(catch_body)) = (catch_name, catch_body) {
+                    result.push_str(&format!(" (catch {} {})", name.lexeme, block_to_string(catch_body)));
+                }
+
+                if let Some(finally_body) = finally_body {
+                    result.push_str(&format!(" (finally {})", block_to_string(finally_body)));
+                }
+
+                result.push(')');
+                result
+            },
+        }
+    }
+}
+
+fn block_to_string(statements: &[Stmt]) -> String {
+    let statements: Vec<String> = statements.iter().map(|s| s.to_string()).collect();
+    return statements.join(" ");
+}
@@ -0,0 +1,146 @@
+use std::borrow::Borrow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::rc::Rc;
+
+// An interned string: identifiers and string literals are stored once, and tokens, the syntax
+// tree and scopes share it, so copying a name is a reference count and comparing two is a
+// pointer compare.
+//
+// The table is per thread, like the values that hold symbols. It keeps a string only while
+// something else refers to it: once the table has doubled since it was last swept, interning
+// drops the strings nothing else holds, so loading and dropping programs doesn't grow it.
+//
+// The string is boxed so a symbol is one pointer, like the index it replaced, and values that
+// hold one stay two words.
+#[derive(Clone, PartialOrd, Ord)]
+pub struct Symbol(Rc<Box<str>>);
+
+// An interned string in the table, which looks it up by its text.
+#[derive(PartialEq, Eq, Hash)]
+struct Entry(Rc<Box<str>>);
+
+impl Borrow<str> for Entry {
+    fn borrow(&self) -> &str {
+        return &self.0;
+    }
+}
+
+// Sweeps start once the table holds this many strings.
+const SWEEP_THRESHOLD: usize = 1024;
+
+struct Interner {
+    strings: HashSet<Entry>,
+    next_sweep: usize,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner {
+        strings: HashSet::new(),
+        next_sweep: SWEEP_THRESHOLD,
+    });
+}
+
+impl Symbol {
+    pub fn intern(string: &str) -> Symbol {
+        return INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+
+            if let Some(entry) = interner.strings.get(string) {
+                return Symbol(entry.0.clone());
+            }
+
+            if interner.strings.len() >= interner.next_sweep {
+                interner.strings.retain(|entry| Rc::strong_count(&entry.0) > 1);
+                interner.next_sweep = SWEEP_THRESHOLD.max(interner.strings.len() * 2);
+            }
+
+            let string: Rc<Box<str>> = Rc::new(Box::from(string));
+            interner.strings.insert(Entry(string.clone()));
+
+            Symbol(string)
+        });
+    }
+
+    // The symbol for a string if it has been interned, without interning it. A name nobody
+    // interned can't have been defined anywhere.
+    pub fn find(string: &str) -> Option<Symbol> {
+        return INTERNER.with(|interner| interner.borrow().strings.get(string).map(|entry| Symbol(entry.0.clone())));
+    }
+
+    pub fn as_str(&self) -> &str {
+        return &self.0;
+    }
+}
+
+// Equal strings share one allocation, so the address identifies the string.
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Symbol) -> bool {
+        return Rc::ptr_eq(&self.0, &other.0);
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Rc::as_ptr(&self.0) as *const u8).hash(state);
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        return self.as_str() == other;
+    }
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        return self.as_str() == *other;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interning_returns_the_same_symbol() {
+        let a = Symbol::intern("platypus");
+        let b = Symbol::intern(&String::from("platypus"));
+
+        assert_eq!(a, b);
+        assert_ne!(a, Symbol::intern("echidna"));
+        assert_eq!(a.as_str(), "platypus");
+        assert_eq!(Symbol::find("platypus"), Some(a));
+        assert_eq!(Symbol::find("never interned by any test"), None);
+    }
+
+    #[test]
+    fn test_unused_strings_are_swept() {
+        let kept = Symbol::intern("kept across sweeps");
+
+        for i in 0..SWEEP_THRESHOLD * 4 {
+            Symbol::intern(&format!("dropped {}", i));
+        }
+
+        let size = INTERNER.with(|interner| interner.borrow().strings.len());
+        assert!(size < SWEEP_THRESHOLD * 2, "{} strings still interned", size);
+        assert_eq!(Symbol::find("kept across sweeps"), Some(kept));
+        assert_eq!(Symbol::find("dropped 0"), None);
+    }
+}
@@ -197,4 +197,82 @@ fn handles_multi_line_comments() {
     assert_eq!(scanner.tokens[8].token_type, TokenType::Number);
     assert_eq!(scanner.tokens[9].token_type, TokenType::Semicolon);
     assert_eq!(scanner.tokens[10].token_type, TokenType::Eof);
-}
\ No newline at end of file
+}
+
+#[test]
+fn handles_match_tokens() {
+    let source = "match x { [a] | _ => a }";
+    let mut scanner = Scanner::new(source);
+    scanner.scan_tokens().unwrap();
+
+    assert_eq!(scanner.tokens[0].token_type, TokenType::Match);
+    assert_eq!(scanner.tokens[2].token_type, TokenType::LeftBrace);
+    assert_eq!(scanner.tokens[3].token_type, TokenType::LeftBracket);
+    assert_eq!(scanner.tokens[5].token_type, TokenType::RightBracket);
+    assert_eq!(scanner.tokens[6].token_type, TokenType::Pipe);
+    assert_eq!(scanner.tokens[7].token_type, TokenType::Identifier);
+    assert_eq!(scanner.tokens[8].token_type, TokenType::FatArrow);
+    assert_eq!(scanner.tokens[11].token_type, TokenType::Eof);
+}
+
+#[test]
+fn handles_raw_string_literals() {
+    let source = r####"r"\d+" r#"say "hi""# rest"####;
+    let mut scanner = Scanner::new(source);
+    scanner.scan_tokens().unwrap();
+
+    assert_eq!(scanner.tokens.len(), 4);
+
+    match scanner.tokens[0].literal.as_ref().unwrap() {
+        StringValue(val) => assert_eq!(val, r"\d+"),
+        _ => panic!("Incorrect literal"),
+    }
+
+    match scanner.tokens[1].literal.as_ref().unwrap() {
+        StringValue(val) => assert_eq!(val, r#"say "hi""#),
+        _ => panic!("Incorrect literal"),
+    }
+
+    assert_eq!(scanner.tokens[2].token_type, TokenType::Identifier);
+    assert!(Scanner::new(r##"r#"open"##).scan_tokens().is_err());
+
+    let mut scanner = Scanner::new("r\"café\" r#\"🦆\"# rest");
+    scanner.scan_tokens().unwrap();
+
+    match scanner.tokens[0].literal.as_ref().unwrap() {
+        StringValue(val) => assert_eq!(val, "café"),
+        _ => panic!("Incorrect literal"),
+    }
+
+    match scanner.tokens[1].literal.as_ref().unwrap() {
+        StringValue(val) => assert_eq!(val, "🦆"),
+        _ => panic!("Incorrect literal"),
+    }
+
+    assert_eq!(scanner.tokens[2].lexeme, "rest");
+}
+
+#[test]
+fn handles_comments_at_the_end_of_the_source() {
+    let mut scanner = Scanner::new("print 1; // trailing");
+    scanner.scan_tokens().unwrap();
+
+    assert_eq!(scanner.tokens.len(), 4);
+    assert_eq!(scanner.tokens[3].token_type, TokenType::Eof);
+
+    let mut scanner = Scanner::new("print 1; /* never closed\n *");
+    assert_eq!(scanner.scan_tokens().unwrap_err(), "Error at line 2: Unterminated comment\n");
+}
+
+#[test]
+fn handles_stars_inside_multi_line_comments() {
+    let mut scanner = Scanner::new("/* a * b */ print 1;\n/* x / y\n ** */ 2");
+    scanner.scan_tokens().unwrap();
+
+    assert_eq!(scanner.tokens[0].token_type, TokenType::Print);
+    assert_eq!(scanner.tokens[1].token_type, TokenType::Number);
+    assert_eq!(scanner.tokens[2].token_type, TokenType::Semicolon);
+    assert_eq!(scanner.tokens[3].token_type, TokenType::Number);
+    assert_eq!(scanner.tokens[3].line_number, 3);
+    assert_eq!(scanner.tokens[4].token_type, TokenType::Eof);
+}
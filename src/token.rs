@@ -1,18 +1,19 @@
 use crate::literal_value::LiteralValue;
+use crate::symbol::Symbol;
 use crate::token_type::TokenType;
 
 #[allow(dead_code)]
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
-    pub lexeme: String,
+    pub lexeme: Symbol,
     pub literal: Option<LiteralValue>,
     pub line_number: usize,
 }
 
 #[allow(dead_code)]
 impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, literal: Option<LiteralValue>, line_number: usize) -> Self {
+    pub fn new(token_type: TokenType, lexeme: Symbol, literal: Option<LiteralValue>, line_number: usize) -> Self {
         Self {
             token_type,
             lexeme,
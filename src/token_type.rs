@@ -5,19 +5,25 @@ pub enum TokenType {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
+    Colon,
     Dot,
+    Ellipsis,
     Minus,
     Plus,
     Semicolon,
     Slash,
     Star,
+    Pipe,
 
     // Comparisons
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    FatArrow,
     Greater,
     GreaterEqual,
     Less,
@@ -30,19 +36,27 @@ pub enum TokenType {
 
     // Keywords
     And,
+    Catch,
     Class,
     Else,
+    Enum,
+    Export,
     False,
+    Finally,
     Fn,
     For,
     If,
+    Import,
+    Match,
     Null,
     Or,
     Print,
     Return,
     Extends,
     This,
+    Throw,
     True,
+    Try,
     Let,
     While,
 
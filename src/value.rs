@@ -0,0 +1,232 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use regex::Regex;
+use crate::chunk::FunctionProto;
+use crate::environment::Environment;
+use crate::exception::RuntimeError;
+use crate::gc;
+use crate::expr::ExpressionLiteralValue;
+use crate::interpreter::Interpreter;
+use crate::module::Module;
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::token::Token;
+
+#[derive(Debug)]
+pub struct EnumDefinition {
+    pub name: String,
+    pub variants: Vec<(Symbol, usize)>,
+}
+
+#[derive(Debug)]
+pub struct EnumValue {
+    pub definition: Rc<EnumDefinition>,
+    pub variant: Symbol,
+    pub fields: Vec<Value>,
+}
+
+pub struct Function {
+    pub name: String,
+    pub file: String,
+    pub params: Vec<Token>,
+    pub body: Rc<Vec<Stmt>>,
+    pub closure: Rc<RefCell<Environment>>,
+}
+
+impl std::fmt::Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.name)
+    }
+}
+
+// A function compiled for the bytecode backend, with the scope its globals resolve in and
+// the variables it captured from enclosing functions.
+pub struct Closure {
+    pub function: Rc<FunctionProto>,
+    pub globals: Rc<RefCell<Environment>>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+// A captured variable lives on the VM stack while its scope is running, and moves into the
+// upvalue when the scope ends, so every closure sharing the upvalue keeps seeing it.
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+impl std::fmt::Debug for Closure {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<fn {}>", self.function.name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Arity {
+    Fixed(usize),
+    Variadic,
+}
+
+pub type NativeFn = dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError>;
+
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Arity,
+    pub function: Box<NativeFn>,
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<NativeFunction>,
+}
+
+// A tag and 8 bytes: numbers, booleans and null are stored inline and everything else is one
+// pointer, so pushing, popping and copying values stays cheap. Strings are immutable and shared
+// rather than copied.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Number(f64),
+    StringValue(Rc<String>),
+    Boolean(bool),
+    Null,
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Rc<RefCell<BTreeMap<String, Value>>>),
+    Enum(Rc<EnumDefinition>),
+    // The variant's index in the definition.
+    EnumConstructor(Rc<EnumDefinition>, u32),
+    EnumValue(Rc<EnumValue>),
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    NativeFunction(Rc<NativeFunction>),
+    BoundMethod(Rc<BoundMethod>),
+    Module(Rc<Module>),
+    Regex(Rc<Regex>),
+}
+
+impl Value {
+    pub fn from_literal(literal: &ExpressionLiteralValue) -> Self {
+        return match literal {
+            ExpressionLiteralValue::Number(n) => Value::Number(*n),
+            ExpressionLiteralValue::StringValue(s) => Value::from(s.to_string()),
+            ExpressionLiteralValue::True => Value::Boolean(true),
+            ExpressionLiteralValue::False => Value::Boolean(false),
+            ExpressionLiteralValue::Null => Value::Null,
+        }
+    }
+
+    pub fn list(elements: Vec<Value>) -> Self {
+        return Value::List(gc::list(elements));
+    }
+
+    pub fn map(entries: BTreeMap<String, Value>) -> Self {
+        return Value::Map(gc::map(entries));
+    }
+
+    pub fn to_string(&self) -> String {
+        return match self {
+            Value::Number(n) => n.to_string(),
+            Value::StringValue(s) => s.to_string(),
+            Value::Boolean(b) => b.to_string(),
+            Value::Null => String::from("null"),
+            Value::List(elements) => {
+                let elements: Vec<String> = elements.borrow().iter().map(|e| e.to_nested_string()).collect();
+                format!("[{}]", elements.join(", "))
+            },
+            Value::Map(entries) => {
+                let entries: Vec<String> = entries.borrow().iter()
+                    .map(|(key, value)| format!("{}: {}", key, value.to_nested_string()))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            },
+            Value::Enum(definition) => format!("<enum {}>", definition.name),
+            Value::EnumConstructor(definition, variant) => format!("<constructor {}.{}>", definition.name, definition.variants[*variant as usize].0),
+            Value::EnumValue(value) => {
+                let name = format!("{}.{}", value.definition.name, value.variant);
+
+                if value.fields.is_empty() {
+                    return name;
+                }
+
+                let fields: Vec<String> = value.fields.iter().map(|f| f.to_nested_string()).collect();
+                format!("{}({})", name, fields.join(", "))
+            },
+            Value::Function(function) => format!("<fn {}>", function.name),
+            Value::Closure(closure) => format!("<fn {}>", closure.function.name),
+            Value::NativeFunction(function) => format!("<native fn {}>", function.name),
+            Value::BoundMethod(bound) => format!("<method {}>", bound.method.name),
+            Value::Module(module) => format!("<module {}>", module.name),
+            Value::Regex(regex) => format!("<regex {}>", regex.as_str()),
+        }
+    }
+
+    // Strings nested inside collections are quoted so `["1"]` and `[1]` print differently.
+    pub(crate) fn to_nested_string(&self) -> String {
+        return match self {
+            Value::StringValue(s) => format!("{:?}", s),
+            _ => self.to_string(),
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        return match self {
+            Value::Number(_) => "number",
+            Value::StringValue(_) => "string",
+            Value::Boolean(_) => "boolean",
+            Value::Null => "null",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Enum(_) => "enum",
+            Value::EnumConstructor(_, _) => "constructor",
+            Value::EnumValue(_) => "enum value",
+            Value::Function(_) | Value::Closure(_) | Value::NativeFunction(_) | Value::BoundMethod(_) => "function",
+            Value::Module(_) => "module",
+            Value::Regex(_) => "regex",
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        return !matches!(self, Value::Null | Value::Boolean(false));
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        return match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a == b,
+            (Value::StringValue(a), Value::StringValue(b)) => a == b,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Null, Value::Null) => true,
+            (Value::List(a), Value::List(b)) => *a.borrow() == *b.borrow(),
+            (Value::Map(a), Value::Map(b)) => *a.borrow() == *b.borrow(),
+            (Value::Enum(a), Value::Enum(b)) => Rc::ptr_eq(a, b),
+            (Value::EnumConstructor(a, x), Value::EnumConstructor(b, y)) => Rc::ptr_eq(a, b) && x == y,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Closure(a), Value::Closure(b)) => Rc::ptr_eq(a, b),
+            (Value::NativeFunction(a), Value::NativeFunction(b)) => Rc::ptr_eq(a, b),
+            (Value::BoundMethod(a), Value::BoundMethod(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) => Rc::ptr_eq(a, b),
+            (Value::Regex(a), Value::Regex(b)) => a.as_str() == b.as_str(),
+            (Value::EnumValue(a), Value::EnumValue(b)) => {
+                Rc::ptr_eq(&a.definition, &b.definition) && a.variant == b.variant && a.fields == b.fields
+            },
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_values_are_two_words() {
+        assert_eq!(std::mem::size_of::<Value>(), 16);
+    }
+}
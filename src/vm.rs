@@ -0,0 +1,984 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+use crate::chunk::{Chunk, Constant, FunctionProto, OpCode, NO_TARGET};
+use crate::disassembler::disassemble_instruction;
+use crate::environment::Environment;
+use crate::exception::{ErrorKind, RuntimeError};
+use crate::gc;
+use crate::interpreter::{binary, unary, Interpreter};
+use crate::symbol::Symbol;
+use crate::token::Token;
+use crate::token_type::TokenType;
+use crate::value::{Closure, EnumDefinition, Upvalue, Value};
+
+struct Frame {
+    closure: Rc<Closure>,
+    ip: usize,
+    // Stack index of slot zero, which holds the function being called.
+    base: usize,
+}
+
+// An active try block: where to resume, and what to unwind to before resuming.
+struct Handler {
+    frame: usize,
+    height: usize,
+    pending: usize,
+    trace_frames: usize,
+    catch: u16,
+    finally: u16,
+}
+
+struct Vm<'i> {
+    interpreter: &'i mut Interpreter,
+    // Borrowed from the interpreter for the run; the values below `stack_base` belong to the
+    // runs this one is nested in.
+    stack: Vec<Value>,
+    stack_base: usize,
+    frames: Vec<Frame>,
+    // Upvalues still pointing at slots of this run, ordered by slot.
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    handlers: Vec<Handler>,
+    // Errors a catch block is about to bind or a finally block will raise again.
+    pending: Vec<RuntimeError>,
+    // Interpreter frames when this VM started, restored if an error escapes it.
+    trace_frames: usize,
+    // Offset of the instruction being executed, for the line of an error it raises.
+    instruction: usize,
+}
+
+// Runs a compiled script or module, resolving its globals in `globals`.
+pub fn run(interpreter: &mut Interpreter, function: Rc<FunctionProto>, globals: Rc<RefCell<Environment>>) -> Result<Value, RuntimeError> {
+    let closure = Rc::new(Closure { function, globals, upvalues: vec![] });
+
+    return Vm::new(interpreter, closure, vec![]).execute();
+}
+
+// Calls a compiled function from outside the VM: from host code, a native or the tree-walker.
+pub fn call(interpreter: &mut Interpreter, line: usize, closure: Rc<Closure>, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+    let function = &closure.function;
+
+    check_arity(function, arguments.len(), line)?;
+    interpreter.push_frame(&function.name, &function.file, line)?;
+    let result = Vm::new(interpreter, closure, arguments).execute();
+    interpreter.pop_frame();
+
+    return result;
+}
+
+impl<'i> Vm<'i> {
+    fn new(interpreter: &'i mut Interpreter, closure: Rc<Closure>, arguments: Vec<Value>) -> Self {
+        let mut stack = std::mem::take(&mut interpreter.vm_stack);
+        let base = stack.len();
+
+        stack.push(Value::Closure(closure.clone()));
+        stack.extend(arguments);
+
+        return Self {
+            trace_frames: interpreter.frame_count(),
+            interpreter,
+            stack,
+            stack_base: base,
+            frames: vec![Frame { closure, ip: 0, base }],
+            open_upvalues: vec![],
+            handlers: vec![],
+            pending: vec![],
+            instruction: 0,
+        };
+    }
+
+    fn execute(mut self) -> Result<Value, RuntimeError> {
+        let result = loop {
+            match self.dispatch() {
+                Ok(value) => break Ok(value),
+                Err(error) => {
+                    if let Err(error) = self.recover(error) {
+                        break Err(error);
+                    }
+                },
+            }
+        };
+
+        self.close_upvalues(self.stack_base);
+        self.stack.truncate(self.stack_base);
+        self.interpreter.vm_stack = std::mem::take(&mut self.stack);
+
+        return result;
+    }
+
+    // Unwinds to the innermost try block that handles the error, or gives it back when none does.
+    fn recover(&mut self, mut error: RuntimeError) -> Result<(), RuntimeError> {
+        if error.line == 0 {
+            error.line = self.frame().closure.function.chunk.lines[self.instruction];
+        }
+
+        let error = self.interpreter.with_trace(error);
+
+        // Reaching a limit ends the script without running any handler.
+        while let Some(handler) = self.handlers.pop().filter(|_| !error.kind.is_termination()) {
+            // Exiting still runs finally blocks on the way out, but can't be caught.
+            let target = match (error.kind.is_catchable() && handler.catch != NO_TARGET, handler.finally) {
+                (true, _) => handler.catch,
+                (false, NO_TARGET) => continue,
+                (false, finally) => finally,
+            };
+
+            // Only a loaded file can leave the stack lower than it was when the try block started.
+            if self.stack.len() < handler.height {
+                self.interpreter.truncate_frames(self.trace_frames);
+                return Err(invalid_bytecode("stack is below its try block", error.line));
+            }
+
+            self.frames.truncate(handler.frame + 1);
+            self.close_upvalues(handler.height);
+            self.stack.truncate(handler.height);
+            self.pending.truncate(handler.pending);
+            self.interpreter.truncate_frames(handler.trace_frames);
+            self.frame_mut().ip = target as usize;
+            self.pending.push(error);
+
+            return Ok(());
+        }
+
+        self.interpreter.truncate_frames(self.trace_frames);
+
+        return Err(error);
+    }
+
+    fn dispatch(&mut self) -> Result<Value, RuntimeError> {
+        loop {
+            let closure = self.frame().closure.clone();
+            let chunk = &closure.function.chunk;
+
+            if self.interpreter.trace() {
+                self.trace(chunk);
+            }
+
+            let frame = self.frames.last_mut().unwrap();
+
+            self.instruction = frame.ip;
+            let op = OpCode::from_byte(chunk.code[frame.ip]).expect("invalid opcode");
+            let operand = frame.ip + 1;
+            frame.ip = operand + op.operand_bytes();
+
+            let line = chunk.lines[self.instruction];
+            self.interpreter.step(line)?;
+
+            match op {
+                OpCode::Constant => {
+                    let value = match &chunk.constants[chunk.read_u16(operand) as usize] {
+                        Constant::Number(n) => Value::Number(*n),
+                        Constant::String(s) => Value::from(s.to_string()),
+                        constant => panic!("Constant {:?} can't be pushed", constant),
+                    };
+
+                    self.stack.push(value);
+                },
+                OpCode::Null => self.stack.push(Value::Null),
+                OpCode::True => self.stack.push(Value::Boolean(true)),
+                OpCode::False => self.stack.push(Value::Boolean(false)),
+                OpCode::Pop => {
+                    self.pop();
+                },
+                OpCode::GetLocal => {
+                    let slot = self.frame().base + chunk.read_u16(operand) as usize;
+                    self.stack.push(self.stack[slot].clone());
+                },
+                OpCode::SetLocal => {
+                    let slot = self.frame().base + chunk.read_u16(operand) as usize;
+                    self.stack[slot] = self.pop();
+                },
+                OpCode::GetGlobal => {
+                    let name = string(chunk, operand);
+                    let value = closure.globals.borrow().lookup(&name);
+
+                    match value {
+                        Some(value) => self.stack.push(value),
+                        None => return Err(RuntimeError::new(ErrorKind::UndefinedVariable, format!("Undefined variable '{}'", name), line)),
+                    }
+                },
+                OpCode::DefineGlobal => {
+                    let value = self.pop();
+                    closure.globals.borrow_mut().define(string(chunk, operand), value);
+                },
+                OpCode::GetProperty => {
+                    let object = self.pop();
+                    let name = Token::new(TokenType::Identifier, string(chunk, operand), None, line);
+
+                    self.stack.push(self.interpreter.get(&name, object)?);
+                },
+                OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide | OpCode::Equal | OpCode::NotEqual
+                | OpCode::Greater | OpCode::GreaterEqual | OpCode::Less | OpCode::LessEqual => {
+                    let right = self.pop();
+                    let left = self.pop();
+
+                    self.stack.push(arithmetic(op, left, right, line)?);
+                },
+                OpCode::Negate => {
+                    let value = match self.pop() {
+                        Value::Number(n) => Value::Number(-n),
+                        value => unary(&TokenType::Minus, "-", line, value)?,
+                    };
+
+                    self.stack.push(value);
+                },
+                OpCode::Not => {
+                    let value = self.pop();
+                    self.stack.push(Value::Boolean(!value.is_truthy()));
+                },
+                OpCode::Print => println!("{}", self.pop().to_string()),
+                OpCode::Jump => self.frame_mut().ip += chunk.read_u16(operand) as usize,
+                OpCode::JumpIfFalse => {
+                    if !self.pop().is_truthy() {
+                        self.frame_mut().ip += chunk.read_u16(operand) as usize;
+                    }
+                },
+                OpCode::Call => self.call_value(chunk.code[operand] as usize, line)?,
+                OpCode::TailCall => self.tail_call(chunk.code[operand] as usize, line)?,
+                OpCode::Closure => {
+                    let function = match &chunk.constants[chunk.read_u16(operand) as usize] {
+                        Constant::Function(function) => function.clone(),
+                        constant => panic!("Constant {:?} is not a function", constant),
+                    };
+
+                    let base = self.frame().base;
+                    let upvalues = function.upvalues.iter()
+                        .map(|source| match source.local {
+                            true => self.capture_upvalue(base + source.index as usize),
+                            false => closure.upvalues[source.index as usize].clone(),
+                        })
+                        .collect();
+
+                    self.stack.push(Value::Closure(Rc::new(Closure { function, globals: closure.globals.clone(), upvalues })));
+                },
+                OpCode::GetUpvalue => {
+                    let value = match &*closure.upvalues[chunk.read_u16(operand) as usize].borrow() {
+                        Upvalue::Open(slot) => self.stack.get(*slot).cloned(),
+                        Upvalue::Closed(value) => Some(value.clone()),
+                    };
+
+                    self.stack.push(value.ok_or_else(|| invalid_bytecode("captured slot was popped without being closed", line))?);
+                },
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                },
+                OpCode::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+
+                    self.interpreter.pop_frame();
+                    self.stack.push(value);
+                },
+                OpCode::List => {
+                    let count = chunk.read_u16(operand) as usize;
+                    let elements = self.stack.split_off(self.stack.len() - count);
+
+                    self.stack.push(Value::list(elements));
+                },
+                OpCode::Map => {
+                    let count = chunk.read_u16(operand) as usize;
+                    let mut entries = BTreeMap::new();
+                    let mut pairs = self.stack.split_off(self.stack.len() - count * 2).into_iter();
+
+                    while let (Some(key), Some(value)) = (pairs.next(), pairs.next()) {
+                        entries.insert(key.to_string(), value);
+                    }
+
+                    self.stack.push(Value::map(entries));
+                },
+                OpCode::Enum => {
+                    let definition = match &chunk.constants[chunk.read_u16(operand) as usize] {
+                        Constant::Enum { name, variants } => EnumDefinition {
+                            name: name.clone(),
+                            variants: variants.iter().map(|(variant, fields)| (Symbol::intern(variant), *fields)).collect(),
+                        },
+                        constant => panic!("Constant {:?} is not an enum", constant),
+                    };
+
+                    self.stack.push(Value::Enum(Rc::new(definition)));
+                },
+                OpCode::Import => {
+                    self.interpreter.set_line(line);
+                    let module = self.lend_stack(|interpreter| interpreter.import_module(line, string(chunk, operand).as_str()))?;
+                    self.stack.push(Value::Module(module));
+                },
+                OpCode::ImportName => {
+                    let name = string(chunk, operand);
+                    let module = match self.pop() {
+                        Value::Module(module) => module,
+                        value => return Err(invalid_bytecode(&format!("expected a module, got {}", value.type_name()), line)),
+                    };
+
+                    match module.exports.get(name.as_str()) {
+                        Some(value) => self.stack.push(value.clone()),
+                        None => return Err(RuntimeError::new(ErrorKind::ImportError, format!(
+                            "Module '{}' has no export '{}'",
+                            module.name,
+                            name,
+                        ), line)),
+                    }
+                },
+                OpCode::Throw => return Err(RuntimeError::thrown(self.pop(), line)),
+                OpCode::SetupTry => self.handlers.push(Handler {
+                    frame: self.frames.len() - 1,
+                    height: self.stack.len(),
+                    pending: self.pending.len(),
+                    trace_frames: self.interpreter.frame_count(),
+                    catch: chunk.read_u16(operand),
+                    finally: chunk.read_u16(operand + 2),
+                }),
+                OpCode::PopTry => {
+                    self.handlers.pop();
+                },
+                OpCode::TakeError => {
+                    let error = self.pending.pop().ok_or_else(|| invalid_bytecode("no error to handle", line))?;
+                    self.stack.push(error.to_value());
+                },
+                OpCode::Rethrow => return Err(self.pending.pop().unwrap_or_else(|| invalid_bytecode("no error to raise again", line))),
+                OpCode::MatchList => {
+                    let count = chunk.read_u16(operand) as usize;
+                    let rest = chunk.code[operand + 2] != 0;
+                    let matched = match self.pop() {
+                        Value::List(values) => {
+                            let length = values.borrow().len();
+                            length == count || (rest && length > count)
+                        },
+                        _ => false,
+                    };
+
+                    self.stack.push(Value::Boolean(matched));
+                },
+                OpCode::IsMap => {
+                    let matched = matches!(self.pop(), Value::Map(_));
+                    self.stack.push(Value::Boolean(matched));
+                },
+                OpCode::MapHas | OpCode::MapGet => {
+                    let entries = match self.pop() {
+                        Value::Map(entries) => entries,
+                        value => return Err(invalid_bytecode(&format!("expected a map, got {}", value.type_name()), line)),
+                    };
+                    let value = entries.borrow().get(string(chunk, operand).as_str()).cloned();
+
+                    match op {
+                        OpCode::MapHas => self.stack.push(Value::Boolean(value.is_some())),
+                        _ => self.stack.push(value.unwrap_or(Value::Null)),
+                    }
+                },
+                OpCode::MatchVariant => {
+                    let enum_name = chunk.read_u16(operand);
+                    let variant = string(chunk, operand + 2);
+                    let count = chunk.read_u16(operand + 4) as usize;
+
+                    let matched = match self.pop() {
+                        Value::EnumValue(value) => {
+                            variant == value.variant
+                                && value.fields.len() == count
+                                && (enum_name == NO_TARGET || string(chunk, operand) == value.definition.name.as_str())
+                        },
+                        _ => false,
+                    };
+
+                    self.stack.push(Value::Boolean(matched));
+                },
+                OpCode::Index | OpCode::SliceFrom => {
+                    let index = chunk.read_u16(operand) as usize;
+                    let values = match self.pop() {
+                        Value::List(values) => values,
+                        value => return Err(invalid_bytecode(&format!("expected a list, got {}", value.type_name()), line)),
+                    };
+
+                    // The pattern's length test comes first in compiled code, but a loaded file may skip it.
+                    let value = match op {
+                        OpCode::Index => values.borrow().get(index).cloned(),
+                        _ => values.borrow().get(index..).map(|rest| Value::list(rest.to_vec())),
+                    };
+
+                    self.stack.push(value.ok_or_else(|| invalid_bytecode(&format!("list index {} out of range", index), line))?);
+                },
+                OpCode::VariantField => {
+                    let index = chunk.read_u16(operand) as usize;
+                    let value = match self.pop() {
+                        Value::EnumValue(value) => value.fields.get(index).cloned(),
+                        value => return Err(invalid_bytecode(&format!("expected an enum value, got {}", value.type_name()), line)),
+                    };
+
+                    self.stack.push(value.ok_or_else(|| invalid_bytecode(&format!("variant field {} out of range", index), line))?);
+                },
+                OpCode::DestructureList => {
+                    let count = chunk.read_u16(operand) as usize;
+                    let rest = chunk.code[operand + 2] != 0;
+
+                    let length = match self.pop() {
+                        Value::List(values) => values.borrow().len(),
+                        value => return Err(destructure_error(format!("Cannot destructure {} as a list", value.type_name()), line)),
+                    };
+
+                    if !rest && length != count {
+                        return Err(destructure_error(format!("Expected a list of {} elements, got {}", count, length), line));
+                    }
+
+                    if length < count {
+                        return Err(destructure_error(format!("Expected a list of at least {} elements, got {}", count, length), line));
+                    }
+                },
+                OpCode::DestructureMap => {
+                    let value = self.pop();
+
+                    if !matches!(value, Value::Map(_)) {
+                        return Err(destructure_error(format!("Cannot destructure {} as a map", value.type_name()), line));
+                    }
+                },
+                OpCode::MissingKey => {
+                    return Err(destructure_error(format!("Missing key '{}' in destructured map", string(chunk, operand)), line));
+                },
+                OpCode::NoMatch => {
+                    let subject = self.pop();
+                    return Err(RuntimeError::new(ErrorKind::MatchError, format!("No match arm matched value {}", subject.to_string()), line));
+                },
+            }
+        }
+    }
+
+    fn call_value(&mut self, count: usize, line: usize) -> Result<(), RuntimeError> {
+        let base = self.stack.len() - count - 1;
+        self.interpreter.set_line(line);
+
+        if let Value::Closure(closure) = &self.stack[base] {
+            let closure = closure.clone();
+            let function = &closure.function;
+
+            check_arity(function, count, line)?;
+            self.interpreter.push_frame(&function.name, &function.file, line)?;
+            self.frames.push(Frame { closure, ip: 0, base });
+
+            return Ok(());
+        }
+
+        let arguments = self.stack.split_off(base + 1);
+        let callee = self.pop();
+        let value = self.lend_stack(|interpreter| interpreter.call(line, callee, arguments))?;
+
+        self.stack.push(value);
+
+        return Ok(());
+    }
+
+    // Replaces the current frame with the called function's, so tail recursion doesn't grow the
+    // frames. Anything but a compiled function is called as usual.
+    fn tail_call(&mut self, count: usize, line: usize) -> Result<(), RuntimeError> {
+        let callee = self.stack.len() - count - 1;
+
+        let closure = match &self.stack[callee] {
+            Value::Closure(closure) => closure.clone(),
+            _ => return self.call_value(count, line),
+        };
+
+        check_arity(&closure.function, count, line)?;
+
+        let base = self.frame().base;
+        self.close_upvalues(base);
+        self.stack.drain(base..callee);
+        self.interpreter.replace_frame(&closure.function.name, &closure.function.file, line);
+
+        let frame = self.frame_mut();
+        frame.closure = closure;
+        frame.ip = 0;
+
+        return Ok(());
+    }
+
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let position = self.open_upvalues.iter().position(|upvalue| match &*upvalue.borrow() {
+            Upvalue::Open(open) => *open >= slot,
+            Upvalue::Closed(_) => false,
+        });
+
+        if let Some(position) = position {
+            if matches!(&*self.open_upvalues[position].borrow(), Upvalue::Open(open) if *open == slot) {
+                return self.open_upvalues[position].clone();
+            }
+        }
+
+        let upvalue = gc::upvalue(Upvalue::Open(slot));
+        self.open_upvalues.insert(position.unwrap_or(self.open_upvalues.len()), upvalue.clone());
+
+        return upvalue;
+    }
+
+    // Moves the values of slots from `slot` up into the upvalues that captured them.
+    fn close_upvalues(&mut self, slot: usize) {
+        while let Some(upvalue) = self.open_upvalues.last() {
+            let open = match &*upvalue.borrow() {
+                Upvalue::Open(open) if *open >= slot => *open,
+                _ => break,
+            };
+
+            // Only a loaded file can pop a captured slot without closing it first.
+            *upvalue.borrow_mut() = Upvalue::Closed(self.stack.get(open).cloned().unwrap_or(Value::Null));
+            self.open_upvalues.pop();
+        }
+    }
+
+    // Hands the stack back to the interpreter while it runs code that may start a nested VM.
+    fn lend_stack<T>(&mut self, f: impl FnOnce(&mut Interpreter) -> T) -> T {
+        self.interpreter.vm_stack = std::mem::take(&mut self.stack);
+        let result = f(self.interpreter);
+        self.stack = std::mem::take(&mut self.interpreter.vm_stack);
+
+        return result;
+    }
+
+    // Prints the stack, then the instruction about to run against it.
+    fn trace(&self, chunk: &Chunk) {
+        let stack: Vec<String> = self.stack.iter().map(|value| format!("[ {} ]", value.to_nested_string())).collect();
+        let (instruction, _) = disassemble_instruction(chunk, self.frame().ip);
+
+        println!("          {}", stack.join(""));
+        println!("{}", instruction);
+    }
+
+    fn pop(&mut self) -> Value {
+        return self.stack.pop().expect("VM stack underflow");
+    }
+
+    fn frame(&self) -> &Frame {
+        return self.frames.last().unwrap();
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        return self.frames.last_mut().unwrap();
+    }
+}
+
+fn string(chunk: &Chunk, operand: usize) -> Symbol {
+    return match &chunk.constants[chunk.read_u16(operand) as usize] {
+        Constant::String(s) => s.clone(),
+        constant => panic!("Constant {:?} is not a name", constant),
+    }
+}
+
+fn invalid_bytecode(message: &str, line: usize) -> RuntimeError {
+    return RuntimeError::new(ErrorKind::InvalidBytecode, format!("Invalid bytecode: {}", message), line);
+}
+
+fn destructure_error(message: String, line: usize) -> RuntimeError {
+    return RuntimeError::new(ErrorKind::DestructureError, message, line);
+}
+
+// Numbers take the fast path; everything else, errors included, goes through the tree-walker's
+// rules so both backends agree.
+fn check_arity(function: &FunctionProto, count: usize, line: usize) -> Result<(), RuntimeError> {
+    if count != function.arity {
+        return Err(RuntimeError::new(ErrorKind::TypeError, format!(
+            "{} expected {} arguments but got {}",
+            function.name,
+            function.arity,
+            count,
+        ), line));
+    }
+
+    return Ok(());
+}
+
+fn arithmetic(op: OpCode, left: Value, right: Value, line: usize) -> Result<Value, RuntimeError> {
+    if let (Value::Number(a), Value::Number(b)) = (&left, &right) {
+        let (a, b) = (*a, *b);
+
+        match op {
+            OpCode::Add => return Ok(Value::Number(a + b)),
+            OpCode::Subtract => return Ok(Value::Number(a - b)),
+            OpCode::Multiply => return Ok(Value::Number(a * b)),
+            OpCode::Divide if b != 0.0 => return Ok(Value::Number(a / b)),
+            OpCode::Greater => return Ok(Value::Boolean(a > b)),
+            OpCode::GreaterEqual => return Ok(Value::Boolean(a >= b)),
+            OpCode::Less => return Ok(Value::Boolean(a < b)),
+            OpCode::LessEqual => return Ok(Value::Boolean(a <= b)),
+            _ => {},
+        }
+    }
+
+    let (token_type, lexeme) = match op {
+        OpCode::Add => (TokenType::Plus, "+"),
+        OpCode::Subtract => (TokenType::Minus, "-"),
+        OpCode::Multiply => (TokenType::Star, "*"),
+        OpCode::Divide => (TokenType::Slash, "/"),
+        OpCode::Equal => (TokenType::EqualEqual, "=="),
+        OpCode::NotEqual => (TokenType::BangEqual, "!="),
+        OpCode::Greater => (TokenType::Greater, ">"),
+        OpCode::GreaterEqual => (TokenType::GreaterEqual, ">="),
+        OpCode::Less => (TokenType::Less, "<"),
+        _ => (TokenType::LessEqual, "<="),
+    };
+
+    return binary(&token_type, lexeme, line, left, right);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use crate::interpreter::{Backend, DEFAULT_MAX_DEPTH};
+    use crate::parser::Parser;
+    use crate::scanner::Scanner;
+    use crate::test_dir::TestDir;
+    use super::*;
+
+    fn run(source: &str, backend: Backend) -> String {
+        return run_with_depth(source, backend, DEFAULT_MAX_DEPTH);
+    }
+
+    fn run_with_depth(source: &str, backend: Backend, max_depth: usize) -> String {
+        let mut scanner = Scanner::new(source);
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap());
+        let statements = parser.parse_statements().unwrap();
+
+        let mut interpreter = Interpreter::with_file("test.pltps");
+        interpreter.set_backend(backend);
+        interpreter.set_max_depth(max_depth);
+
+        return match interpreter.interpret(&statements) {
+            Ok(value) => value.to_string(),
+            Err(error) => error.report(),
+        };
+    }
+
+    // Runs the source on both backends, which must agree, and returns what they produced.
+    fn both(source: &str) -> String {
+        let tree = run(source, Backend::Tree);
+        assert_eq!(run(source, Backend::Vm), tree, "backends disagree on:\n{}", source);
+
+        return tree;
+    }
+
+    #[test]
+    fn test_expressions_and_globals() {
+        assert_eq!(both("let a = 2; let b = a * 3 + 1; [a, b, -b, !a, a == 2, a != 2, a < b, \"x\" + \"y\"];"), r#"[2, 7, -7, false, true, false, true, "xy"]"#);
+        assert_eq!(both("let m = {a: 1, b: [2, 3]}; [m.a, m.b, m.b.len()];"), "[1, [2, 3], 2]");
+    }
+
+    #[test]
+    fn test_runtime_errors_match() {
+        assert!(both("1 / 0;").ends_with("DivisionByZero: Division by zero"));
+        assert!(both("1 + \"a\";").ends_with("TypeError: Unsupported operand types for '+': number and string"));
+        assert!(both("-\"a\";").ends_with("TypeError: Operand of '-' must be a number, got string"));
+        assert!(both("missing;").ends_with("UndefinedVariable: Undefined variable 'missing'"));
+        assert!(both("let x = 1; x();").ends_with("TypeError: Can only call functions and constructors, got number"));
+        assert!(both("fn f(a) { return a; } f();").ends_with("TypeError: f expected 1 arguments but got 0"));
+    }
+
+    #[test]
+    fn test_functions_blocks_and_locals() {
+        let source = r#"
+            fn fib(n) {
+                return match n { 0 => 0, 1 => 1, _ => fib(n - 1) + fib(n - 2) };
+            }
+            fn shadow(x) {
+                let y = x + 1;
+                {
+                    let x = y * 2;
+                    let y = x + 1;
+                    return [x, y];
+                }
+            }
+            fn implicit() { let unused = 1; }
+            fn fn_double(x) { return x * 2; }
+            [fib(15), shadow(1), implicit(), [1, 2, 3].map(fn_double), [3, 1, 2].sort()];
+        "#;
+
+        assert_eq!(both(source), "[610, [4, 5], null, [2, 4, 6], [1, 2, 3]]");
+    }
+
+    #[test]
+    fn test_match_patterns() {
+        let source = r#"
+            enum Shape { Circle(r), Rect(w, h), Empty }
+            fn describe(value) {
+                return match value {
+                    [0, 0] => "origin",
+                    [x, 0] | [0, x] => ["axis", x],
+                    [x, y] if x == y => "diagonal",
+                    [first, ...rest] => ["list", first, rest],
+                    {name, port = 80} => [name, port],
+                    Circle(r) => 3 * r * r,
+                    Shape.Rect(w, h) => w * h,
+                    Shape.Empty => "empty",
+                    "a" | "b" => "letter",
+                    true => "yes",
+                    _ => "other",
+                };
+            }
+            [
+                describe([0, 0]), describe([0, 5]), describe([2, 2]), describe([1, 2, 3]),
+                describe({name: "api"}), describe({name: "db", port: 5432}), describe({}),
+                describe(Shape.Circle(2)), describe(Shape.Rect(3, 4)), describe(Shape.Empty),
+                describe("b"), describe(true), describe(null),
+            ];
+        "#;
+
+        assert_eq!(both(source), r#"["origin", ["axis", 5], "diagonal", ["list", 1, [2, 3]], ["api", 80], ["db", 5432], "other", 12, 12, "empty", "letter", "yes", "other"]"#);
+        assert!(both("match 3 { 1 => 1, 2 => 2 };").ends_with("MatchError: No match arm matched value 3"));
+        assert_eq!(both("let x = 10; [match 1 { y if y > 5 => 0, y => y + x }, x];"), "[11, 10]");
+        assert_eq!(both("enum E { A(x), Empty } fn f(e) { return match e { Empty => 0, _ => 1 }; } [f(E.A(1)), f(E.Empty)];"), "[1, 0]");
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let source = r#"
+            let [a, b, ...rest] = [1, 2, 3, 4];
+            let {name, port = 8080, host: h = "localhost"} = {name: "api", host: "example.com"};
+            fn swap(pair) {
+                let [x, y] = pair;
+                return [y, x];
+            }
+            [a, b, rest, name, port, h, swap([1, 2])];
+        "#;
+
+        assert_eq!(both(source), r#"[1, 2, [3, 4], "api", 8080, "example.com", [2, 1]]"#);
+        assert!(both("let [a, b] = [1];").ends_with("DestructureError: Expected a list of 2 elements, got 1"));
+        assert!(both("let [a, b, ...c] = [1];").ends_with("DestructureError: Expected a list of at least 2 elements, got 1"));
+        assert!(both("let {name} = {age: 3};").ends_with("DestructureError: Missing key 'name' in destructured map"));
+        assert!(both("fn f() { let [a] = 1; } f();").ends_with("DestructureError: Cannot destructure number as a list"));
+    }
+
+    #[test]
+    fn test_try_catch_finally() {
+        let source = r#"
+            let log = [];
+            fn attempt(value) {
+                try {
+                    log.push("try");
+                    return 10 / value;
+                } catch (e) {
+                    log.push(e.kind);
+                    return -1;
+                } finally {
+                    log.push("finally");
+                }
+            }
+            fn nested() {
+                try {
+                    try { throw {code: 1}; } finally { log.push("inner"); }
+                } catch (e) {
+                    return e.code + 1;
+                }
+            }
+            fn rethrown() {
+                try {
+                    try { throw "first"; } catch (e) { throw e + " again"; } finally { log.push("cleanup"); }
+                } catch (e) {
+                    return e;
+                }
+            }
+            [attempt(2), attempt(0), nested(), rethrown(), log];
+        "#;
+
+        assert_eq!(both(source), r#"[5, -1, 2, "first again", ["try", "finally", "try", "DivisionByZero", "finally", "inner", "cleanup"]]"#);
+        assert!(both("try { let a = 1; } finally { throw \"done\"; }").ends_with("Error: done"));
+        assert!(both("try { throw \"first\"; } finally { throw \"second\"; }").ends_with("Error: second"));
+        assert!(both("let log = []; try { process.exit(3); } catch (e) { log.push(1); } finally { print log; }").ends_with("Exit: Exited with status 3"));
+    }
+
+    #[test]
+    fn test_tracebacks_match() {
+        let source = "fn inner(x) {\n  return x / 0;\n}\nfn outer() {\n  return [1].map(inner);\n}\nouter();";
+
+        assert_eq!(both(source), "Traceback (most recent call last):
+  File \"test.pltps\", line 7, in <script>
+  File \"test.pltps\", line 5, in outer
+  File \"<native>\", line 5, in list.map
+  File \"test.pltps\", line 2, in inner
+DivisionByZero: Division by zero");
+    }
+
+    #[test]
+    fn test_imports() {
+        let directory = TestDir::new("vm-imports");
+        fs::write(directory.join("util.pltps"), "export fn double(x) { return x * 2; }\nexport let name = \"util\";").unwrap();
+
+        let main = directory.join("main.pltps");
+        let source = "import \"./util\" as util;\nimport { double, name } from \"./util\";\n[util.double(2), double(3), name];";
+
+        for backend in [Backend::Tree, Backend::Vm] {
+            let statements = Parser::new(Scanner::new(source).scan_tokens().unwrap()).parse_statements().unwrap();
+            let mut interpreter = Interpreter::with_file(main.to_str().unwrap());
+            interpreter.set_backend(backend);
+
+            assert_eq!(interpreter.interpret(&statements).unwrap().to_string(), r#"[4, 6, "util"]"#);
+        }
+    }
+
+    #[test]
+    fn test_closures_over_the_same_loop_variable() {
+        // `map` is the loop: each iteration's `i` is captured by two closures, and every
+        // iteration also captures the list they all share.
+        let source = r#"
+            fn make_closures() {
+                let seen = [];
+                fn iteration(i) {
+                    fn record() {
+                        seen.push(i);
+                        return seen.len();
+                    }
+                    fn scaled() { return i * 10; }
+                    return [record, scaled];
+                }
+                return [1, 2, 3].map(iteration);
+            }
+            fn call_both(pair) {
+                let [record, scaled] = pair;
+                return [record(), scaled()];
+            }
+            let closures = make_closures();
+            let first = closures.map(call_both);
+            let second = closures.reverse().map(call_both);
+            [first, second];
+        "#;
+
+        assert_eq!(both(source), "[[[1, 10], [2, 20], [3, 30]], [[4, 30], [5, 20], [6, 10]]]");
+    }
+
+    #[test]
+    fn test_counters_share_captured_state() {
+        let source = r#"
+            fn counter(start) {
+                let count = [start];
+                fn increment() {
+                    count.push(count.pop() + 1);
+                    return current();
+                }
+                fn current() {
+                    let [value] = count;
+                    return value;
+                }
+                return {increment: increment, current: current};
+            }
+            let a = counter(0);
+            let b = counter(10);
+            [a.increment(), a.increment(), b.increment(), a.current(), b.current()];
+        "#;
+
+        assert_eq!(both(source), "[1, 2, 11, 2, 11]");
+    }
+
+    #[test]
+    fn test_collector_frees_function_cycles() {
+        // Each call leaves a function behind that refers to itself: through the scope it was
+        // defined in on the tree-walker, through its upvalue on the VM.
+        let source = r#"
+            fn make() {
+                fn recurse(n) { return match n { 0 => 0, _ => recurse(n - 1) }; }
+                return recurse(3);
+            }
+            let kept = [];
+            kept.push(kept);
+            gc.collect();
+            let before = gc.stats();
+            [make(), make(), make()];
+            [gc.collect(), gc.collect(), gc.stats().collections - before.collections];
+        "#;
+
+        assert_eq!(both(source), "[3, 0, 2]");
+    }
+
+    #[test]
+    fn test_captured_variables_are_shared_by_reference() {
+        let source = r#"
+            fn outer() {
+                fn read() { return x; }
+                fn even(n) { return match n { 0 => true, _ => odd(n - 1) }; }
+                fn odd(n) { return match n { 0 => false, _ => even(n - 1) }; }
+                let x = 1;
+                let before = read();
+                let x = 2;
+                return [before, read(), even(10), odd(7)];
+            }
+            fn nested(a) {
+                fn middle(b) {
+                    fn inner(c) { return [a, b, c]; }
+                    return inner;
+                }
+                return middle;
+            }
+            [outer(), nested(1)(2)(3)];
+        "#;
+
+        assert_eq!(both(source), "[[1, 2, true, true], [1, 2, 3]]");
+    }
+
+    #[test]
+    fn test_tail_calls_run_in_constant_depth() {
+        let source = r#"
+            fn count(n, total) {
+                return match n { 0 => total, _ => count(n - 1, total + 1) };
+            }
+            fn even(n) { return match n { 0 => true, _ => (odd(n - 1)) }; }
+            fn odd(n) { return match n { 0 => false, _ => even(n - 1) }; }
+            fn last(xs) {
+                return match xs { [x] => x, [_, ...rest] => last(rest) };
+            }
+            fn tail_native(n) { return match n { 0 => [1, 2].len(), _ => tail_native(n - 1) }; }
+            [count(5000, 0), even(3001), last([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]), tail_native(50)];
+        "#;
+
+        for backend in [Backend::Tree, Backend::Vm] {
+            assert_eq!(run_with_depth(source, backend, 10), "[5000, false, 12, 2]");
+        }
+    }
+
+    #[test]
+    fn test_stack_overflow_is_catchable() {
+        let source = r#"
+            fn down(n) { return match n { 0 => 0, _ => 1 + down(n - 1) }; }
+            fn guarded(n) {
+                try { return guarded(n + 1); } catch (e) { return [e.kind, n]; }
+            }
+            fn attempt() {
+                try { return down(100); } catch (e) { return e.kind + ": " + e.message; }
+            }
+            [attempt(), down(10), guarded(0)];
+        "#;
+
+        for backend in [Backend::Tree, Backend::Vm] {
+            assert_eq!(
+                run_with_depth(source, backend, 20),
+                r#"["StackOverflow: Maximum call depth of 20 exceeded", 10, ["StackOverflow", 19]]"#,
+            );
+        }
+
+        let uncaught = "fn down(n) {\n  return 1 + down(n - 1);\n}\ndown(1);";
+
+        for backend in [Backend::Tree, Backend::Vm] {
+            assert_eq!(run_with_depth(uncaught, backend, 20), "Traceback (most recent call last):
+  File \"test.pltps\", line 4, in <script>
+  File \"test.pltps\", line 2, in down
+  File \"test.pltps\", line 2, in down
+  File \"test.pltps\", line 2, in down
+  [Previous line repeated 17 more times]
+StackOverflow: Maximum call depth of 20 exceeded");
+        }
+    }
+
+    #[test]
+    fn test_default_depth_fits_a_default_thread() {
+        let source = format!(r#"
+            fn down(n) {{ return match n {{ 0 => 0, _ => 1 + down(n - 1) }}; }}
+            fn attempt(n) {{
+                try {{ return down(n); }} catch (e) {{ return e.kind; }}
+            }}
+            [down({}), attempt({})];
+        "#, DEFAULT_MAX_DEPTH - 10, DEFAULT_MAX_DEPTH + 10);
+
+        // Test threads are the default size, like an embedder's would be.
+        std::thread::spawn(move || {
+            for backend in [Backend::Tree, Backend::Vm] {
+                assert_eq!(run(&source, backend), format!(r#"[{}, "StackOverflow"]"#, DEFAULT_MAX_DEPTH - 10));
+            }
+        }).join().unwrap();
+    }
+}